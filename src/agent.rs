@@ -0,0 +1,228 @@
+//! In-memory password agent: holds the password for a TTL and serves it over a user-only socket
+
+#[cfg(unix)]
+use std::env;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+#[cfg(unix)]
+use console::style;
+#[cfg(unix)]
+use secrecy::ExposeSecret;
+use secrecy::SecretString;
+
+#[cfg(unix)]
+use crate::{cancel, interactive};
+
+/// Default time the agent holds the password (15 minutes)
+pub const DEFAULT_TTL_SECS: u64 = 15 * 60;
+
+/// Socket file name inside the user-only runtime directory
+#[cfg(unix)]
+const SOCKET_NAME: &str = "wc-envc-agent.sock";
+
+/// Password held by the agent until its TTL runs out
+#[cfg_attr(not(unix), allow(dead_code))]
+struct CachedPassword {
+    password: Option<SecretString>,
+    expires: Instant,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl CachedPassword {
+    fn new(password: SecretString, ttl: Duration, now: Instant) -> Self {
+        Self { password: Some(password), expires: now + ttl }
+    }
+    
+    /// The password, or None once expired; the secret is dropped (and zeroized) on expiry
+    fn get(&mut self, now: Instant) -> Option<&SecretString> {
+        if now >= self.expires {
+            self.password = None;
+        }
+        self.password.as_ref()
+    }
+}
+
+/// User-only directory for the socket: $XDG_RUNTIME_DIR, else a 700 dir under the temp dir
+#[cfg(unix)]
+fn socket_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let user = env::var("USER").unwrap_or_else(|_| "user".to_string());
+            env::temp_dir().join(format!("wc-envc-{}", user))
+        }
+    }
+}
+
+/// Refuse a socket directory another user could have planted: it must be a real directory
+/// (not a symlink) owned by the current user, with mode 700
+#[cfg(unix)]
+fn check_socket_dir(dir: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(dir)?;
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid {
+        anyhow::bail!("{} is not a directory owned by the current user; not using the agent socket", dir.display());
+    }
+    if metadata.mode() & 0o777 != 0o700 {
+        anyhow::bail!(
+            "{} has mode {:o} instead of 700; not using the agent socket",
+            dir.display(),
+            metadata.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+/// Ask a running agent for the password
+#[cfg(unix)]
+pub fn fetch_password() -> Option<SecretString> {
+    fetch_password_from(&socket_dir())
+}
+
+/// Ask the agent listening in `dir` for the password
+/// A socket directory that fails `check_socket_dir` is never connected to
+#[cfg(unix)]
+pub fn fetch_password_from(dir: &Path) -> Option<SecretString> {
+    if !dir.exists() {
+        return None;
+    }
+    if let Err(e) = check_socket_dir(dir) {
+        eprintln!("{} {}", style("⚠️").yellow().for_stderr(), e);
+        return None;
+    }
+    
+    let mut stream = UnixStream::connect(dir.join(SOCKET_NAME)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok()?;
+    
+    let mut password = String::new();
+    stream.read_to_string(&mut password).ok()?;
+    let password = SecretString::new(password);
+    (!password.expose_secret().is_empty()).then_some(password)
+}
+
+/// Without Unix domain sockets there is never an agent to ask
+#[cfg(not(unix))]
+pub fn fetch_password() -> Option<SecretString> {
+    None
+}
+
+/// Handle agent command
+/// Prompts for the password once and serves it until the TTL expires or Ctrl-C
+#[cfg(unix)]
+pub fn handle_agent(ttl_secs: u64) -> Result<()> {
+    let dir = socket_dir();
+    let path = dir.join(SOCKET_NAME);
+    if fetch_password().is_some() {
+        anyhow::bail!("An agent is already running at {}", path.display());
+    }
+    
+    let password = interactive::get_password_from_env_or_prompt(true)?;
+    
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    check_socket_dir(&dir)?;
+    // A stale socket is left behind if the agent was killed
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    
+    println!("{} Agent holding the password for {}s at {}",
+        style("🔐").cyan(),
+        ttl_secs,
+        style(path.display()).cyan()
+    );
+    println!("   Press Ctrl-C to stop");
+    
+    let mut cached = CachedPassword::new(password, Duration::from_secs(ttl_secs), Instant::now());
+    
+    // Defer Ctrl-C so the socket is always removed on the way out
+    let _guard = cancel::WriteGuard::new();
+    let served = serve(&listener, &mut cached);
+    let _ = fs::remove_file(&path);
+    served?;
+    
+    if cancel::is_cancelled() {
+        return Err(cancel::Cancelled::Interrupted.into());
+    }
+    
+    println!("{} Agent expired, password forgotten", style("⏱️").dim());
+    Ok(())
+}
+
+/// The agent needs Unix domain sockets
+#[cfg(not(unix))]
+pub fn handle_agent(_ttl_secs: u64) -> Result<()> {
+    anyhow::bail!("The password agent is only available on Unix-like systems")
+}
+
+/// Answer connections with the password until it expires or Ctrl-C is pressed
+#[cfg(unix)]
+fn serve(listener: &UnixListener, cached: &mut CachedPassword) -> Result<()> {
+    while !cancel::is_cancelled() {
+        let Some(password) = cached.get(Instant::now()) else {
+            return Ok(());
+        };
+        
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                let _ = stream.write_all(password.expose_secret().as_bytes());
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+    
+    #[test]
+    fn test_password_expires_after_ttl() {
+        let start = Instant::now();
+        let password = SecretString::new("secret".to_string());
+        let mut cached = CachedPassword::new(password, Duration::from_secs(60), start);
+        
+        let held = cached.get(start + Duration::from_secs(59)).unwrap();
+        assert_eq!(held.expose_secret(), "secret");
+        
+        assert!(cached.get(start + Duration::from_secs(60)).is_none());
+        // Once forgotten, the password does not come back
+        assert!(cached.get(start).is_none());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_dir_must_be_private() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("agent");
+        fs::DirBuilder::new().mode(0o700).create(&dir).unwrap();
+        assert!(check_socket_dir(&dir).is_ok());
+        
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(check_socket_dir(&dir).unwrap_err().to_string().contains("mode 755"));
+        
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(check_socket_dir(&link).unwrap_err().to_string().contains("not a directory owned"));
+    }
+}
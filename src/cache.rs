@@ -0,0 +1,105 @@
+//! Per-directory cache used to skip re-encrypting unchanged files
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+
+use crate::interactive::RunOptions;
+use crate::keypolicy;
+
+/// Cache file name, stored next to the source files
+pub const CACHE_FILE: &str = ".wc-envc-cache";
+
+/// Domain separation for cache entries
+const CACHE_CONTEXT: &[u8] = b"wc-envc cache v1\0";
+
+/// Cache location for a source file
+fn cache_path(source: &Path) -> PathBuf {
+    source.parent().unwrap_or(Path::new("")).join(CACHE_FILE)
+}
+
+/// Cache key for a source file (its file name)
+fn cache_key(source: &Path) -> String {
+    source.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Load cache entries (file name -> entry); missing or bad lines are ignored
+fn load(path: &Path) -> BTreeMap<String, String> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| line.split_once("  "))
+        .map(|(hash, name)| (name.to_string(), hash.to_string()))
+        .collect()
+}
+
+/// Cache entry for `source` as it is on disk now: HMAC-SHA256 keyed by the password over the
+/// raw bytes, the output name and every option that shapes the ciphertext (key policy included)
+/// Keyed, so the cache is no guessing oracle for the plaintext; None if the source can't be read
+pub fn entry(source: &Path, output: &Path, password: &SecretString, opts: &RunOptions) -> Option<String> {
+    let raw = fs::read(source).ok()?;
+    let process = keypolicy::options_for(source, &opts.process).ok()?;
+    let options = format!("{:?}|{}|{}|{}", process, output.display(), opts.armor, opts.store_name);
+    
+    let mut mac = Hmac::<Sha256>::new_from_slice(password.expose_secret().as_bytes()).ok()?;
+    mac.update(CACHE_CONTEXT);
+    for part in [&raw[..], options.as_bytes()] {
+        mac.update(&(part.len() as u64).to_be_bytes());
+        mac.update(part);
+    }
+    Some(mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Check if a source's current `entry` matches the cached one and its encrypted output still exists
+pub fn is_unchanged(source: &Path, output: &Path, entry: &str) -> bool {
+    output.exists() && load(&cache_path(source)).get(&cache_key(source)).map(String::as_str) == Some(entry)
+}
+
+/// Record the entry of a freshly encrypted source
+pub fn record(source: &Path, entry: &str) -> Result<()> {
+    let path = cache_path(source);
+    let mut entries = load(&path);
+    entries.insert(cache_key(source), entry.to_string());
+    
+    let lines: String = entries
+        .iter()
+        .map(|(name, hash)| format!("{}  {}\n", hash, name))
+        .collect();
+    fs::write(path, lines)?;
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_entry_covers_password_options_and_raw_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join(".env.json");
+        let output = dir.path().join(".env.json.enc");
+        fs::write(&source, "{\"DB_HOST\": \"localhost\"}").unwrap();
+        let password = SecretString::new("secret".to_string());
+        let opts = RunOptions::default();
+        
+        let first = entry(&source, &output, &password, &opts).unwrap();
+        assert_eq!(entry(&source, &output, &password, &opts).unwrap(), first);
+        assert_ne!(entry(&source, &output, &SecretString::new("other".to_string()), &opts).unwrap(), first);
+        assert_ne!(entry(&source, &output, &password, &RunOptions { armor: true, ..Default::default() }).unwrap(), first);
+        
+        // The entry never contains a plain hash of the file
+        assert_ne!(first, crate::engine::sha256_hex(&fs::read(&source).unwrap()));
+        
+        fs::write(&output, "").unwrap();
+        record(&source, &first).unwrap();
+        assert!(is_unchanged(&source, &output, &first));
+        fs::write(&source, "{\"DB_HOST\": \"db\"}").unwrap();
+        assert!(!is_unchanged(&source, &output, &entry(&source, &output, &password, &opts).unwrap()));
+    }
+}
@@ -0,0 +1,76 @@
+//! Ctrl-C handling that never leaves half-written output files
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use console::{style, Term};
+
+/// Exit code for an interrupted run (128 + SIGINT)
+pub const EXIT_CODE: i32 = 130;
+
+/// Set by the Ctrl-C handler while files are being written
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// True while files are being written, so Ctrl-C waits for the current file
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// Deliberate abort: reported neutrally with exit code 130 instead of as a failure
+#[derive(Debug)]
+pub enum Cancelled {
+    /// Ctrl-C stopped a batch before the next file
+    Interrupted,
+    /// The user declined a confirmation prompt or chose Quit
+    ByUser,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cancelled::Interrupted => write!(f, "Cancelled: stopped before the next file"),
+            Cancelled::ByUser => write!(f, "Operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Install the Ctrl-C handler
+/// Outside of writes it exits right away; during writes it lets the current file finish
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if BUSY.load(Ordering::SeqCst) {
+            CANCELLED.store(true, Ordering::SeqCst);
+            return;
+        }
+        
+        // Prompts may have hidden the cursor
+        let _ = Term::stderr().show_cursor();
+        eprintln!();
+        eprintln!("{}", style("Cancelled").dim().for_stderr());
+        std::process::exit(EXIT_CODE);
+    })?;
+    
+    Ok(())
+}
+
+/// Whether Ctrl-C was pressed during a write section
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Marks a write section; Ctrl-C is deferred until the guard is dropped
+pub struct WriteGuard;
+
+impl WriteGuard {
+    pub fn new() -> Self {
+        BUSY.store(true, Ordering::SeqCst);
+        WriteGuard
+    }
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        BUSY.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,61 @@
+//! Timestamp formatting for everything that emits times: UTC by default, local time on request
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+
+/// Clock used when rendering timestamps
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TimeZone {
+    /// Coordinated Universal Time, identical on every machine
+    #[default]
+    Utc,
+    /// The machine's local time zone (`--local-time`)
+    Local,
+}
+
+/// A point in time rendered in a fixed zone
+pub struct Timestamp(DateTime<FixedOffset>);
+
+impl Timestamp {
+    pub fn new(time: SystemTime, zone: TimeZone) -> Self {
+        let utc = DateTime::<Utc>::from(time);
+        Self(match zone {
+            TimeZone::Utc => utc.fixed_offset(),
+            TimeZone::Local => utc.with_timezone(&Local).fixed_offset(),
+        })
+    }
+    
+    /// RFC 3339 full date, e.g. `2024-03-05`
+    pub fn date(&self) -> String {
+        self.0.format("%Y-%m-%d").to_string()
+    }
+    
+    /// RFC 3339 date and time with offset, e.g. `2024-03-05T14:07:09Z`
+    pub fn rfc3339(&self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+    
+    /// Compact file-name-safe date and time, e.g. `20240305-140709`
+    pub fn compact(&self) -> String {
+        self.0.format("%Y%m%d-%H%M%S").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+    
+    #[test]
+    fn test_utc_formatting() {
+        // 2024-03-05 23:59:59 UTC is already the 6th east of UTC
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_683_199);
+        let stamp = Timestamp::new(time, TimeZone::Utc);
+        
+        assert_eq!(stamp.date(), "2024-03-05");
+        assert_eq!(stamp.compact(), "20240305-235959");
+        assert_eq!(stamp.rfc3339(), "2024-03-05T23:59:59Z");
+        assert_eq!(Timestamp::new(UNIX_EPOCH, TimeZone::default()).compact(), "19700101-000000");
+    }
+}
@@ -0,0 +1,88 @@
+//! Dynamic shell completion: FILE arguments suggest only the .env files the command works on
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use clap::CommandFactory;
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate, PathCompleter, ValueCompleter};
+use clap_complete::CompleteEnv;
+
+use crate::engine::ProcessMode;
+use crate::scanner;
+
+/// Environment variable that switches the binary into completion mode (e.g. `WC_ENVC_COMPLETE=bash`)
+pub const COMPLETE_ENV_VAR: &str = "WC_ENVC_COMPLETE";
+
+/// Answer a completion request from the shell and exit; a no-op on regular runs
+pub fn complete<C: CommandFactory>() {
+    CompleteEnv::with_factory(C::command).var(COMPLETE_ENV_VAR).complete();
+}
+
+/// Completer for FILE arguments: `.env*` to encrypt, `.env*.enc` to decrypt, plus directories to descend into
+pub fn env_files(mode: ProcessMode) -> ArgValueCompleter {
+    ArgValueCompleter::new(EnvFileCompleter(mode))
+}
+
+/// Path completion narrowed to the env files for one mode
+struct EnvFileCompleter(ProcessMode);
+
+impl ValueCompleter for EnvFileCompleter {
+    fn complete(&self, current: &OsStr) -> Vec<CompletionCandidate> {
+        let mode = self.0;
+        let wanted = move |path: &Path| path.is_file() && is_env_file(path, mode);
+        
+        // Every .env file is a dotfile, which clap would otherwise hide until a `.` is typed
+        PathCompleter::any()
+            .filter(wanted)
+            .complete(current)
+            .into_iter()
+            .map(|candidate| {
+                let hidden = candidate.is_hide_set() && !is_env_file(Path::new(candidate.get_value()), mode);
+                candidate.hide(hidden)
+            })
+            .collect()
+    }
+}
+
+/// Whether the file name of `path` matches the env file pattern for `mode`
+fn is_env_file(path: &Path, mode: ProcessMode) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| scanner::is_env_file_for(name, mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::fs;
+    
+    use super::*;
+    use crate::Cli;
+    
+    /// Candidates the shell would get for `wc-envc <subcommand> <dir>/`
+    fn candidates(subcommand: &str, dir: &Path) -> Vec<String> {
+        let prefix = format!("{}/", dir.display());
+        let args: Vec<OsString> = ["wc-envc", subcommand, &prefix].iter().map(OsString::from).collect();
+        clap_complete::engine::complete(&mut Cli::command(), args, 2, Some(dir))
+            .unwrap()
+            .iter()
+            .filter(|candidate| !candidate.is_hide_set())
+            .map(|candidate| {
+                let value = Path::new(candidate.get_value());
+                value.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_file_completion_uses_env_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [".env", ".env.production", ".env.enc", ".env.production.enc", ".env.example", "README.md"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::create_dir(dir.path().join("config")).unwrap();
+        
+        assert_eq!(candidates("encrypt", dir.path()), [".env", ".env.production", "config"]);
+        assert_eq!(candidates("decrypt", dir.path()), [".env.enc", ".env.production.enc", "config"]);
+    }
+}
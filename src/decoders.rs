@@ -0,0 +1,189 @@
+//! Decoders for encrypted env files written by other tools (see `engine::Decoder`)
+
+#[cfg(feature = "dotenv-vault")]
+use aes_gcm::aead::{Aead, KeyInit};
+#[cfg(feature = "dotenv-vault")]
+use aes_gcm::{Aes256Gcm, Nonce};
+#[cfg(feature = "dotenv-vault")]
+use base64::Engine;
+#[cfg(feature = "dotenv-vault")]
+use secrecy::ExposeSecret;
+use secrecy::SecretString;
+
+#[cfg(feature = "dotenv-vault")]
+use crate::engine::{self, DecryptError};
+use crate::engine::{Decoder, EngineError, EngineResult};
+
+/// Prefix of the per-environment ciphertext lines in a `.env.vault` file
+const VAULT_KEY_PREFIX: &str = "DOTENV_VAULT_";
+
+/// Bytes of AES-GCM nonce in front of each dotenv-vault ciphertext
+#[cfg(feature = "dotenv-vault")]
+const VAULT_NONCE_LEN: usize = 12;
+
+/// dotenv-vault `.env.vault` files: one AES-256-GCM ciphertext per environment, unlocked by a DOTENV_KEY
+/// The password is the DOTENV_KEY (`dotenv://:key_<hex>@dotenv.org/vault/.env.vault?environment=production`,
+/// comma-separated keys are tried in order) or a bare `key_<hex>` for the development environment
+/// Recognized in every build so a build without the `dotenv-vault` feature can say how to read the file
+pub struct DotenvVault;
+
+impl Decoder for DotenvVault {
+    fn name(&self) -> &'static str {
+        "dotenv-vault"
+    }
+    
+    /// The `#/...env.vault...` banner, or nothing but `DOTENV_VAULT_*` keys; a .env that merely
+    /// has one such key among others is not a vault
+    fn can_decode(&self, header: &str) -> bool {
+        if header.lines().any(|line| line.starts_with("#/") && line.contains(".env.vault")) {
+            return true;
+        }
+        
+        let keys: Vec<&str> = header
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split_once('=').map_or(line, |(key, _)| key).trim())
+            .collect();
+        !keys.is_empty() && keys.iter().all(|key| key.starts_with(VAULT_KEY_PREFIX))
+    }
+    
+    #[cfg(not(feature = "dotenv-vault"))]
+    fn decrypt(&self, _content: &str, _password: &SecretString) -> EngineResult<String> {
+        Err(EngineError::Invalid(
+            "This is a dotenv-vault .env.vault file; rebuild wc-envc with `--features dotenv-vault` to decrypt it".to_string(),
+        ))
+    }
+    
+    #[cfg(feature = "dotenv-vault")]
+    fn decrypt(&self, content: &str, password: &SecretString) -> EngineResult<String> {
+        let mut last_error = None;
+        for dotenv_key in password.expose_secret().split(',') {
+            let (key, environment) = parse_dotenv_key(dotenv_key.trim())?;
+            let ciphertext = vault_entry(content, &environment).ok_or_else(|| {
+                EngineError::Invalid(format!("No {}{} in the file", VAULT_KEY_PREFIX, environment.to_uppercase()))
+            })?;
+            match decrypt_entry(ciphertext, &key) {
+                Ok(plain) => return Ok(plain),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| EngineError::Invalid("Empty DOTENV_KEY".to_string())))
+    }
+}
+
+/// (32-byte key, environment) from a DOTENV_KEY URI or a bare `key_<hex>`
+#[cfg(feature = "dotenv-vault")]
+fn parse_dotenv_key(dotenv_key: &str) -> EngineResult<([u8; 32], String)> {
+    let invalid = || EngineError::Invalid("Expected a DOTENV_KEY (dotenv://:key_...@.../vault/.env.vault?environment=...)".to_string());
+    
+    let (hex, environment) = match dotenv_key.strip_prefix("dotenv://:") {
+        Some(rest) => {
+            let (hex, location) = rest.split_once('@').ok_or_else(invalid)?;
+            let environment = location
+                .split_once("environment=")
+                .map(|(_, env)| env.split('&').next().unwrap_or(env))
+                .filter(|env| !env.is_empty())
+                .ok_or_else(invalid)?;
+            (hex, environment.to_string())
+        }
+        None => (dotenv_key, "development".to_string()),
+    };
+    
+    let hex = hex.strip_prefix("key_").unwrap_or(hex);
+    let bytes = decode_hex(hex).filter(|bytes| bytes.len() == 32).ok_or_else(invalid)?;
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Ok((key, environment))
+}
+
+/// Ciphertext of `environment` (the `DOTENV_VAULT_<ENVIRONMENT>` value, unquoted)
+#[cfg(feature = "dotenv-vault")]
+fn vault_entry<'a>(content: &'a str, environment: &str) -> Option<&'a str> {
+    let name = format!("{}{}", VAULT_KEY_PREFIX, environment.to_uppercase());
+    engine::lines(content)
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == name)
+        .map(|(_, value)| value.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+/// Decrypt Base64 `nonce || ciphertext || tag` with AES-256-GCM
+#[cfg(feature = "dotenv-vault")]
+fn decrypt_entry(ciphertext: &str, key: &[u8; 32]) -> EngineResult<String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(ciphertext)
+        .map_err(|_| EngineError::Corrupt("dotenv-vault ciphertext is not Base64".to_string()))?;
+    if bytes.len() <= VAULT_NONCE_LEN {
+        return Err(EngineError::Corrupt("dotenv-vault ciphertext is too short".to_string()));
+    }
+    
+    let (nonce, sealed) = bytes.split_at(VAULT_NONCE_LEN);
+    let plain = Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| DecryptError::AuthError)?;
+    String::from_utf8(plain).map_err(|_| EngineError::Corrupt("dotenv-vault plaintext is not UTF-8".to_string()))
+}
+
+/// Decode lowercase or uppercase hex; None on odd length or a non-hex digit
+#[cfg(feature = "dotenv-vault")]
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "dotenv-vault")]
+    use crate::engine::{ProcessMode, ProcessOptions};
+    
+    /// `.env.vault` fixture and keys from the dotenv test suite
+    const FIXTURE: &str = "#/-------------------.env.vault---------------------/\n\
+        #/         cloud-agnostic vaulting standard         /\n\
+        #/   [how it works](https://dotenv.org/env-vault)   /\n\
+        #/--------------------------------------------------/\n\
+        \n\
+        # development\n\
+        DOTENV_VAULT_DEVELOPMENT=\"s7NYXa809k/bVSPwIAmJhPJmEGTtU0hG58hOZy7I0ix6y5HP8LsHBsZCYC/gw5DDFy5DgOcyd18R\"\n";
+    const DEVELOPMENT_KEY: &str =
+        "dotenv://:key_ddcaa26504cd70a6fef9801901c3981538563a1767c297cb8416e8a38c62fe00@dotenv.local/vault/.env.vault?environment=development";
+    
+    #[test]
+    fn test_dotenv_vault_detection() {
+        assert!(DotenvVault.can_decode(FIXTURE));
+        assert!(DotenvVault.can_decode("# production\nDOTENV_VAULT_PRODUCTION=\"abc\"\nDOTENV_VAULT_CI=\"def\"\n"));
+        assert!(!DotenvVault.can_decode("DOTENV_VAULT_PRODUCTION=abc\nDB_HOST=bG9jYWxob3N0\n"));
+        assert!(!DotenvVault.can_decode("# only comments\n"));
+        
+        #[cfg(not(feature = "dotenv-vault"))]
+        assert!(DotenvVault
+            .decrypt(FIXTURE, &SecretString::new(DEVELOPMENT_KEY.to_string()))
+            .unwrap_err()
+            .to_string()
+            .contains("rebuild wc-envc with `--features dotenv-vault`"));
+    }
+    
+    #[cfg(feature = "dotenv-vault")]
+    #[test]
+    fn test_dotenv_vault_fixture() {
+        let password = SecretString::new(DEVELOPMENT_KEY.to_string());
+        let opts = ProcessOptions::default();
+        assert!(engine::validate_encrypted_file(FIXTURE, &opts).is_ok());
+        
+        let (decrypted, keys) = engine::process_file(FIXTURE, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, "# development@v6\nALPHA=\"zeta\"");
+        assert_eq!(keys, ["ALPHA"]);
+        
+        let production = SecretString::new(DEVELOPMENT_KEY.replace("development", "production"));
+        let err = engine::process_file(FIXTURE, &production, ProcessMode::Decrypt, &opts).unwrap_err();
+        assert_eq!(err.to_string(), "No DOTENV_VAULT_PRODUCTION in the file");
+        
+        let wrong = SecretString::new(DEVELOPMENT_KEY.replace("key_dd", "key_ee"));
+        assert!(matches!(
+            engine::process_file(FIXTURE, &wrong, ProcessMode::Decrypt, &opts),
+            Err(EngineError::WrongPassword(_))
+        ));
+    }
+}
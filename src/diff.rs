@@ -0,0 +1,301 @@
+//! Compare the decrypted contents of two encrypted .env files, and preview overwrites (`--diff`)
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use console::style;
+use secrecy::SecretString;
+use similar::{ChangeTag, TextDiff};
+
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::interactive::{self, RunOptions};
+use crate::setenv;
+
+/// A single key-level difference between two env files
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added(String),
+    Removed(String),
+    Changed { old: String, new: String },
+}
+
+/// Compare two parsed variable lists by key, sorted by key name
+fn diff_vars(old: Vec<(String, String)>, new: Vec<(String, String)>) -> Vec<(String, Change)> {
+    let old: BTreeMap<String, String> = old.into_iter().collect();
+    let mut new: BTreeMap<String, String> = new.into_iter().collect();
+    let mut changes = Vec::new();
+    
+    for (key, old_value) in old {
+        match new.remove(&key) {
+            Some(new_value) if new_value != old_value => {
+                changes.push((key, Change::Changed { old: old_value, new: new_value }));
+            }
+            Some(_) => {}
+            None => changes.push((key, Change::Removed(old_value))),
+        }
+    }
+    
+    changes.extend(new.into_iter().map(|(key, value)| (key, Change::Added(value))));
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    changes
+}
+
+/// Read, validate and decrypt an encrypted file into (key, value) pairs
+fn decrypt_vars(
+    path: &Path,
+    password: &SecretString,
+    opts: &RunOptions,
+) -> Result<Vec<(String, String)>> {
+    let content = interactive::read_input(path, ProcessMode::Decrypt, opts)?;
+    engine::validate_encrypted_file(&content, &opts.process)?;
+    
+    let (decrypted, _) = engine::process_file(&content, password, ProcessMode::Decrypt, &opts.process)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    
+    Ok(setenv::parse_env_file(&decrypted, &opts.process))
+}
+
+/// Handle diff command
+/// Returns true when the files differ
+pub fn handle_diff(
+    old: &Path,
+    new: &Path,
+    password: Option<String>,
+    show_values: bool,
+    opts: &RunOptions,
+) -> Result<bool> {
+    for path in [old, new] {
+        if !path.exists() {
+            anyhow::bail!("File not found: {}", path.display());
+        }
+    }
+    
+    // Get password from: arg > env > prompt
+    let password = match password {
+        Some(p) => interactive::password_from_arg(p, ProcessMode::Decrypt)?,
+        None => interactive::get_password_from_env_or_prompt(false)?,
+    };
+    
+    let changes = diff_vars(
+        decrypt_vars(old, &password, opts)?,
+        decrypt_vars(new, &password, opts)?,
+    );
+    
+    let display = |value: &str| {
+        if show_values {
+            value.to_string()
+        } else {
+            engine::mask_value(value)
+        }
+    };
+    
+    println!();
+    if changes.is_empty() {
+        println!("{} No differences", style("✅").green());
+        return Ok(false);
+    }
+    
+    for (key, change) in &changes {
+        match change {
+            Change::Added(value) => {
+                println!("  {} {} = {}", style("+").green(), style(key).green(), display(value));
+            }
+            Change::Removed(value) => {
+                println!("  {} {} = {}", style("-").red(), style(key).red(), display(value));
+            }
+            Change::Changed { old, new } => {
+                println!("  {} {}: {} → {}", 
+                    style("~").yellow(),
+                    style(key).yellow(),
+                    display(old),
+                    display(new)
+                );
+            }
+        }
+    }
+    
+    println!();
+    println!("{} {} difference(s) between {} and {}", 
+        style("⚠️").yellow(),
+        changes.len(),
+        style(old.display()).cyan(),
+        style(new.display()).cyan()
+    );
+    
+    Ok(true)
+}
+
+/// Mask the value of a KEY=VALUE line for display; other lines are shown as-is
+fn mask_line(line: &str, opts: &ProcessOptions) -> String {
+    match line.split_once('=') {
+        Some((key, value)) if !opts.is_ignored(line.trim()) => {
+            format!("{}={}", key, engine::mask_value(value.trim()))
+        }
+        _ => line.to_string(),
+    }
+}
+
+/// Unified line diff of two versions of a file with all values masked
+/// Lines are compared unmasked, so a changed value still shows up as a changed line
+pub fn masked_diff(old: &str, new: &str, opts: &ProcessOptions) -> String {
+    line_diff(old, new, true, opts)
+}
+
+/// Unified line diff of two versions of a file, optionally masking values
+fn line_diff(old: &str, new: &str, mask: bool, opts: &ProcessOptions) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut output = String::new();
+    
+    for (index, group) in diff.grouped_ops(1).iter().enumerate() {
+        if index > 0 {
+            output.push_str(&format!("{}\n", style("@@").cyan()));
+        }
+        for change in group.iter().flat_map(|op| diff.iter_changes(op)) {
+            let line = change.value().trim_end_matches(['\r', '\n']);
+            let line = if mask { mask_line(line, opts) } else { line.to_string() };
+            let rendered = match change.tag() {
+                ChangeTag::Delete => style(format!("-{}", line)).red().to_string(),
+                ChangeTag::Insert => style(format!("+{}", line)).green().to_string(),
+                ChangeTag::Equal => format!(" {}", line),
+            };
+            output.push_str(&rendered);
+            output.push('\n');
+        }
+    }
+    
+    output
+}
+
+/// Arguments git passes to `diff.<driver>.command`: path, old-file, old-hex, old-mode, new-file, new-hex, new-mode
+const GIT_DIFF_ARGS: usize = 7;
+
+/// Decrypted version of one side of a git diff; git passes /dev/null for a side that doesn't exist
+fn git_diff_side(path: &Path, password: &SecretString, opts: &RunOptions) -> Result<String> {
+    if path == Path::new("/dev/null") {
+        return Ok(String::new());
+    }
+    
+    let content = interactive::read_input(path, ProcessMode::Decrypt, opts)?;
+    if content.trim().is_empty() {
+        return Ok(String::new());
+    }
+    let (decrypted, _) = engine::process_file(&content, password, ProcessMode::Decrypt, &opts.process)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    Ok(decrypted)
+}
+
+/// Diff of the decrypted versions named by `args`: OLD NEW, or the seven arguments of a git diff driver
+fn git_diff(args: &[PathBuf], password: &SecretString, show_values: bool, opts: &RunOptions) -> Result<String> {
+    let (name, old, new) = match args {
+        [old, new] => (new, old, new),
+        [name, old, _, _, new, _, _] => (name, old, new),
+        _ => anyhow::bail!(
+            "Expected OLD NEW, or the {} arguments git passes to a diff driver; got {}",
+            GIT_DIFF_ARGS,
+            args.len()
+        ),
+    };
+    
+    let old = git_diff_side(old, password, opts)?;
+    let new = git_diff_side(new, password, opts)?;
+    let mut output = style(format!("diff --git a/{0} b/{0} (decrypted)", name.display())).bold().to_string();
+    output.push('\n');
+    if old == new {
+        output.push_str("No changes to the decrypted contents\n");
+    } else {
+        output.push_str(&line_diff(&old, &new, !show_values, &opts.process));
+    }
+    Ok(output)
+}
+
+/// Handle git-diff command
+/// An external diff driver for encrypted files; the password comes from the environment since git
+/// gives the driver no way to ask. git treats a non-zero exit as a broken driver, so differences don't fail
+pub fn handle_git_diff(args: &[PathBuf], show_values: bool, opts: &RunOptions) -> Result<()> {
+    let password = interactive::require_env_password("git-diff", ProcessMode::Decrypt)?;
+    
+    print!("{}", git_diff(args, &password, show_values, opts)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn write_encrypted(path: &Path, content: &str, password: &SecretString) {
+        let opts = engine::ProcessOptions::default();
+        let (encrypted, _) = engine::process_file(content, password, ProcessMode::Encrypt, &opts).unwrap();
+        std::fs::write(path, encrypted).unwrap();
+    }
+    
+    #[test]
+    fn test_diff_one_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("secret".to_string());
+        let old = dir.path().join("old.env.enc");
+        let new = dir.path().join("new.env.enc");
+        write_encrypted(&old, "DB_HOST=localhost\nDB_PASS=one\n", &password);
+        write_encrypted(&new, "DB_HOST=localhost\nDB_PASS=two\n", &password);
+        
+        let opts = RunOptions::default();
+        let changes = diff_vars(
+            decrypt_vars(&old, &password, &opts).unwrap(),
+            decrypt_vars(&new, &password, &opts).unwrap(),
+        );
+        
+        assert_eq!(changes, vec![(
+            "DB_PASS".to_string(),
+            Change::Changed { old: "one".to_string(), new: "two".to_string() },
+        )]);
+    }
+    
+    #[test]
+    fn test_diff_added_and_removed() {
+        let old = vec![("A".to_string(), "1".to_string())];
+        let new = vec![("B".to_string(), "2".to_string())];
+        
+        assert_eq!(diff_vars(old, new), vec![
+            ("A".to_string(), Change::Removed("1".to_string())),
+            ("B".to_string(), Change::Added("2".to_string())),
+        ]);
+    }
+    
+    #[test]
+    fn test_git_diff_driver_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("secret".to_string());
+        let old = dir.path().join("old");
+        let new = dir.path().join("new");
+        write_encrypted(&old, "DB_HOST=localhost\nDB_PASS=one\n", &password);
+        write_encrypted(&new, "DB_HOST=localhost\nDB_PASS=two\n", &password);
+        
+        let opts = RunOptions::default();
+        let args: Vec<PathBuf> = [".env.enc", "old", "abc123", "100644", "new", "def456", "100644"]
+            .iter()
+            .map(|arg| if arg.starts_with('.') { PathBuf::from(arg) } else { dir.path().join(arg) })
+            .collect();
+        let diff = console::strip_ansi_codes(&git_diff(&args, &password, true, &opts).unwrap()).to_string();
+        assert!(diff.starts_with("diff --git a/.env.enc b/.env.enc (decrypted)\n"));
+        assert!(diff.contains(" DB_HOST=localhost\n-DB_PASS=one\n+DB_PASS=two\n"));
+        
+        // A newly added file is diffed against /dev/null; masked by default
+        let added = git_diff(&[PathBuf::from("/dev/null"), new.clone()], &password, false, &opts).unwrap();
+        assert!(console::strip_ansi_codes(&added).contains("+DB_PASS=****\n"));
+        assert!(git_diff(&[old.clone(), old], &password, false, &opts).unwrap().contains("No changes"));
+        assert!(git_diff(&[new], &password, false, &opts).is_err());
+    }
+    
+    #[test]
+    fn test_masked_diff_hides_values() {
+        let old = "# db\nDB_HOST=localhost\nDB_PASS=hunter2-old\nAPI_KEY=abcdef\n";
+        let new = "# db\nDB_HOST=localhost\nDB_PASS=hunter2-new\nAPI_KEY=abcdef\nDEBUG=true\n";
+        
+        let diff = console::strip_ansi_codes(&masked_diff(old, new, &ProcessOptions::default())).to_string();
+        assert!(diff.contains("-DB_PASS=****\n+DB_PASS=****\n"));
+        assert!(diff.contains("+DEBUG=****\n"));
+        for secret in ["hunter2", "=hu", "localhost", "abcdef", "true", "=t"] {
+            assert!(!diff.contains(secret), "{} leaked", secret);
+        }
+    }
+}
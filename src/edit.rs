@@ -0,0 +1,211 @@
+//! Edit an encrypted .env file in $EDITOR and re-encrypt it on save
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Result;
+use console::style;
+use secrecy::SecretString;
+
+use crate::cancel;
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::interactive;
+use crate::memfile::TempPlaintext;
+
+/// Editor used when neither $VISUAL nor $EDITOR is set
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+
+/// Editor used when neither $VISUAL nor $EDITOR is set
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Editor command from $VISUAL, then $EDITOR, then the platform default
+fn editor_command() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Run the editor on `path`; the command may carry arguments (e.g. `code --wait`)
+fn run_editor(editor: &str, path: &Path) -> Result<()> {
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No editor configured; set $EDITOR"))?;
+    
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to start editor '{}': {}", program, e))?;
+    
+    if !status.success() {
+        anyhow::bail!("Editor exited with {}; nothing was written", status);
+    }
+    
+    Ok(())
+}
+
+/// Decrypt `content`, let `editor` change it and return the re-encrypted file
+/// Returns None if the plaintext was not changed
+fn edit_content(
+    content: &str,
+    password: &SecretString,
+    editor: &str,
+    opts: &ProcessOptions,
+) -> Result<Option<String>> {
+    let armored = engine::is_armored(content);
+    let content = if armored { engine::dearmor(content)? } else { content.to_string() };
+    
+    let (plaintext, _) = engine::process_file(&content, password, ProcessMode::Decrypt, opts)?;
+    
+    let temp = TempPlaintext::create(&plaintext)?;
+    let edited = {
+        // Ctrl-C while the editor runs is deferred, so the plaintext copy is always removed
+        let _guard = cancel::WriteGuard::new();
+        run_editor(editor, temp.path()).and_then(|_| Ok(fs::read_to_string(temp.path())?))
+    };
+    drop(temp);
+    if cancel::is_cancelled() {
+        return Err(cancel::Cancelled::Interrupted.into());
+    }
+    let edited = edited?;
+    
+    if edited == plaintext {
+        return Ok(None);
+    }
+    
+    // Keep the file's stored name, share scheme, key policy, cipher, compression, hidden keys and armoring as they were
+    let (name, body) = engine::split_name_header(&content);
+    let (shares, body) = engine::split_shares_header(body);
+    let (key_policy, body) = engine::split_keys_header(body).map_err(|e| e.within(&content, body))?;
+    let compress = engine::is_compressed(body);
+    let (cipher, body) = engine::split_cipher_header(body).map_err(|e| e.within(&content, body))?;
+    let opts = ProcessOptions {
+        cipher: cipher.cipher,
+        compress,
+        shares,
+        hide_keys: engine::has_hidden_keys(body),
+        key_policy,
+        ..opts.clone()
+    };
+    let (encrypted, _) = engine::process_file(&edited, password, ProcessMode::Encrypt, &opts)?;
+    let encrypted = match name {
+        Some(name) => engine::add_name_header(&encrypted, name),
+        None => encrypted,
+    };
+    
+    Ok(Some(if armored { engine::armor(&encrypted) } else { encrypted }))
+}
+
+/// Handle edit command
+/// Decrypts to a private temp file, opens $EDITOR and re-encrypts on a successful save
+pub fn handle_edit(file: &Path, password: Option<String>, opts: &ProcessOptions) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("File not found: {}", file.display());
+    }
+    
+    let content = fs::read_to_string(file)?;
+    let password = match password {
+        Some(p) => interactive::password_from_arg(p, ProcessMode::Decrypt)?,
+        None => interactive::get_password_from_env_or_prompt(false)?,
+    };
+    
+    let Some(encrypted) = edit_content(&content, &password, &editor_command(), opts)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?
+    else {
+        println!("{} No changes, {} left unchanged", style("ℹ️").cyan(), file.display());
+        return Ok(());
+    };
+    
+    write_atomic(file, &encrypted)?;
+    println!("{} Done! Re-encrypted {}", style("✅").green(), style(file.display()).cyan());
+    
+    Ok(())
+}
+
+/// Replace `path` (through a symlink) by writing a temp file next to it and renaming it over,
+/// so a crash never leaves the only copy of the encrypted file truncated
+fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let target = fs::canonicalize(path)?;
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = target.with_file_name(format!(".{}.wc-envc-{}.tmp", name, std::process::id()));
+    
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(content.as_bytes())?;
+            file.set_permissions(fs::metadata(&target)?.permissions())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, &target));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    Ok(written?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_with_fake_editor() {
+        use std::os::unix::fs::PermissionsExt;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let editor = dir.path().join("editor.sh");
+        fs::write(&editor, "#!/bin/sh\necho 'API_KEY=abc123' >> \"$1\"\n").unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+        
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions::default();
+        let (encrypted, _) =
+            engine::process_file("DB_HOST=localhost\n", &password, ProcessMode::Encrypt, &opts).unwrap();
+        
+        let edited = edit_content(&encrypted, &password, editor.to_str().unwrap(), &opts)
+            .unwrap()
+            .unwrap();
+        assert!(!edited.contains("abc123"));
+        
+        let (decrypted, _) = engine::process_file(&edited, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, "DB_HOST=localhost\nAPI_KEY=abc123\n");
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_aborted_or_unchanged() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions::default();
+        let (encrypted, _) =
+            engine::process_file("DB_HOST=localhost\n", &password, ProcessMode::Encrypt, &opts).unwrap();
+        
+        assert!(edit_content(&encrypted, &password, "false", &opts).is_err());
+        assert!(edit_content(&encrypted, &password, "true", &opts).unwrap().is_none());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_mode_and_leaves_no_temp() {
+        use std::os::unix::fs::PermissionsExt;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".env.enc");
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        
+        write_atomic(&file, "new").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
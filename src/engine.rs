@@ -1,26 +1,733 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
-use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use clap::ValueEnum;
+use magic_crypt::{new_magic_crypt, MagicCrypt256, MagicCryptTrait};
 use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+
+use crate::format::InputFormat;
+use crate::keypolicy::KeyPolicy;
+
+/// First line of an armored encrypted file
+const ARMOR_BEGIN: &str = "-----BEGIN WC-ENVC-----";
+
+/// Last line of an armored encrypted file
+const ARMOR_END: &str = "-----END WC-ENVC-----";
+
+/// Line width for armored Base64 body
+const ARMOR_LINE_WIDTH: usize = 64;
+
+/// First line of a compressed file: the whole plaintext is gzipped and encrypted as one blob
+const COMPRESSED_HEADER: &str = "# wc-envc: compressed=gzip";
+
+/// First line of a `--hide-keys` file: the encrypted hidden-name -> key mapping
+const HIDDEN_KEYS_PREFIX: &str = "# wc-envc keys: ";
+
+/// Ciphertext prefix of an inlined file (`--inline-files`): `@inline:<path>:<base64>`
+const INLINE_MARKER: &str = "@inline:";
+
+/// Header line recording a non-default cipher: `# wc-envc: cipher=<name>`
+const CIPHER_HEADER_PREFIX: &str = "# wc-envc: cipher=";
+
+/// Key derivation recorded in salted cipher headers, and its Argon2id cost
+const ARGON2_KDF: &str = "argon2id";
+#[cfg_attr(not(feature = "chacha20"), allow(dead_code))]
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+#[cfg_attr(not(feature = "chacha20"), allow(dead_code))]
+const ARGON2_ITERATIONS: u32 = 2;
+
+/// Length of the random per-file salt
+#[cfg_attr(not(feature = "chacha20"), allow(dead_code))]
+const SALT_LEN: usize = 16;
+
+/// Header line with the original file name (`--store-name`); stored in plain text
+const NAME_HEADER_PREFIX: &str = "# wc-envc: name=";
+
+/// Header line of a `--split` file: `# wc-envc: shares=<K>-of-<N>`
+const SHARES_HEADER_PREFIX: &str = "# wc-envc: shares=";
+
+/// Header line per recipient of an `encrypt --recipient` file: `# wc-envc: recipient=<public key> <wrapped file key>`
+const RECIPIENT_HEADER_PREFIX: &str = "# wc-envc: recipient=";
+
+/// Header line of a file encrypted under a `.wc-envc-keys` policy: `# wc-envc: encrypted-keys=<patterns>`
+const KEYS_HEADER_PREFIX: &str = "# wc-envc: encrypted-keys=";
 
 /// Modes for processing .env files
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
 pub enum ProcessMode {
+    /// Plain .env files to encrypt
     Encrypt,
+    /// Encrypted .env.enc files to decrypt
     Decrypt,
 }
 
-/// Encrypts a single value using AES-256
-pub fn encrypt_value(value: &str, password: &SecretString) -> String {
-    let mc = new_magic_crypt!(password.expose_secret(), 256);
-    mc.encrypt_str_to_base64(value.trim())
+/// Options controlling how .env content is parsed and validated
+#[derive(Clone, Debug)]
+pub struct ProcessOptions {
+    /// Line prefixes treated as comments (`#` is always included)
+    pub comment_prefixes: Vec<String>,
+    /// Fail validation if any value is not encrypted
+    pub fail_on_plaintext: bool,
+    /// Minimum fraction (0.0-1.0) of values that must look encrypted
+    pub min_encrypted: f64,
+    /// Treat invalid environment variable names as errors instead of warnings
+    pub strict: bool,
+    /// Collapse duplicate keys, keeping the last occurrence
+    pub dedupe: bool,
+    /// Format of plain inputs (converted to .env before encrypting)
+    pub input_format: InputFormat,
+    /// Trim whitespace around values before encrypting (off = byte-exact values)
+    pub trim: bool,
+    /// Gzip the whole plaintext and encrypt it as one blob (`compress` feature)
+    pub compress: bool,
+    /// Replace key names with hashes on encrypt; the mapping is stored encrypted
+    pub hide_keys: bool,
+    /// Base directory for `KEY=@path` file inlining (None = `@` values are plain text)
+    pub inline_files: Option<PathBuf>,
+    /// Cipher for encrypting; decryption follows the file's cipher header
+    pub cipher: Cipher,
+    /// Stop at the first value that fails; off = check every line and report all failures
+    pub fail_fast: bool,
+    /// Record a `--split` share scheme (threshold, count) in the encrypted file's header
+    pub shares: Option<(u8, u8)>,
+    /// Record the file key wrapped to each `encrypt --recipient` public key in the header (see recipient.rs)
+    pub recipient_keys: Vec<String>,
+    /// Merge in files named by `# include:` lines (decrypt and setenv, see include.rs)
+    pub follow_includes: bool,
+    /// Tidy the output afterwards (see `normalize`); off = only values change
+    pub normalize: bool,
+    /// Encrypt only the keys a `.wc-envc-keys` policy lists (see keypolicy.rs); decryption reads it from the header
+    pub key_policy: Option<KeyPolicy>,
+    /// On decrypt, pass values that don't look encrypted (`is_likely_encrypted`) through unchanged
+    pub allow_plaintext: bool,
+    /// Write keys as `KEY=`, dropping whitespace around the key and before the value
+    pub trim_keys: bool,
+    /// Final newline of the output: as in the input, always or never
+    pub final_newline: FinalNewline,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            comment_prefixes: vec!["#".to_string()],
+            fail_on_plaintext: false,
+            min_encrypted: 0.0,
+            strict: false,
+            dedupe: false,
+            input_format: InputFormat::Auto,
+            trim: true,
+            compress: false,
+            hide_keys: false,
+            inline_files: None,
+            cipher: Cipher::default(),
+            fail_fast: true,
+            shares: None,
+            recipient_keys: Vec::new(),
+            follow_includes: false,
+            normalize: false,
+            key_policy: None,
+            allow_plaintext: false,
+            trim_keys: true,
+            final_newline: FinalNewline::Preserve,
+        }
+    }
+}
+
+impl ProcessOptions {
+    /// Build options with extra comment prefixes in addition to `#`
+    pub fn with_comment_prefixes(extra: &[String]) -> Self {
+        let mut opts = Self::default();
+        for prefix in extra {
+            if !prefix.is_empty() && !opts.comment_prefixes.contains(prefix) {
+                opts.comment_prefixes.push(prefix.clone());
+            }
+        }
+        opts
+    }
+    
+    /// Check if a trimmed line is empty or a comment (preserved as-is)
+    pub fn is_ignored(&self, trimmed: &str) -> bool {
+        trimmed.is_empty() || self.comment_prefixes.iter().any(|p| trimmed.starts_with(p.as_str()))
+    }
+    
+    /// Whether these options need the whole input before anything is written, which rules out
+    /// streaming with `process_reader` (content-based reasons, like headers, are checked there)
+    pub fn needs_whole_file(&self, mode: ProcessMode) -> bool {
+        let either = self.dedupe
+            || self.normalize
+            || self.strict
+            || !self.fail_fast
+            || self.fail_on_plaintext
+            || self.min_encrypted > 0.0
+            || self.follow_includes
+            || self.inline_files.is_some()
+            || self.final_newline != FinalNewline::Preserve;
+        either
+            || match mode {
+                ProcessMode::Encrypt => {
+                    self.compress
+                        || self.hide_keys
+                        || self.shares.is_some()
+                        || !self.recipient_keys.is_empty()
+                        || self.key_policy.is_some()
+                }
+                // Passthrough judges the file as a whole, not by its first value
+                ProcessMode::Decrypt => self.allow_plaintext,
+            }
+    }
+}
+
+/// Check if a key is a valid POSIX environment variable name (`[A-Za-z_][A-Za-z0-9_]*`)
+pub fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Find keys that are not valid environment variable names
+/// Returns list of (line number, key); errors instead under `strict`
+pub fn validate_key_names(content: &str, opts: &ProcessOptions) -> Result<Vec<(usize, String)>> {
+    let mut invalid = Vec::new();
+    
+    for (index, line) in lines(content).enumerate() {
+        if opts.is_ignored(line.trim()) {
+            continue;
+        }
+        
+        if let Some(eq_pos) = line.find('=') {
+            let key = line[..eq_pos].trim();
+            if !is_valid_key(key) {
+                invalid.push((index + 1, key.to_string()));
+            }
+        }
+    }
+    
+    if opts.strict && !invalid.is_empty() {
+        let details: Vec<String> = invalid
+            .iter()
+            .map(|(line, key)| format!("line {}: '{}'", line, key))
+            .collect();
+        anyhow::bail!("Invalid environment variable name(s): {}", details.join(", "));
+    }
+    
+    Ok(invalid)
+}
+
+/// Iterate over (0-based line index, key) for every KEY=VALUE line
+pub fn key_lines<'a>(content: &'a str, opts: &'a ProcessOptions) -> impl Iterator<Item = (usize, &'a str)> {
+    lines(content)
+        .enumerate()
+        .filter(|(_, line)| !opts.is_ignored(line.trim()))
+        .filter_map(|(index, line)| line.split_once('=').map(|(key, _)| (index, key.trim())))
+}
+
+/// Keys of every KEY=VALUE line, in file order (duplicates included)
+pub fn keys<'a>(content: &'a str, opts: &'a ProcessOptions) -> impl Iterator<Item = &'a str> {
+    key_lines(content, opts).map(|(_, key)| key)
+}
+
+/// Find keys defined more than once
+/// Returns list of (key, 1-based line numbers) in order of first occurrence
+pub fn find_duplicate_keys(content: &str, opts: &ProcessOptions) -> Vec<(String, Vec<usize>)> {
+    let mut order = Vec::new();
+    let mut lines: HashMap<&str, Vec<usize>> = HashMap::new();
+    
+    for (index, key) in key_lines(content, opts) {
+        let entry = lines.entry(key).or_default();
+        if entry.is_empty() {
+            order.push(key);
+        }
+        entry.push(index + 1);
+    }
+    
+    order
+        .into_iter()
+        .filter_map(|key| {
+            let numbers = lines.remove(key)?;
+            (numbers.len() > 1).then(|| (key.to_string(), numbers))
+        })
+        .collect()
+}
+
+/// Line indices of earlier occurrences of duplicated keys (last one wins)
+fn superseded_lines(content: &str, opts: &ProcessOptions) -> HashSet<usize> {
+    let mut last: HashMap<&str, usize> = HashMap::new();
+    let mut superseded = HashSet::new();
+    
+    for (index, key) in key_lines(content, opts) {
+        if let Some(previous) = last.insert(key, index) {
+            superseded.insert(previous);
+        }
+    }
+    
+    superseded
+}
+
+/// Drop earlier occurrences of duplicated keys, keeping the last one like shells do
+pub fn dedupe_keys(content: &str, opts: &ProcessOptions) -> String {
+    let superseded = superseded_lines(content, opts);
+    lines(content)
+        .enumerate()
+        .filter(|(index, _)| !superseded.contains(index))
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Tidy the layout of .env content: leading and repeated blank lines dropped, `\n` line endings,
+/// exactly one final newline, and trailing whitespace trimmed where it can't belong to a value
+pub fn normalize(content: &str, opts: &ProcessOptions) -> String {
+    let mut output = String::new();
+    let mut blank = true;
+    for line in lines(content) {
+        let line = if ends_in_value(line, opts) { line } else { line.trim_end() };
+        if line.is_empty() && blank {
+            continue;
+        }
+        blank = line.is_empty();
+        output.push_str(line);
+        output.push('\n');
+    }
+    
+    if blank {
+        output.pop();
+    }
+    output
+}
+
+/// Whether `line` ends with an unquoted value, whose trailing whitespace may be part of it
+fn ends_in_value(line: &str, opts: &ProcessOptions) -> bool {
+    let Some(eq_pos) = line.find('=').filter(|_| !opts.is_ignored(line.trim())) else {
+        return false;
+    };
+    let (value, comment) = split_inline_comment(&line[eq_pos + 1..]);
+    let value = value.trim();
+    let quoted = ['"', '\''].iter().any(|&q| value.len() > 1 && value.starts_with(q) && value.ends_with(q));
+    comment.is_empty() && !quoted
+}
+
+/// Whether output ends with a line ending (`--final-newline`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum FinalNewline {
+    /// Keep the input's final newline or lack of one
+    #[default]
+    Preserve,
+    /// End with a line ending (POSIX text files), in the style the file already uses
+    Always,
+    /// Drop line endings after the last line
+    Never,
+}
+
+impl FinalNewline {
+    /// Apply the policy to finished output; empty output stays empty
+    pub fn apply(self, content: &str) -> String {
+        match self {
+            FinalNewline::Preserve => content.to_string(),
+            FinalNewline::Never => content.trim_end_matches(['\r', '\n']).to_string(),
+            FinalNewline::Always if content.is_empty() || content.ends_with(['\r', '\n']) => content.to_string(),
+            FinalNewline::Always => {
+                let ending = lines_inclusive(content)
+                    .map(|line| split_line_ending(line).1)
+                    .filter(|ending| !ending.is_empty())
+                    .last()
+                    .unwrap_or("\n");
+                format!("{}{}", content, ending)
+            }
+        }
+    }
+}
+
+/// Cipher backends for encrypting values
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Cipher {
+    /// AES-256 (default; files without a cipher header)
+    #[default]
+    Aes256,
+    /// ChaCha20-Poly1305 (requires the `chacha20` feature)
+    #[value(name = "chacha20-poly1305")]
+    Chacha20Poly1305,
+}
+
+impl Cipher {
+    /// Name used on the command line and in the cipher header
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::Aes256 => "aes256",
+            Cipher::Chacha20Poly1305 => "chacha20-poly1305",
+        }
+    }
+    
+    /// How a file newly encrypted with this cipher derives its value key from the password
+    pub fn kdf(self) -> &'static str {
+        match self {
+            Cipher::Aes256 => "SHA-256 of the password (no salt)",
+            Cipher::Chacha20Poly1305 => "Argon2id with a per-file salt",
+        }
+    }
+}
+
+/// How a file's value key is derived: a random key wrapped to its age recipients if it has any,
+/// otherwise `kdf` from the password
+pub fn key_derivation(kdf: &'static str, recipients: usize) -> &'static str {
+    if recipients > 0 {
+        "random file key wrapped to age X25519 recipients"
+    } else {
+        kdf
+    }
+}
+
+/// A file's cipher with its key derivation salt, as recorded in the cipher header
+/// ChaCha20-Poly1305 keys are derived with Argon2id from the password and a random per-file salt;
+/// headers without a salt come from versions that used an unsalted SHA-256 and still decrypt
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileCipher {
+    pub cipher: Cipher,
+    pub salt: Option<Vec<u8>>,
+}
+
+impl FileCipher {
+    /// Cipher for a new file: a fresh random salt for the backends that use one
+    pub fn generate(cipher: Cipher) -> EngineResult<Self> {
+        let salt = match cipher {
+            Cipher::Aes256 => None,
+            Cipher::Chacha20Poly1305 => Some(random_salt()?),
+        };
+        Ok(Self { cipher, salt })
+    }
+    
+    /// Build the value cipher from the password
+    pub fn build(&self, password: &SecretString) -> EngineResult<Box<dyn ValueCipher>> {
+        timed_derivation(|| match self.cipher {
+            Cipher::Aes256 => Ok(Box::new(derive_key(password)) as Box<dyn ValueCipher>),
+            Cipher::Chacha20Poly1305 => chacha_cipher(password, self.salt.as_deref()),
+        })
+    }
+    
+    /// How the value key is derived from the password
+    pub fn kdf(&self) -> &'static str {
+        match (self.cipher, &self.salt) {
+            (Cipher::Chacha20Poly1305, None) => "SHA-256 of a domain tag and the password (no salt, older file)",
+            (cipher, _) => cipher.kdf(),
+        }
+    }
+    
+    /// Header line for an encrypted file; None for the default AES-256, which has none
+    fn header(&self) -> Option<String> {
+        match (self.cipher, &self.salt) {
+            (Cipher::Aes256, _) => None,
+            (cipher, Some(salt)) => Some(format!(
+                "{}{} kdf={} salt={}\n",
+                CIPHER_HEADER_PREFIX,
+                cipher.name(),
+                ARGON2_KDF,
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, salt)
+            )),
+            (cipher, None) => Some(format!("{}{}\n", CIPHER_HEADER_PREFIX, cipher.name())),
+        }
+    }
+}
+
+/// Why a value could not be decrypted
+#[derive(Debug, PartialEq)]
+pub enum DecryptError {
+    /// Wrong password or not encrypted data (AES-256 can't tell them apart)
+    Invalid,
+    /// The authentication tag did not verify: wrong password or modified ciphertext
+    #[cfg_attr(not(any(feature = "chacha20", feature = "dotenv-vault")), allow(dead_code))]
+    AuthError,
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::Invalid => write!(f, "Wrong password or invalid encrypted data"),
+            DecryptError::AuthError => {
+                write!(f, "Authentication failed: wrong password or the encrypted data was tampered with")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+/// Why processing failed, for callers that act on the kind of failure (e.g. ask for the password again)
+#[derive(Debug)]
+pub enum EngineError {
+    /// A value did not decrypt: the wrong password, or data that isn't (intact) ciphertext
+    WrongPassword(DecryptError),
+    /// Malformed encrypted data: a hidden key mapping or inlined file that doesn't decode
+    Corrupt(String),
+    /// A cipher or format this build can't handle (unknown header, optional feature not built in)
+    UnsupportedVersion(String),
+    /// Reading or writing failed (inlined files, streamed input and output)
+    Io { context: String, source: std::io::Error },
+    /// A header or armored block that doesn't parse, at a 1-based line of the file
+    Parse { line: usize, message: String },
+    /// Where `source` happened: a 1-based line
+    AtLine { line: usize, source: Box<EngineError> },
+    /// Several values failed with `fail_fast` off: (line, key, why) for each
+    Failures(Vec<(usize, String, EngineError)>),
+    /// Input the engine refuses: values that aren't encrypted, unsafe inlined paths, conflicting options
+    Invalid(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::WrongPassword(e) => write!(f, "{}", e),
+            EngineError::Corrupt(message)
+            | EngineError::UnsupportedVersion(message)
+            | EngineError::Invalid(message) => write!(f, "{}", message),
+            EngineError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            EngineError::Io { context, source } => write!(f, "{}: {}", context, source),
+            EngineError::AtLine { line, source } => write!(f, "line {}: {}", line, source),
+            EngineError::Failures(failures) => {
+                write!(f, "{} value(s) failed:", failures.len())?;
+                for (line, key, e) in failures {
+                    write!(f, "\n  line {} ({}): {}", line, key, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::WrongPassword(e) => Some(e),
+            EngineError::Io { source, .. } => Some(source),
+            EngineError::AtLine { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl EngineError {
+    /// Parse error for the first line of the header or block being parsed; see `within`
+    fn parse(message: impl Into<String>) -> Self {
+        EngineError::Parse { line: 1, message: message.into() }
+    }
+    
+    /// Count the line of a `Parse` error from the start of `content`, for one found in `rest`,
+    /// the part of `content` left after splitting off the headers before it
+    pub fn within(self, content: &str, rest: &str) -> Self {
+        match self {
+            EngineError::Parse { line, message } => {
+                let before = content.get(..content.len().saturating_sub(rest.len())).unwrap_or_default();
+                EngineError::Parse { line: line + before.matches('\n').count(), message }
+            }
+            e => e,
+        }
+    }
+}
+
+impl From<DecryptError> for EngineError {
+    fn from(e: DecryptError) -> Self {
+        EngineError::WrongPassword(e)
+    }
+}
+
+/// Result of the engine's processing functions
+pub type EngineResult<T> = std::result::Result<T, EngineError>;
+
+/// Decrypts whole files written by another tool, picked by sniffing the start of the file
+pub trait Decoder: Sync {
+    /// Format name for messages
+    fn name(&self) -> &'static str;
+    /// Whether `header` (the first `DECODER_HEADER_LINES` lines) is in this decoder's format
+    fn can_decode(&self, header: &str) -> bool;
+    /// Decrypt the whole file to plain .env content
+    fn decrypt(&self, content: &str, password: &SecretString) -> EngineResult<String>;
+}
+
+/// Lines of a file shown to `Decoder::can_decode`
+const DECODER_HEADER_LINES: usize = 10;
+
+/// Decoders for other tools' formats; without its optional feature a decoder still recognizes
+/// its format and fails with a hint to rebuild
+static DECODERS: &[&dyn Decoder] = &[&crate::decoders::DotenvVault];
+
+/// Registered decoder for `content`, if it is in another tool's format
+pub fn find_decoder(content: &str) -> Option<&'static dyn Decoder> {
+    let header: String = lines_inclusive(content).take(DECODER_HEADER_LINES).collect();
+    DECODERS.iter().copied().find(|decoder| decoder.can_decode(&header))
+}
+
+/// Encrypts and decrypts single values to and from Base64
+pub trait ValueCipher {
+    /// Encrypt a value byte-exact
+    fn encrypt(&self, value: &str) -> String;
+    /// Decrypt a value; AEAD backends report a failed tag check as `AuthError`
+    fn decrypt(&self, encrypted: &str) -> Result<String, DecryptError>;
+}
+
+impl ValueCipher for MagicCrypt256 {
+    fn encrypt(&self, value: &str) -> String {
+        self.encrypt_str_to_base64(value)
+    }
+    
+    fn decrypt(&self, encrypted: &str) -> Result<String, DecryptError> {
+        self.decrypt_base64_to_string(encrypted.trim())
+            .map_err(|_| DecryptError::Invalid)
+    }
+}
+
+/// ChaCha20-Poly1305 with a nonce derived from key and plaintext, so re-encrypting
+/// an unchanged value gives the same ciphertext (like the AES backend)
+#[cfg(feature = "chacha20")]
+struct ChaChaCipher {
+    key: [u8; 32],
+}
+
+#[cfg(feature = "chacha20")]
+impl ValueCipher for ChaChaCipher {
+    fn encrypt(&self, value: &str) -> String {
+        use chacha20poly1305::aead::{Aead, KeyInit};
+        
+        let digest = Sha256::new().chain_update(self.key).chain_update(value).finalize();
+        let nonce = chacha20poly1305::Nonce::from_slice(&digest[..12]);
+        let sealed = chacha20poly1305::ChaCha20Poly1305::new(&self.key.into())
+            .encrypt(nonce, value.as_bytes())
+            .expect("ChaCha20-Poly1305 encryption cannot fail for in-memory values");
+        
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&sealed);
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes)
+    }
+    
+    fn decrypt(&self, encrypted: &str) -> Result<String, DecryptError> {
+        use chacha20poly1305::aead::{Aead, KeyInit};
+        
+        let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encrypted.trim())
+            .map_err(|_| DecryptError::Invalid)?;
+        if bytes.len() < 12 {
+            return Err(DecryptError::Invalid);
+        }
+        
+        let (nonce, sealed) = bytes.split_at(12);
+        let plain = chacha20poly1305::ChaCha20Poly1305::new(&self.key.into())
+            .decrypt(chacha20poly1305::Nonce::from_slice(nonce), sealed)
+            .map_err(|_| DecryptError::AuthError)?;
+        String::from_utf8(plain).map_err(|_| DecryptError::Invalid)
+    }
+}
+
+/// ChaCha20-Poly1305 key: Argon2id of the password and the file's salt,
+/// or for files written before salts a SHA-256 of a domain prefix and the password
+#[cfg(feature = "chacha20")]
+fn chacha_cipher(password: &SecretString, salt: Option<&[u8]>) -> EngineResult<Box<dyn ValueCipher>> {
+    let Some(salt) = salt else {
+        let key = Sha256::new()
+            .chain_update("wc-envc chacha20-poly1305\0")
+            .chain_update(password.expose_secret())
+            .finalize();
+        return Ok(Box::new(ChaChaCipher { key: key.into() }));
+    };
+    
+    let params = argon2::Params::new(ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, 1, Some(32))
+        .map_err(|e| EngineError::Invalid(format!("Argon2 parameters: {}", e)))?;
+    let mut key = [0u8; 32];
+    argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+        .hash_password_into(password.expose_secret().as_bytes(), salt, &mut key)
+        .map_err(|e| EngineError::Corrupt(format!("Cannot derive the file key: {}", e)))?;
+    Ok(Box::new(ChaChaCipher { key }))
+}
+
+/// ChaCha20-Poly1305 needs the optional `chacha20` feature
+#[cfg(not(feature = "chacha20"))]
+fn chacha_cipher(_password: &SecretString, _salt: Option<&[u8]>) -> EngineResult<Box<dyn ValueCipher>> {
+    Err(EngineError::UnsupportedVersion(
+        "The chacha20-poly1305 cipher requires building with `--features chacha20`".to_string(),
+    ))
+}
+
+/// Random key derivation salt for a new file
+#[cfg(feature = "chacha20")]
+fn random_salt() -> EngineResult<Vec<u8>> {
+    use rand::RngCore;
+    
+    let mut salt = vec![0u8; SALT_LEN];
+    rand::rngs::OsRng
+        .try_fill_bytes(&mut salt)
+        .map_err(|e| EngineError::Invalid(format!("Cannot generate a salt: {}", e)))?;
+    Ok(salt)
+}
+
+/// Without the `chacha20` feature there is nothing to salt; `chacha_cipher` reports why
+#[cfg(not(feature = "chacha20"))]
+fn random_salt() -> EngineResult<Vec<u8>> {
+    Ok(Vec::new())
+}
+
+/// Split off a cipher header line; content without one uses the default AES-256
+pub fn split_cipher_header(content: &str) -> EngineResult<(FileCipher, &str)> {
+    let Some(rest) = content.strip_prefix(CIPHER_HEADER_PREFIX) else {
+        return Ok((FileCipher::default(), content));
+    };
+    
+    let (line, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let mut fields = line.split_whitespace();
+    let name = fields.next().unwrap_or_default();
+    let cipher = Cipher::from_str(name, false)
+        .map_err(|_| EngineError::UnsupportedVersion(format!("Unknown cipher '{}' in file header", name)))?;
+    
+    let (mut kdf, mut salt) = (false, None);
+    for field in fields {
+        match field.split_once('=') {
+            Some(("kdf", ARGON2_KDF)) => kdf = true,
+            Some(("salt", encoded)) => {
+                let decoded = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded)
+                    .map_err(|_| EngineError::parse("Malformed salt in the cipher header"))?;
+                salt = Some(decoded);
+            }
+            _ => {
+                return Err(EngineError::UnsupportedVersion(format!(
+                    "Unknown cipher header field '{}'",
+                    field
+                )))
+            }
+        }
+    }
+    if kdf != salt.is_some() {
+        return Err(EngineError::parse("The cipher header needs both kdf= and salt="));
+    }
+    Ok((FileCipher { cipher, salt }, body))
+}
+
+thread_local! {
+    /// Time this thread has spent deriving keys, for `--time`
+    static KEY_DERIVATION: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Run `derive` and add its duration to this thread's key derivation time
+fn timed_derivation<T>(derive: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let derived = derive();
+    KEY_DERIVATION.with(|total| total.set(total.get() + started.elapsed()));
+    derived
 }
 
-/// Decrypts a Base64 encrypted value
-/// Returns Err if password is wrong or value is not valid encrypted data
-pub fn decrypt_value(encrypted: &str, password: &SecretString) -> Result<String> {
-    let mc = new_magic_crypt!(password.expose_secret(), 256);
-    mc.decrypt_base64_to_string(encrypted.trim())
-        .map_err(|_| anyhow::anyhow!("Wrong password or invalid encrypted data"))
+/// Run `f` and also return how long it spent deriving keys (`--time`)
+/// Only derivations on the calling thread count, which is where the engine does them
+pub fn with_key_derivation_time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let before = KEY_DERIVATION.with(Cell::get);
+    let result = f();
+    (result, KEY_DERIVATION.with(Cell::get) - before)
+}
+
+/// Derives the AES-256 cipher key from the password
+pub fn derive_key(password: &SecretString) -> MagicCrypt256 {
+    new_magic_crypt!(password.expose_secret(), 256)
 }
 
 /// Checks if a string looks like Base64 encoded data
@@ -35,108 +742,988 @@ pub fn is_likely_encrypted(value: &str) -> bool {
         && trimmed.len() >= 8 // Encrypted values are typically longer
 }
 
+/// Mask a value for display: no character of it is shown, whatever its length
+/// Only whether it is empty is visible
+pub fn mask_value(value: &str) -> String {
+    if value.is_empty() {
+        return String::new();
+    }
+    "****".to_string()
+}
+
 /// Process a single line from .env file
 /// Returns the processed line (encrypted/decrypted)
-fn process_line(line: &str, password: &SecretString, mode: ProcessMode) -> Result<String> {
+fn process_line(
+    line: &str,
+    cipher: &dyn ValueCipher,
+    mode: ProcessMode,
+    opts: &ProcessOptions,
+) -> EngineResult<String> {
     let trimmed = line.trim();
     
     // Preserve empty lines and comments
-    if trimmed.is_empty() || trimmed.starts_with('#') {
+    if opts.is_ignored(trimmed) {
         return Ok(line.to_string());
     }
     
     // Check for KEY=VALUE pattern
     if let Some(eq_pos) = line.find('=') {
-        let key = &line[..eq_pos];
         let value = &line[eq_pos + 1..];
+        let (start, end) = value_bounds(value, mode, opts);
+        
+        // Keys outside a `.wc-envc-keys` policy stay plaintext both ways
+        let unlisted = opts.key_policy.as_ref().is_some_and(|policy| !policy.matches(line[..eq_pos].trim()));
         
-        match mode {
-            ProcessMode::Encrypt => {
-                let encrypted = encrypt_value(value, password);
-                Ok(format!("{}={}", key, encrypted))
+        let replaced = match (mode, &opts.inline_files) {
+            _ if unlisted => value[start..end].to_string(),
+            (ProcessMode::Encrypt, Some(base)) if value[start..end].starts_with('@') => {
+                cipher.encrypt(&inline_file(base, &value[start + 1..end])?)
             }
-            ProcessMode::Decrypt => {
-                let decrypted = decrypt_value(value, password)?;
-                Ok(format!("{}={}", key, decrypted))
+            (ProcessMode::Encrypt, _) => cipher.encrypt(&value[start..end]),
+            (ProcessMode::Decrypt, _) if opts.allow_plaintext && !is_likely_encrypted(&value[start..end]) => {
+                value[start..end].to_string()
             }
-        }
+            (ProcessMode::Decrypt, _) => restore_inline_file(cipher.decrypt(&value[start..end])?, opts)?,
+        };
+        
+        Ok(format!("{}{}{}", pair_prefix(line, eq_pos, start, opts), replaced, &value[end..]))
     } else {
         // No '=' found, preserve the line as-is
         Ok(line.to_string())
     }
 }
 
-/// Process entire file content line by line
-/// Returns tuple: (processed_content, list of processed keys)
-pub fn process_file(content: &str, password: &SecretString, mode: ProcessMode) -> Result<(String, Vec<String>)> {
-    let mut output_lines = Vec::new();
-    let mut processed_keys = Vec::new();
-    
-    for line in content.lines() {
-        let processed = process_line(line, password, mode)?;
-        
-        // Track which keys were processed
-        if let Some(eq_pos) = line.find('=') {
-            let trimmed = line.trim();
-            if !trimmed.is_empty() && !trimmed.starts_with('#') {
-                let key = line[..eq_pos].trim().to_string();
-                processed_keys.push(key);
+/// Span of `value` that gets encrypted or decrypted
+/// Only this span is replaced; everything around it stays byte-identical unless `trim_keys` tidies the key
+fn value_bounds(value: &str, mode: ProcessMode, opts: &ProcessOptions) -> (usize, usize) {
+    if mode == ProcessMode::Encrypt && !opts.trim {
+        (0, value.len())
+    } else {
+        value_span(value)
+    }
+}
+
+/// Everything before the value span: `KEY=` under `trim_keys`, otherwise the original bytes
+fn pair_prefix(line: &str, eq_pos: usize, value_start: usize, opts: &ProcessOptions) -> String {
+    if opts.trim_keys {
+        format!("{}=", line[..eq_pos].trim())
+    } else {
+        line[..eq_pos + 1 + value_start].to_string()
+    }
+}
+
+/// Apply `trim_keys` to plaintext the way encrypting does, e.g. to compare a source with its round trip
+pub fn trim_keys(content: &str, opts: &ProcessOptions) -> String {
+    lines_inclusive(content)
+        .map(|raw_line| {
+            let (line, ending) = split_line_ending(raw_line);
+            match line.find('=') {
+                Some(eq_pos) if !opts.is_ignored(line.trim()) => {
+                    let value = &line[eq_pos + 1..];
+                    let start = value_bounds(value, ProcessMode::Encrypt, opts).0;
+                    format!("{}{}{}", pair_prefix(line, eq_pos, start, opts), &value[start..], ending)
+                }
+                _ => raw_line.to_string(),
             }
-        }
-        
-        output_lines.push(processed);
+        })
+        .collect()
+}
+
+/// Check a path named inside a file stays under that file's directory (relative, no `..`)
+/// Used for inlined files and `# include:` targets
+pub fn check_relative_path(path: &str) -> EngineResult<&Path> {
+    let checked = Path::new(path);
+    let escapes = checked
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if path.is_empty() || escapes {
+        return Err(EngineError::Invalid(format!(
+            "Path '{}' must be relative and must not contain '..'",
+            path
+        )));
     }
-    
-    Ok((output_lines.join("\n"), processed_keys))
+    Ok(checked)
 }
 
-/// Validate that file content appears to be encrypted
-/// Checks if values look like Base64
-pub fn validate_encrypted_file(content: &str) -> Result<()> {
-    let mut has_variables = false;
-    let mut encrypted_count = 0;
-    let mut plain_count = 0;
+/// Read `base/path` into an `@inline:<path>:<base64>` value for encryption
+fn inline_file(base: &Path, path: &str) -> EngineResult<String> {
+    let bytes = std::fs::read(base.join(check_relative_path(path)?))
+        .map_err(|e| EngineError::Io { context: format!("Cannot inline '{}'", path), source: e })?;
+    let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
+    Ok(format!("{}{}:{}", INLINE_MARKER, path, encoded))
+}
+
+/// A file decoded from an `@inline:` value, written by the caller once the output is confirmed
+#[derive(Debug, PartialEq)]
+pub struct InlineFile {
+    /// Path relative to the `--inline-files` base directory
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+}
+
+/// Split `<path>:<base64>` (after the `@inline:` marker) into its checked path and contents
+fn parse_inline_file(inlined: &str) -> EngineResult<InlineFile> {
+    let (path, encoded) = inlined
+        .rsplit_once(':')
+        .ok_or_else(|| EngineError::Corrupt("Malformed inlined file value".to_string()))?;
+    let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded)
+        .map_err(|_| EngineError::Corrupt(format!("Malformed inlined file '{}'", path)))?;
+    Ok(InlineFile { path: check_relative_path(path)?.to_path_buf(), bytes })
+}
+
+/// Undo `inline_file` for one decrypted value
+/// With `--inline-files` the value is checked and kept for `take_inline_files`;
+/// without a base directory the Base64 contents are returned inline
+fn restore_inline_file(decrypted: String, opts: &ProcessOptions) -> EngineResult<String> {
+    let Some(inlined) = decrypted.strip_prefix(INLINE_MARKER) else {
+        return Ok(decrypted);
+    };
+    if opts.inline_files.is_some() {
+        parse_inline_file(inlined)?;
+        return Ok(decrypted);
+    }
+    let (_, encoded) = inlined
+        .rsplit_once(':')
+        .ok_or_else(|| EngineError::Corrupt("Malformed inlined file value".to_string()))?;
+    Ok(encoded.to_string())
+}
+
+/// Replace the `@inline:` values of decrypted `content` with `@path`, returning the decoded files
+/// The engine never writes them itself: the caller does, after its overwrite and diff prompts
+pub fn take_inline_files(content: &str, opts: &ProcessOptions) -> EngineResult<(String, Vec<InlineFile>)> {
+    let mut files = Vec::new();
+    let mut output = String::with_capacity(content.len());
     
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
+    for raw_line in lines_inclusive(content) {
+        let (line, ending) = split_line_ending(raw_line);
+        let inlined = match line.find('=') {
+            Some(eq_pos) if opts.inline_files.is_some() && !opts.is_ignored(line.trim()) => {
+                let value = &line[eq_pos + 1..];
+                let (start, end) = value_span(value);
+                value[start..end].strip_prefix(INLINE_MARKER).map(|inlined| (eq_pos + 1 + start, eq_pos + 1 + end, inlined))
+            }
+            _ => None,
+        };
+        let Some((start, end, inlined)) = inlined else {
+            output.push_str(raw_line);
             continue;
-        }
+        };
         
-        if let Some(eq_pos) = line.find('=') {
-            has_variables = true;
-            let value = &line[eq_pos + 1..];
-            
-            if is_likely_encrypted(value) {
-                encrypted_count += 1;
-            } else {
-                plain_count += 1;
-            }
-        }
-    }
-    
-    if !has_variables {
-        anyhow::bail!("File contains no environment variables");
+        let file = parse_inline_file(inlined)?;
+        output.push_str(&format!("{}@{}{}{}", &line[..start], file.path.display(), &line[end..], ending));
+        files.push(file);
     }
     
-    if encrypted_count == 0 && plain_count > 0 {
-        anyhow::bail!("This file appears to be unencrypted");
+    Ok((output, files))
+}
+
+/// Write files from `take_inline_files` under `base`, readable by the owner only
+pub fn write_inline_files(base: &Path, files: &[InlineFile]) -> EngineResult<()> {
+    for file in files {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(base.join(&file.path))
+            .and_then(|mut target| std::io::Write::write_all(&mut target, &file.bytes))
+            .map_err(|e| EngineError::Io {
+                context: format!("Cannot write inlined file '{}'", file.path.display()),
+                source: e,
+            })?;
     }
-    
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Key name without a leading `export `
+pub fn bare_key(key: &str) -> &str {
+    let key = key.trim();
+    key.strip_prefix("export ").map(str::trim_start).unwrap_or(key)
+}
+
+/// Split a raw value into the value itself and a trailing inline comment (` # ...`)
+/// A quoted value ends at its closing quote; an unquoted one at a `#` after whitespace
+pub fn split_inline_comment(value: &str) -> (&str, &str) {
+    let start = value.len() - value.trim_start().len();
+    let body = &value[start..];
+    for quote in ['"', '\''] {
+        if let Some(end) = body.strip_prefix(quote).and_then(|rest| rest.find(quote)) {
+            let end = start + end + 2;
+            let comment = value[end..].find('#').map_or(value.len(), |i| end + i);
+            return (&value[..comment], &value[comment..]);
+        }
+    }
     
-    #[test]
-    fn test_encrypt_decrypt_roundtrip() {
-        let original = "secret_value_123";
-        let password = SecretString::new("test_password".to_string());
-        
-        let encrypted = encrypt_value(original, &password);
-        let decrypted = decrypt_value(&encrypted, &password).unwrap();
+    let comment = body
+        .char_indices()
+        .find(|&(i, c)| c == '#' && body[..i].ends_with(char::is_whitespace))
+        .map_or(value.len(), |(i, _)| start + i);
+    value.split_at(comment)
+}
+
+/// Value without its inline comment, surrounding whitespace and matching quotes
+pub fn unquoted_value(value: &str) -> &str {
+    let value = split_inline_comment(value).0.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|rest| rest.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Byte range of a value without its surrounding whitespace
+fn value_span(value: &str) -> (usize, usize) {
+    let start = value.len() - value.trim_start().len();
+    let end = value.trim_end().len().max(start);
+    (start, end)
+}
+
+/// Split a line from `lines_inclusive` into its content and line ending
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let body = line.strip_suffix('\n').unwrap_or(line);
+    let body = body.strip_suffix('\r').unwrap_or(body);
+    line.split_at(body.len())
+}
+
+/// Lines of .env content with their endings (`\n`, `\r\n`, or lone `\r` in classic Mac files)
+/// Lone `\r` only ends lines when the body has no `\n` at all; leading `# wc-envc` headers always end in `\n`
+pub fn lines_inclusive(content: &str) -> impl Iterator<Item = &str> {
+    let mut headers = 0;
+    while content[headers..].starts_with("# wc-envc") {
+        match content[headers..].find('\n') {
+            Some(end) => headers += end + 1,
+            None => break,
+        }
+    }
+    
+    let (headers, body) = content.split_at(headers);
+    let separator = if body.contains('\r') && !body.contains('\n') { '\r' } else { '\n' };
+    headers.split_inclusive('\n').chain(body.split_inclusive(separator))
+}
+
+/// Lines of .env content without endings: `str::lines` that also understands lone `\r` files
+pub fn lines(content: &str) -> impl Iterator<Item = &str> {
+    lines_inclusive(content).map(|line| split_line_ending(line).0)
+}
+
+/// Process entire file content line by line
+/// Line endings and all bytes outside values are preserved exactly
+/// Returns tuple: (processed_content, list of processed keys)
+pub fn process_file(
+    content: &str,
+    password: &SecretString,
+    mode: ProcessMode,
+    opts: &ProcessOptions,
+) -> EngineResult<(String, Vec<String>)> {
+    let input = content;
+    let content = match mode {
+        ProcessMode::Decrypt => split_recipient_header(split_shares_header(split_name_header(content).1).1).1,
+        ProcessMode::Encrypt => content,
+    };
+    
+    // A key policy header decides which values are encrypted, whatever the caller's options say
+    let with_policy;
+    let (content, opts) = match (mode, split_keys_header(content).map_err(|e| e.within(input, content))?) {
+        (ProcessMode::Decrypt, (Some(policy), body)) => {
+            with_policy = ProcessOptions { key_policy: Some(policy), ..opts.clone() };
+            (body, &with_policy)
+        }
+        _ => (content, opts),
+    };
+    
+    // Parse errors further down count lines from the end of the headers split off here
+    process_after_headers(content, password, mode, opts).map_err(|e| e.within(input, content))
+}
+
+/// `process_file` once the headers before the cipher header are split off
+fn process_after_headers(
+    content: &str,
+    password: &SecretString,
+    mode: ProcessMode,
+    opts: &ProcessOptions,
+) -> EngineResult<(String, Vec<String>)> {
+    if opts.final_newline != FinalNewline::Preserve {
+        let (output, keys) = process_file(content, password, mode, &ProcessOptions { final_newline: FinalNewline::Preserve, ..opts.clone() })?;
+        return Ok((opts.final_newline.apply(&output), keys));
+    }
+    
+    if opts.normalize {
+        let (output, keys) = process_file(content, password, mode, &ProcessOptions { normalize: false, ..opts.clone() })?;
+        return Ok((normalize(&output, opts), keys));
+    }
+    
+    if let (ProcessMode::Encrypt, Some((threshold, count))) = (mode, opts.shares) {
+        let (output, keys) = process_file(content, password, mode, &ProcessOptions { shares: None, ..opts.clone() })?;
+        return Ok((format!("{}{}-of-{}\n{}", SHARES_HEADER_PREFIX, threshold, count, output), keys));
+    }
+    
+    if mode == ProcessMode::Encrypt && !opts.recipient_keys.is_empty() {
+        let (output, keys) = process_file(content, password, mode, &ProcessOptions { recipient_keys: Vec::new(), ..opts.clone() })?;
+        return Ok((add_recipient_header(&output, &opts.recipient_keys), keys));
+    }
+    
+    if let (ProcessMode::Decrypt, Some(decoder)) = (mode, find_decoder(content)) {
+        let decrypted = decoder.decrypt(content, password)?;
+        let keys = key_lines(&decrypted, opts).map(|(_, key)| key.to_string()).collect();
+        return Ok((decrypted, keys));
+    }
+    
+    if mode == ProcessMode::Decrypt && is_compressed(content) {
+        let decrypted = decrypt_compressed(content, password)?;
+        let keys = key_lines(&decrypted, opts).map(|(_, key)| key.to_string()).collect();
+        return Ok((decrypted, keys));
+    }
+    
+    if mode == ProcessMode::Encrypt && opts.compress {
+        if opts.cipher != Cipher::Aes256 {
+            return Err(EngineError::Invalid("--compress only supports the default aes256 cipher".to_string()));
+        }
+        if opts.key_policy.is_some() {
+            return Err(EngineError::Invalid(
+                "--compress encrypts the whole file and can't follow a .wc-envc-keys policy".to_string(),
+            ));
+        }
+        let keys = key_lines(content, opts).map(|(_, key)| key.to_string()).collect();
+        return Ok((encrypt_compressed(content, password)?, keys));
+    }
+    
+    // The cipher header, if any, comes first and is kept outside the per-value processing
+    let (cipher, body) = match mode {
+        ProcessMode::Decrypt => split_cipher_header(content)?,
+        ProcessMode::Encrypt => (FileCipher::generate(opts.cipher)?, content),
+    };
+    let (mut output, keys) = process_body(body, password, &*cipher.build(password)?, mode, opts)?;
+    
+    if let (ProcessMode::Encrypt, Some(header)) = (mode, cipher.header()) {
+        output = header + &output;
+    }
+    if let (ProcessMode::Encrypt, Some(policy)) = (mode, &opts.key_policy) {
+        output = format!("{}{}\n{}", KEYS_HEADER_PREFIX, policy, output);
+    }
+    Ok((output, keys))
+}
+
+/// Process file content after the cipher header: hidden keys, then line by line
+fn process_body(
+    content: &str,
+    password: &SecretString,
+    cipher: &dyn ValueCipher,
+    mode: ProcessMode,
+    opts: &ProcessOptions,
+) -> EngineResult<(String, Vec<String>)> {
+    if mode == ProcessMode::Decrypt && has_hidden_keys(content) {
+        let revealed = reveal_keys(content, cipher, opts)?;
+        return process_body(&revealed, password, cipher, mode, opts);
+    }
+    
+    if mode == ProcessMode::Encrypt && opts.hide_keys {
+        let visible = ProcessOptions { hide_keys: false, ..opts.clone() };
+        let (encrypted, keys) = process_body(content, password, cipher, mode, &visible)?;
+        return Ok((hide_keys(&encrypted, password, cipher, opts), keys));
+    }
+    
+    let mut output = String::with_capacity(content.len());
+    let mut lines = LineProcessor::new(cipher, mode, opts);
+    
+    // Skip superseded duplicates by index so error line numbers stay accurate
+    let superseded = if opts.dedupe {
+        superseded_lines(content, opts)
+    } else {
+        HashSet::new()
+    };
+    
+    for (index, raw_line) in lines_inclusive(content).enumerate() {
+        if superseded.contains(&index) {
+            continue;
+        }
+        
+        if let Some(processed) = lines.process(index, raw_line)? {
+            output.push_str(&processed);
+        }
+    }
+    
+    Ok((output, lines.finish()?))
+}
+
+/// Per-line processing shared by `process_body` and the streaming `process_reader`
+struct LineProcessor<'a> {
+    cipher: &'a dyn ValueCipher,
+    mode: ProcessMode,
+    opts: &'a ProcessOptions,
+    keys: Vec<String>,
+    failures: Vec<(usize, String, EngineError)>,
+}
+
+impl<'a> LineProcessor<'a> {
+    fn new(cipher: &'a dyn ValueCipher, mode: ProcessMode, opts: &'a ProcessOptions) -> Self {
+        Self { cipher, mode, opts, keys: Vec::new(), failures: Vec::new() }
+    }
+    
+    /// Process one line (0-based `index`, ending included)
+    /// Returns None for a failed value recorded for later when `fail_fast` is off
+    fn process(&mut self, index: usize, raw_line: &str) -> EngineResult<Option<String>> {
+        let (line, ending) = split_line_ending(raw_line);
+        let processed = match process_line(line, self.cipher, self.mode, self.opts) {
+            Ok(processed) => processed,
+            Err(e) if self.opts.fail_fast => return Err(EngineError::AtLine { line: index + 1, source: Box::new(e) }),
+            Err(e) => {
+                let key = line.split_once('=').map_or("", |(key, _)| key.trim());
+                self.failures.push((index + 1, key.to_string(), e));
+                return Ok(None);
+            }
+        };
+        
+        // Track which keys were processed
+        if let Some(eq_pos) = line.find('=') {
+            let trimmed = line.trim();
+            if !self.opts.is_ignored(trimmed) {
+                self.keys.push(line[..eq_pos].trim().to_string());
+            }
+        }
+        
+        Ok(Some(processed + ending))
+    }
+    
+    /// Processed keys, or every failure collected with `fail_fast` off
+    fn finish(self) -> EngineResult<Vec<String>> {
+        if !self.failures.is_empty() {
+            return Err(EngineError::Failures(self.failures));
+        }
+        Ok(self.keys)
+    }
+}
+
+/// Streaming `process_file`: reads and writes one line at a time, returns the processed keys
+/// Inputs that need the whole file (`ProcessOptions::needs_whole_file`, headers, armor) are buffered.
+/// Decryption validates the file before writing: the lines up to the first variable are held back
+/// until it checks out. Later lines are written as they go, so a failure can leave `writer` with
+/// the lines before it
+pub fn process_reader<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    password: &SecretString,
+    mode: ProcessMode,
+    opts: &ProcessOptions,
+) -> EngineResult<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(read_failed)?;
+    
+    let buffered = mode == ProcessMode::Decrypt
+        && (line.starts_with("# wc-envc") || is_armored(&line) || find_decoder(&line).is_some());
+    // Without a `\n` the first read took in a whole classic Mac (`\r`) file
+    let buffered = buffered || (line.contains('\r') && !line.ends_with('\n'));
+    if buffered || opts.needs_whole_file(mode) {
+        reader.read_to_string(&mut line).map_err(read_failed)?;
+        if is_armored(&line) {
+            line = dearmor(&line)?;
+        }
+        if mode == ProcessMode::Decrypt {
+            validate_encrypted_file(&line, opts)?;
+        }
+        let (output, keys) = process_file(&line, password, mode, opts)?;
+        writer.write_all(output.as_bytes()).and_then(|_| writer.flush()).map_err(write_failed)?;
+        return Ok(keys);
+    }
+    
+    // Files with a cipher header are buffered above, so a streamed decrypt is always AES-256
+    let cipher = match mode {
+        ProcessMode::Encrypt => FileCipher::generate(opts.cipher)?,
+        ProcessMode::Decrypt => FileCipher::default(),
+    };
+    if let (ProcessMode::Encrypt, Some(header)) = (mode, cipher.header()) {
+        writer.write_all(header.as_bytes()).map_err(write_failed)?;
+    }
+    let cipher = cipher.build(password)?;
+    let mut lines = LineProcessor::new(&*cipher, mode, opts);
+    
+    let mut held = String::new();
+    let mut validated = mode == ProcessMode::Encrypt;
+    let mut index = 0;
+    while !line.is_empty() {
+        if validated {
+            index = write_lines(&mut lines, index, &line, &mut writer)?;
+        } else {
+            held.push_str(&line);
+            if !opts.is_ignored(line.trim()) && line.contains('=') {
+                validate_encrypted_file(&held, opts)?;
+                validated = true;
+                index = write_lines(&mut lines, index, &held, &mut writer)?;
+                held.clear();
+            }
+        }
+        line.clear();
+        reader.read_line(&mut line).map_err(read_failed)?;
+    }
+    if !validated {
+        // No variable at all: validation fails and nothing has been written
+        validate_encrypted_file(&held, opts)?;
+    }
+    
+    writer.flush().map_err(write_failed)?;
+    lines.finish()
+}
+
+/// Process and write `\n`-terminated lines for `process_reader`, returning the next line index
+fn write_lines<W: Write>(lines: &mut LineProcessor, mut index: usize, text: &str, writer: &mut W) -> EngineResult<usize> {
+    for line in text.split_inclusive('\n') {
+        if let Some(processed) = lines.process(index, line)? {
+            writer.write_all(processed.as_bytes()).map_err(write_failed)?;
+        }
+        index += 1;
+    }
+    Ok(index)
+}
+
+fn read_failed(source: std::io::Error) -> EngineError {
+    EngineError::Io { context: "Cannot read the input".to_string(), source }
+}
+
+fn write_failed(source: std::io::Error) -> EngineError {
+    EngineError::Io { context: "Cannot write the output".to_string(), source }
+}
+
+/// Prepend a `--store-name` header recording the original file name
+pub fn add_name_header(content: &str, name: &str) -> String {
+    format!("{}{}\n{}", NAME_HEADER_PREFIX, name, content)
+}
+
+/// Split off a `--store-name` header: (stored file name, rest of the content)
+/// Only a bare file name is returned: anything with a separator (`/` or `\` on any platform),
+/// `.`/`..` or a control character is ignored so a header can't redirect output
+pub fn split_name_header(content: &str) -> (Option<&str>, &str) {
+    let Some(rest) = content.strip_prefix(NAME_HEADER_PREFIX) else {
+        return (None, content);
+    };
+    
+    let (name, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let name = name.trim_end_matches('\r');
+    let bare = !name.contains(['/', '\\'])
+        && !name.chars().any(char::is_control)
+        && matches!(Path::new(name).components().collect::<Vec<_>>()[..], [Component::Normal(_)]);
+    (bare.then_some(name), body)
+}
+
+/// Split off a `--split` header: ((threshold, count), rest of the content)
+pub fn split_shares_header(content: &str) -> (Option<(u8, u8)>, &str) {
+    let Some(rest) = content.strip_prefix(SHARES_HEADER_PREFIX) else {
+        return (None, content);
+    };
+    
+    let (scheme, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let scheme = scheme
+        .trim_end_matches('\r')
+        .split_once("-of-")
+        .and_then(|(k, n)| Some((k.parse().ok()?, n.parse().ok()?)));
+    (scheme, body)
+}
+
+/// Share scheme of an encrypted file, looking past a `--store-name` header
+pub fn shares_scheme(content: &str) -> Option<(u8, u8)> {
+    split_shares_header(split_name_header(content).1).0
+}
+
+/// Prepend one `encrypt --recipient` header line per wrapped key
+pub fn add_recipient_header(content: &str, wrapped_keys: &[String]) -> String {
+    let header: String = wrapped_keys
+        .iter()
+        .map(|wrapped| format!("{}{}\n", RECIPIENT_HEADER_PREFIX, wrapped))
+        .collect();
+    header + content
+}
+
+/// Split off the `encrypt --recipient` header lines: (wrapped file keys, rest of the content)
+pub fn split_recipient_header(content: &str) -> (Vec<&str>, &str) {
+    let mut wrapped_keys = Vec::new();
+    let mut body = content;
+    while let Some(rest) = body.strip_prefix(RECIPIENT_HEADER_PREFIX) {
+        let (wrapped, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        wrapped_keys.push(wrapped.trim_end_matches('\r'));
+        body = next;
+    }
+    (wrapped_keys, body)
+}
+
+/// Wrapped file keys of an encrypted file, looking past `--store-name` and `--split` headers
+pub fn recipient_keys(content: &str) -> Vec<&str> {
+    split_recipient_header(split_shares_header(split_name_header(content).1).1).0
+}
+
+/// Split off a `.wc-envc-keys` policy header: (policy, rest of the content)
+pub fn split_keys_header(content: &str) -> EngineResult<(Option<KeyPolicy>, &str)> {
+    let Some(rest) = content.strip_prefix(KEYS_HEADER_PREFIX) else {
+        return Ok((None, content));
+    };
+    
+    let (list, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let policy = KeyPolicy::from_header(list.trim_end_matches('\r'))
+        .map_err(|e| EngineError::parse(format!("Malformed encrypted-keys header: {}", e)))?;
+    Ok((Some(policy), body))
+}
+
+/// Cipher of an encrypted file, looking past the headers before its cipher header
+/// A compressed file is always AES-256 and has none
+pub fn file_cipher(content: &str) -> EngineResult<FileCipher> {
+    let body = split_recipient_header(split_shares_header(split_name_header(content).1).1).1;
+    let body = split_keys_header(body).map_err(|e| e.within(content, body))?.1;
+    if is_compressed(body) {
+        return Ok(FileCipher::default());
+    }
+    Ok(split_cipher_header(body).map_err(|e| e.within(content, body))?.0)
+}
+
+/// Key policy of an encrypted file, looking past the name, share and recipient headers
+pub fn key_policy(content: &str) -> Option<KeyPolicy> {
+    let body = split_recipient_header(split_shares_header(split_name_header(content).1).1).1;
+    split_keys_header(body).ok()?.0
+}
+
+/// Check if content starts with a `--hide-keys` mapping line
+pub fn has_hidden_keys(content: &str) -> bool {
+    content.starts_with(HIDDEN_KEYS_PREFIX)
+}
+
+/// Deterministic stand-in for a key name, keyed by the password so names can't be guessed from a wordlist
+fn hidden_key_name(key: &str, password: &SecretString) -> String {
+    let digest = sha256_hex(format!("{}\0{}", password.expose_secret(), key).as_bytes());
+    format!("K_{}", digest[..16].to_uppercase())
+}
+
+/// Replace key names with hidden names and prepend the encrypted mapping line
+fn hide_keys(
+    content: &str,
+    password: &SecretString,
+    cipher: &dyn ValueCipher,
+    opts: &ProcessOptions,
+) -> String {
+    let mut body = String::with_capacity(content.len());
+    let mut mapping: Vec<String> = Vec::new();
+    
+    for raw_line in lines_inclusive(content) {
+        match raw_line.split_once('=') {
+            Some((key_part, rest)) if !opts.is_ignored(raw_line.trim()) && !key_part.trim().is_empty() => {
+                let key = key_part.trim();
+                let hidden = hidden_key_name(key, password);
+                let entry = format!("{}={}", hidden, key);
+                if !mapping.contains(&entry) {
+                    mapping.push(entry);
+                }
+                
+                body.push_str(&key_part.replacen(key, &hidden, 1));
+                body.push('=');
+                body.push_str(rest);
+            }
+            _ => body.push_str(raw_line),
+        }
+    }
+    
+    format!("{}{}\n{}", HIDDEN_KEYS_PREFIX, cipher.encrypt(&mapping.join("\n")), body)
+}
+
+/// Restore key names hidden by `--hide-keys`, dropping the mapping line
+/// Content without a mapping line is returned unchanged
+pub fn reveal_keys(
+    content: &str,
+    cipher: &dyn ValueCipher,
+    opts: &ProcessOptions,
+) -> EngineResult<String> {
+    if !has_hidden_keys(content) {
+        return Ok(content.to_string());
+    }
+    
+    let (first, body) = content.split_once('\n').unwrap_or((content, ""));
+    let decrypted = cipher.decrypt(&first[HIDDEN_KEYS_PREFIX.len()..])
+        .map_err(|e| EngineError::AtLine { line: 1, source: Box::new(e.into()) })?;
+    let mapping: HashMap<&str, &str> = decrypted.lines().filter_map(|l| l.split_once('=')).collect();
+    
+    let mut output = String::with_capacity(body.len());
+    for raw_line in lines_inclusive(body) {
+        match raw_line.split_once('=') {
+            Some((key_part, rest)) if !opts.is_ignored(raw_line.trim()) && !key_part.trim().is_empty() => {
+                let hidden = key_part.trim();
+                let key = mapping
+                    .get(hidden)
+                    .ok_or_else(|| EngineError::Corrupt(format!("Key '{}' is not in the hidden key mapping", hidden)))?;
+                
+                output.push_str(&key_part.replacen(hidden, key, 1));
+                output.push('=');
+                output.push_str(rest);
+            }
+            _ => output.push_str(raw_line),
+        }
+    }
+    
+    Ok(output)
+}
+
+/// Validate that file content appears to be encrypted
+/// Checks if values look like Base64
+pub fn validate_encrypted_file(content: &str, opts: &ProcessOptions) -> EngineResult<()> {
+    let content = split_recipient_header(split_shares_header(split_name_header(content).1).1).1;
+    
+    // A compressed blob has no per-value structure to check, and other tools' formats are their own
+    if is_compressed(content) || find_decoder(content).is_some() {
+        return Ok(());
+    }
+    
+    let mut has_variables = false;
+    let mut encrypted_count = 0;
+    let mut plain_keys = Vec::new();
+    let policy = key_policy(content);
+    
+    for (index, line) in lines(content).enumerate() {
+        let trimmed = line.trim();
+        if opts.is_ignored(trimmed) {
+            continue;
+        }
+        
+        if let Some(eq_pos) = line.find('=') {
+            has_variables = true;
+            let value = &line[eq_pos + 1..];
+            if policy.as_ref().is_some_and(|policy| !policy.matches(line[..eq_pos].trim())) {
+                continue;
+            }
+            
+            if is_likely_encrypted(value) {
+                encrypted_count += 1;
+            } else {
+                plain_keys.push(format!("{} (line {})", line[..eq_pos].trim(), index + 1));
+            }
+        }
+    }
+    
+    if !has_variables {
+        return Err(EngineError::Invalid("File contains no environment variables".to_string()));
+    }
+    
+    if encrypted_count == 0 && !plain_keys.is_empty() {
+        return Err(EngineError::Invalid("This file appears to be unencrypted".to_string()));
+    }
+    
+    if opts.fail_on_plaintext && !plain_keys.is_empty() {
+        return Err(EngineError::Invalid(format!(
+            "Found {} unencrypted value(s): {}",
+            plain_keys.len(),
+            plain_keys.join(", ")
+        )));
+    }
+    
+    let total = encrypted_count + plain_keys.len();
+    if (encrypted_count as f64) < opts.min_encrypted * total as f64 {
+        return Err(EngineError::Invalid(format!(
+            "Only {}/{} values look encrypted, below --min-encrypted {}: {}",
+            encrypted_count, total, opts.min_encrypted, plain_keys.join(", ")
+        )));
+    }
+    
+    Ok(())
+}
+
+/// Decrypt freshly encrypted content in memory and check it reproduces the source
+/// Catches cipher/key misconfiguration before a broken file is written
+pub fn verify_roundtrip(
+    source: &str,
+    encrypted: &str,
+    password: &SecretString,
+    opts: &ProcessOptions,
+) -> Result<()> {
+    let expected = if opts.dedupe {
+        dedupe_keys(source, opts)
+    } else {
+        source.to_string()
+    };
+    let expected = if opts.normalize { normalize(&expected, opts) } else { expected };
+    let expected = if opts.trim_keys { trim_keys(&expected, opts) } else { expected };
+    let expected = opts.final_newline.apply(&expected);
+    let (decrypted, _) = process_file(encrypted, password, ProcessMode::Decrypt, opts)
+        .map_err(|e| anyhow::anyhow!("Verification failed: {}", e))?;
+    
+    // Inlined files come back as `@path` like the source, and must match the files they were read from
+    let (decrypted, inlined) = take_inline_files(&decrypted, opts)
+        .map_err(|e| anyhow::anyhow!("Verification failed: {}", e))?;
+    if let Some(base) = &opts.inline_files {
+        if let Some(file) = inlined.iter().find(|file| std::fs::read(base.join(&file.path)).ok().as_ref() != Some(&file.bytes)) {
+            anyhow::bail!("Verification failed: inlined file '{}' differs from the original", file.path.display());
+        }
+    }
+    
+    let mismatch = lines(&expected)
+        .zip(lines(&decrypted))
+        .position(|(a, b)| a != b)
+        .or_else(|| (lines(&expected).count() != lines(&decrypted).count()).then_some(0));
+    
+    if let Some(index) = mismatch {
+        anyhow::bail!(
+            "Verification failed: decrypted output differs from the input (line {})",
+            index + 1
+        );
+    }
+    
+    Ok(())
+}
+
+/// Check whether decrypted content still looks encrypted
+/// True when more than half of the values still look like Base64 ciphertext,
+/// which usually means the wrong password or format produced garbage
+pub fn looks_still_encrypted(content: &str, opts: &ProcessOptions) -> bool {
+    let mut total = 0;
+    let mut suspicious = 0;
+    
+    for line in lines(content) {
+        let trimmed = line.trim();
+        if opts.is_ignored(trimmed) {
+            continue;
+        }
+        
+        if let Some(eq_pos) = line.find('=') {
+            total += 1;
+            if is_likely_encrypted(&line[eq_pos + 1..]) {
+                suspicious += 1;
+            }
+        }
+    }
+    
+    suspicious > 0 && suspicious * 2 > total
+}
+
+/// Wrap encrypted file content in a copy-paste safe armored text block
+pub fn armor(content: &str) -> String {
+    let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, content);
+    
+    let mut armored = String::from(ARMOR_BEGIN);
+    armored.push('\n');
+    armored.push_str(&wrap_lines(&encoded));
+    armored.push_str(ARMOR_END);
+    armored.push('\n');
+    armored
+}
+
+/// Wrap Base64 text at `ARMOR_LINE_WIDTH`, each line ending with a newline
+fn wrap_lines(encoded: &str) -> String {
+    let mut wrapped = String::new();
+    for chunk in encoded.as_bytes().chunks(ARMOR_LINE_WIDTH) {
+        wrapped.push_str(&String::from_utf8_lossy(chunk));
+        wrapped.push('\n');
+    }
+    wrapped
+}
+
+/// Check if content is a compressed single-blob file
+pub fn is_compressed(content: &str) -> bool {
+    content.lines().next().is_some_and(|line| line.trim() == COMPRESSED_HEADER)
+}
+
+/// Gzip the plaintext and encrypt it as one wrapped Base64 blob under the compressed header
+#[cfg(feature = "compress")]
+fn encrypt_compressed(content: &str, password: &SecretString) -> EngineResult<String> {
+    use std::io::Write;
+    
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    let compressed = encoder
+        .write_all(content.as_bytes())
+        .and_then(|_| encoder.finish())
+        .map_err(|e| EngineError::Io { context: "Cannot compress".to_string(), source: e })?;
+    let encrypted = timed_derivation(|| derive_key(password)).encrypt_bytes_to_base64(&compressed);
+    
+    Ok(format!("{}\n{}", COMPRESSED_HEADER, wrap_lines(&encrypted)))
+}
+
+/// Decrypt and gunzip a compressed single-blob file
+#[cfg(feature = "compress")]
+fn decrypt_compressed(content: &str, password: &SecretString) -> EngineResult<String> {
+    use std::io::Read;
+    
+    let encoded: String = content.lines().skip(1).flat_map(str::split_whitespace).collect();
+    let compressed = timed_derivation(|| derive_key(password))
+        .decrypt_base64_to_bytes(&encoded)
+        .map_err(|_| DecryptError::Invalid)?;
+    
+    let mut decompressed = String::new();
+    flate2::read::GzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut decompressed)
+        .map_err(|e| EngineError::Corrupt(format!("Corrupt compressed data: {}", e)))?;
+    
+    Ok(decompressed)
+}
+
+/// Compression needs the optional `compress` feature
+#[cfg(not(feature = "compress"))]
+fn encrypt_compressed(_content: &str, _password: &SecretString) -> EngineResult<String> {
+    Err(EngineError::UnsupportedVersion("--compress requires building with `--features compress`".to_string()))
+}
+
+/// Compressed files need the optional `compress` feature
+#[cfg(not(feature = "compress"))]
+fn decrypt_compressed(_content: &str, _password: &SecretString) -> EngineResult<String> {
+    Err(EngineError::UnsupportedVersion(
+        "This file is compressed; rebuild with `--features compress` to decrypt it".to_string(),
+    ))
+}
+
+/// Check if content is an armored text block
+pub fn is_armored(content: &str) -> bool {
+    content.trim_start().starts_with(ARMOR_BEGIN)
+}
+
+/// Unwrap an armored text block back to encrypted file content
+/// Errors point at the line the block begins on
+pub fn dearmor(content: &str) -> EngineResult<String> {
+    let block = content.trim_start();
+    let malformed = |message: &str| EngineError::parse(message).within(content, block);
+    let body = block
+        .trim_end()
+        .strip_prefix(ARMOR_BEGIN)
+        .and_then(|rest| rest.strip_suffix(ARMOR_END))
+        .ok_or_else(|| malformed("Malformed armored block"))?;
+    
+    let encoded: String = body.split_whitespace().collect();
+    let decoded = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded)
+        .map_err(|_| malformed("Malformed armored block: invalid Base64"))?;
+    
+    String::from_utf8(decoded).map_err(|_| malformed("Malformed armored block: invalid UTF-8"))
+}
+
+/// Compute SHA-256 of data as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Format a checksum line compatible with `sha256sum -c`
+pub fn checksum_line(data: &[u8], filename: &str) -> String {
+    format!("{}  {}\n", sha256_hex(data), filename)
+}
+
+/// Verify data against a `sha256sum`-style checksum line
+pub fn verify_checksum(data: &[u8], checksum: &str) -> Result<()> {
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Checksum file is empty"))?;
+    
+    if !expected.eq_ignore_ascii_case(&sha256_hex(data)) {
+        anyhow::bail!("Checksum mismatch: file may have been tampered with");
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let original = "secret_value_123";
+        let password = SecretString::new("test_password".to_string());
+        
+        let encrypted = derive_key(&password).encrypt(original);
+        let decrypted = derive_key(&password).decrypt(&encrypted).unwrap();
         
         assert_eq!(original, decrypted);
     }
@@ -146,10 +1733,10 @@ mod tests {
         let correct_pwd = SecretString::new("correct_password".to_string());
         let wrong_pwd = SecretString::new("wrong_password".to_string());
         
-        let encrypted = encrypt_value("secret", &correct_pwd);
-        let result = decrypt_value(&encrypted, &wrong_pwd);
+        let encrypted = derive_key(&correct_pwd).encrypt("secret");
+        let result = derive_key(&wrong_pwd).decrypt(&encrypted);
         
-        assert!(result.is_err());
+        assert_eq!(result, Err(DecryptError::Invalid));
     }
     
     #[test]
@@ -157,11 +1744,687 @@ mod tests {
         let content = "# Comment\nDB_HOST=localhost\nDB_PASS=secret\n";
         let password = SecretString::new("test".to_string());
         
-        let (result, keys) = process_file(content, &password, ProcessMode::Encrypt).unwrap();
+        let (result, keys) = process_file(content, &password, ProcessMode::Encrypt, &ProcessOptions::default()).unwrap();
         
         assert!(result.contains("# Comment"));
         assert!(result.contains("DB_HOST="));
         assert!(!result.contains("localhost")); // Should be encrypted
         assert_eq!(keys, vec!["DB_HOST", "DB_PASS"]);
     }
+    
+    #[test]
+    fn test_mask_value() {
+        assert_eq!(mask_value("1234"), "****");
+        assert_eq!(mask_value("abc"), "****");
+        assert_eq!(mask_value("my_long_secret_token"), "****");
+        assert_eq!(mask_value(""), "");
+    }
+    
+    #[test]
+    fn test_checksum_match() {
+        let data = b"DB_HOST=abc123==";
+        let line = checksum_line(data, ".env.enc");
+        
+        assert!(line.ends_with("  .env.enc\n"));
+        assert!(verify_checksum(data, &line).is_ok());
+    }
+    
+    #[test]
+    fn test_checksum_mismatch() {
+        let line = checksum_line(b"DB_HOST=abc123==", ".env.enc");
+        let result = verify_checksum(b"DB_HOST=tampered", &line);
+        
+        assert!(result.unwrap_err().to_string().contains("Checksum mismatch"));
+    }
+    
+    #[test]
+    fn test_looks_still_encrypted() {
+        let gibberish = "DB_HOST=U2FsdGVkX19hYmNkZWZn\nDB_PASS=c2VjcmV0X3ZhbHVlXzEyMw==\nPORT=5432";
+        assert!(looks_still_encrypted(gibberish, &ProcessOptions::default()));
+        
+        let plain = "DB_HOST=localhost\nDB_PASS=hunter2!\nPORT=5432";
+        assert!(!looks_still_encrypted(plain, &ProcessOptions::default()));
+    }
+    
+    #[test]
+    fn test_custom_comment_prefixes_roundtrip() {
+        let content = "; old_timeout=30\n// OLD_URL=http://example.com\nDB_HOST=localhost";
+        let password = SecretString::new("test".to_string());
+        let opts = ProcessOptions::with_comment_prefixes(&[";".to_string(), "//".to_string()]);
+        
+        let (encrypted, keys) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(keys, vec!["DB_HOST"]);
+        assert!(encrypted.starts_with("; old_timeout=30\n// OLD_URL=http://example.com\n"));
+        
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, content);
+    }
+    
+    #[test]
+    fn test_fail_on_plaintext() {
+        let password = SecretString::new("test".to_string());
+        let content = format!(
+            "DB_HOST={}\nDB_PASS=hunter2\nAPI_KEY=plain-key",
+            derive_key(&password).encrypt("localhost")
+        );
+        
+        assert!(validate_encrypted_file(&content, &ProcessOptions::default()).is_ok());
+        
+        let strict = ProcessOptions { fail_on_plaintext: true, ..Default::default() };
+        let err = validate_encrypted_file(&content, &strict).unwrap_err().to_string();
+        assert!(err.contains("DB_PASS (line 2), API_KEY (line 3)"));
+    }
+    
+    #[test]
+    fn test_min_encrypted_ratio() {
+        let password = SecretString::new("test".to_string());
+        let content = format!(
+            "DB_HOST={}\nDB_USER={}\nDB_PASS={}\nAPI_KEY=plain-key",
+            derive_key(&password).encrypt("localhost"),
+            derive_key(&password).encrypt("admin"),
+            derive_key(&password).encrypt("hunter2"),
+        );
+        
+        // 3 of 4 values (0.75) look encrypted
+        for (min_encrypted, ok) in [(0.0, true), (0.5, true), (0.75, true), (0.9, false), (1.0, false)] {
+            let opts = ProcessOptions { min_encrypted, ..Default::default() };
+            assert_eq!(validate_encrypted_file(&content, &opts).is_ok(), ok, "min {}", min_encrypted);
+        }
+        
+        let opts = ProcessOptions { min_encrypted: 0.9, ..Default::default() };
+        let err = validate_encrypted_file(&content, &opts).unwrap_err().to_string();
+        assert!(err.contains("3/4") && err.contains("API_KEY (line 4)"));
+    }
+    
+    #[test]
+    fn test_armor_roundtrip() {
+        let content = "# Comment\nDB_HOST=localhost\nDB_PASS=secret";
+        let password = SecretString::new("test".to_string());
+        let opts = ProcessOptions::default();
+        
+        let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        let armored = armor(&encrypted);
+        
+        assert!(armored.starts_with("-----BEGIN WC-ENVC-----\n"));
+        assert!(armored.ends_with("-----END WC-ENVC-----\n"));
+        assert!(armored.lines().all(|l| l.len() <= ARMOR_LINE_WIDTH || l.starts_with("-----")));
+        assert!(is_armored(&armored));
+        
+        let unwrapped = dearmor(&armored).unwrap();
+        let (decrypted, _) = process_file(&unwrapped, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, content);
+    }
+    
+    #[test]
+    fn test_invalid_key_names() {
+        assert!(is_valid_key("DB_HOST"));
+        assert!(is_valid_key("_private1"));
+        assert!(!is_valid_key("MY KEY"));
+        assert!(!is_valid_key("1ABC"));
+        assert!(!is_valid_key(""));
+        
+        let content = "# Comment\nGOOD=1\nMY KEY=2\n1ABC=3";
+        let invalid = validate_key_names(content, &ProcessOptions::default()).unwrap();
+        assert_eq!(invalid, vec![(3, "MY KEY".to_string()), (4, "1ABC".to_string())]);
+        
+        let strict = ProcessOptions { strict: true, ..Default::default() };
+        let err = validate_key_names(content, &strict).unwrap_err().to_string();
+        assert!(err.contains("line 3: 'MY KEY'"));
+        assert!(err.contains("line 4: '1ABC'"));
+    }
+    
+    #[test]
+    fn test_decrypt_error_reports_line_number() {
+        let password = SecretString::new("test".to_string());
+        let content = format!(
+            "# Comment\nDB_HOST={}\n\nDB_PASS=not-encrypted",
+            derive_key(&password).encrypt("localhost")
+        );
+        
+        let err = process_file(&content, &password, ProcessMode::Decrypt, &ProcessOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("line 4: "));
+    }
+    
+    #[test]
+    fn test_duplicate_keys() {
+        let content = "DB_HOST=a\n# DB_HOST=comment\nDB_PASS=x\nDB_HOST=b";
+        let opts = ProcessOptions::default();
+        
+        assert_eq!(find_duplicate_keys(content, &opts), vec![("DB_HOST".to_string(), vec![1, 4])]);
+        assert_eq!(dedupe_keys(content, &opts), "# DB_HOST=comment\nDB_PASS=x\nDB_HOST=b");
+        
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { dedupe: true, ..Default::default() };
+        let (encrypted, keys) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(keys, vec!["DB_PASS", "DB_HOST"]);
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert!(decrypted.ends_with("DB_HOST=b"));
+    }
+    
+    #[test]
+    fn test_normalize_messy_file() {
+        let messy = "\r\n# database  \r\nDB_HOST=\"localhost\" \t\r\n\r\n\r\n\r\nDB_PASS=secret # note \r\n\r\n";
+        let opts = ProcessOptions { normalize: true, ..Default::default() };
+        assert_eq!(normalize(messy, &opts), "# database\nDB_HOST=\"localhost\"\n\nDB_PASS=secret # note\n");
+        assert_eq!(normalize("A=1", &opts), "A=1\n");
+        assert_eq!(normalize("", &opts), "");
+        
+        let password = SecretString::new("secret".to_string());
+        let (encrypted, _) = process_file(messy, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert!(!encrypted.contains('\r') && !encrypted.contains("\n\n\n"));
+        verify_roundtrip(messy, &encrypted, &password, &opts).unwrap();
+        
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, normalize(messy, &opts));
+        
+        // Trailing whitespace of an unquoted value may be part of it and is kept
+        assert_eq!(normalize("PAD=x  \r\n", &opts), "PAD=x  \n");
+        let untrimmed = ProcessOptions { trim: false, ..opts.clone() };
+        let (encrypted, _) = process_file("PAD=x  \n", &password, ProcessMode::Encrypt, &untrimmed).unwrap();
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, "PAD=x  \n");
+    }
+    
+    #[test]
+    fn test_key_derivation_time() {
+        let password = SecretString::new("secret".to_string());
+        let (_, idle) = with_key_derivation_time(|| lines("A=1\n").count());
+        assert_eq!(idle, Duration::ZERO);
+        
+        let opts = ProcessOptions::default();
+        let (encrypted, elapsed) = with_key_derivation_time(|| process_file("A=1\n", &password, ProcessMode::Encrypt, &opts));
+        assert!(encrypted.is_ok());
+        assert!(elapsed > Duration::ZERO);
+    }
+    
+    #[test]
+    fn test_verify_roundtrip() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions::default();
+        let source = "# comment\nDB_HOST=localhost\nDB_PASS=secret\n";
+        let (encrypted, _) = process_file(source, &password, ProcessMode::Encrypt, &opts).unwrap();
+        
+        assert!(verify_roundtrip(source, &encrypted, &password, &opts).is_ok());
+        
+        // Simulate a broken round trip: ciphertext that decrypts to a different value
+        let broken = encrypted.replace(
+            encrypted.lines().nth(2).unwrap(),
+            &format!("DB_PASS={}", derive_key(&password).encrypt("truncated")),
+        );
+        let err = verify_roundtrip(source, &broken, &password, &opts).unwrap_err();
+        assert!(err.to_string().contains("line 3"));
+    }
+    
+    #[test]
+    fn test_trim_policy() {
+        let password = SecretString::new("secret".to_string());
+        let content = "GREETING=  hello world \t";
+        
+        // Trimmed: surrounding whitespace stays outside the ciphertext
+        let trimmed = ProcessOptions { trim_keys: false, ..Default::default() };
+        let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &trimmed).unwrap();
+        assert!(encrypted.starts_with("GREETING=  ") && encrypted.ends_with(" \t"));
+        let ciphertext = encrypted["GREETING=".len()..].trim();
+        assert_eq!(derive_key(&password).decrypt(ciphertext).unwrap(), "hello world");
+        
+        let exact = ProcessOptions { trim: false, trim_keys: false, ..Default::default() };
+        let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &exact).unwrap();
+        let ciphertext = &encrypted["GREETING=".len()..];
+        assert_eq!(derive_key(&password).decrypt(ciphertext).unwrap(), "  hello world \t");
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &exact).unwrap();
+        assert_eq!(decrypted, content);
+    }
+    
+    #[test]
+    fn test_non_value_bytes_preserved() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { trim_keys: false, ..Default::default() };
+        let content = "  DB_HOST = localhost  \r\n# comment\r\n\nexport DB_PASS=secret\n";
+        
+        let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        let lines: Vec<&str> = encrypted.split_inclusive('\n').collect();
+        assert!(lines[0].starts_with("  DB_HOST = ") && lines[0].ends_with("  \r\n"));
+        assert_eq!(lines[1], "# comment\r\n");
+        assert_eq!(lines[2], "\n");
+        assert!(lines[3].starts_with("export DB_PASS=") && lines[3].ends_with('\n'));
+        
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, content);
+    }
+    
+    #[test]
+    fn test_trim_keys() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions::default();
+        let content = "  KEY = value\n\tOTHER\t=x \n";
+        
+        let (encrypted, keys) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        let lines: Vec<&str> = encrypted.lines().collect();
+        assert_eq!(lines[0], format!("KEY={}", derive_key(&password).encrypt("value")));
+        assert!(lines[1].starts_with("OTHER=") && lines[1].ends_with(' '));
+        assert_eq!(keys, ["KEY", "OTHER"]);
+        
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, "KEY=value\nOTHER=x \n");
+        assert_eq!(trim_keys(content, &opts), decrypted);
+        assert!(verify_roundtrip(content, &encrypted, &password, &opts).is_ok());
+    }
+    
+    #[test]
+    fn test_final_newline_policies() {
+        let password = SecretString::new("secret".to_string());
+        let cases = [
+            (FinalNewline::Preserve, "A=1\nB=2\n", "A=1\nB=2\n"),
+            (FinalNewline::Preserve, "A=1\nB=2", "A=1\nB=2"),
+            (FinalNewline::Always, "A=1\nB=2\n", "A=1\nB=2\n"),
+            (FinalNewline::Always, "A=1\r\nB=2", "A=1\r\nB=2\r\n"),
+            (FinalNewline::Never, "A=1\nB=2\n\n", "A=1\nB=2"),
+            (FinalNewline::Never, "A=1\nB=2", "A=1\nB=2"),
+        ];
+        for (final_newline, content, expected) in cases {
+            let opts = ProcessOptions { final_newline, ..Default::default() };
+            let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+            assert_eq!(encrypted.ends_with('\n'), expected.ends_with('\n'), "{:?} {:?}", final_newline, content);
+            assert!(verify_roundtrip(content, &encrypted, &password, &opts).is_ok());
+            
+            // Applied on encrypt it carries through a plain decrypt; applied on decrypt it shapes the output
+            let preserve = ProcessOptions::default();
+            let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &preserve).unwrap();
+            assert_eq!(decrypted, expected);
+            let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &preserve).unwrap();
+            let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+            assert_eq!(decrypted, expected);
+        }
+        assert_eq!(FinalNewline::Always.apply(""), "");
+    }
+    
+    #[test]
+    fn test_separator_preserved() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { trim_keys: false, ..Default::default() };
+        
+        let cases = [("KEY = value\n", "KEY = "), ("KEY=value\n", "KEY="), ("KEY =\tvalue\n", "KEY =\t")];
+        for (content, separator) in cases {
+            let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+            let ciphertext = encrypted.strip_prefix(separator).unwrap().trim_end();
+            assert_eq!(derive_key(&password).decrypt(ciphertext).unwrap(), "value");
+            
+            let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+            assert_eq!(decrypted, content);
+        }
+    }
+    
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_compressed_roundtrip() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { compress: true, ..Default::default() };
+        let content = "# certs\nDB_HOST=localhost\nCERT=\"-----BEGIN CERTIFICATE-----\"\n";
+        
+        let (encrypted, keys) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert!(is_compressed(&encrypted));
+        assert!(!encrypted.contains("DB_HOST"));
+        assert_eq!(keys, vec!["DB_HOST", "CERT"]);
+        assert!(validate_encrypted_file(&encrypted, &opts).is_ok());
+        
+        let plain_opts = ProcessOptions::default();
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &plain_opts).unwrap();
+        assert_eq!(decrypted, content);
+    }
+    
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn test_chacha20_roundtrip() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { cipher: Cipher::Chacha20Poly1305, hide_keys: true, ..Default::default() };
+        let content = "# database\nDB_HOST=localhost\nDB_PASS=\"p@ss word\"\n";
+        
+        let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert!(encrypted.starts_with("# wc-envc: cipher=chacha20-poly1305 kdf=argon2id salt="));
+        assert!(!encrypted.contains("DB_HOST") && !encrypted.contains("localhost"));
+        assert!(validate_encrypted_file(&encrypted, &opts).is_ok());
+        
+        // Decryption follows the header, not the options
+        let plain_opts = ProcessOptions::default();
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &plain_opts).unwrap();
+        assert_eq!(decrypted, content);
+        
+        let wrong = SecretString::new("wrong".to_string());
+        assert!(process_file(&encrypted, &wrong, ProcessMode::Decrypt, &plain_opts).is_err());
+        
+        // Every file gets its own salt, and so its own key
+        let (again, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        let (first, _) = split_cipher_header(&encrypted).unwrap();
+        let (second, _) = split_cipher_header(&again).unwrap();
+        assert_eq!(first.salt.as_ref().map(Vec::len), Some(SALT_LEN));
+        assert_ne!(first.salt, second.salt);
+        
+        // Files from before salts still decrypt with the unsalted key
+        let legacy = FileCipher { cipher: Cipher::Chacha20Poly1305, salt: None };
+        let old = format!("# wc-envc: cipher=chacha20-poly1305\nA={}\n", legacy.build(&password).unwrap().encrypt("1"));
+        assert_eq!(process_file(&old, &password, ProcessMode::Decrypt, &plain_opts).unwrap().0, "A=1\n");
+        
+        let unsalted = encrypted.replacen(" salt=", " pepper=", 1);
+        assert!(process_file(&unsalted, &password, ProcessMode::Decrypt, &plain_opts).is_err());
+    }
+    
+    #[cfg(feature = "chacha20")]
+    #[test]
+    fn test_chacha20_detects_tampering() {
+        let password = SecretString::new("secret".to_string());
+        let cipher = FileCipher { cipher: Cipher::Chacha20Poly1305, salt: None }.build(&password).unwrap();
+        let encrypted = cipher.encrypt("localhost");
+        
+        let mut bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &encrypted).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        let flipped = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
+        assert_eq!(cipher.decrypt(&flipped), Err(DecryptError::AuthError));
+        
+        // The CLI reports the distinct message rather than the generic one
+        let content = format!("# wc-envc: cipher=chacha20-poly1305\nDB_HOST={}\n", flipped);
+        let err = process_file(&content, &password, ProcessMode::Decrypt, &ProcessOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Authentication failed"));
+    }
+    
+    #[test]
+    fn test_aggregate_failures() {
+        let password = SecretString::new("secret".to_string());
+        let other = SecretString::new("other".to_string());
+        let content = format!(
+            "DB_HOST={}\nDB_PASS={}\n# comment\nAPI_KEY={}\n",
+            derive_key(&password).encrypt("localhost"),
+            derive_key(&other).encrypt("hunter2"),
+            derive_key(&other).encrypt("abc"),
+        );
+        
+        let fail_fast = ProcessOptions::default();
+        let err = process_file(&content, &password, ProcessMode::Decrypt, &fail_fast).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: Wrong password or invalid encrypted data");
+        
+        let all = ProcessOptions { fail_fast: false, ..Default::default() };
+        let err = process_file(&content, &password, ProcessMode::Decrypt, &all).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 value(s) failed:\n  line 2 (DB_PASS): Wrong password or invalid encrypted data\n  \
+             line 4 (API_KEY): Wrong password or invalid encrypted data"
+        );
+    }
+    
+    #[test]
+    fn test_name_header_rejects_paths() {
+        assert_eq!(split_name_header("# wc-envc: name=.env.local\nA=1\n"), (Some(".env.local"), "A=1\n"));
+        assert_eq!(split_name_header("# wc-envc: name=../.bashrc\nA=1\n"), (None, "A=1\n"));
+        assert_eq!(split_name_header("# wc-envc: name=/etc/passwd\nA=1\n"), (None, "A=1\n"));
+        assert_eq!(split_name_header("# wc-envc: name=..\\.bashrc\nA=1\n"), (None, "A=1\n"));
+        assert_eq!(split_name_header("# wc-envc: name=config/\nA=1\n"), (None, "A=1\n"));
+        assert_eq!(split_name_header("# wc-envc: name=..\nA=1\n"), (None, "A=1\n"));
+        assert_eq!(split_name_header("A=1\n"), (None, "A=1\n"));
+    }
+    
+    #[test]
+    fn test_process_reader_large_file() {
+        let password = SecretString::new("stream".to_string());
+        let opts = ProcessOptions::default();
+        let plain: String = (0..20_000)
+            .map(|i| if i % 100 == 0 { format!("# section {}\r\n", i) } else { format!("KEY_{}=value {}\n", i, i) })
+            .collect();
+        
+        let mut encrypted = Vec::new();
+        let keys = process_reader(plain.as_bytes(), &mut encrypted, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(keys.len(), 19_800);
+        let encrypted = String::from_utf8(encrypted).unwrap();
+        assert_eq!(encrypted, process_file(&plain, &password, ProcessMode::Encrypt, &opts).unwrap().0);
+        
+        let mut decrypted = Vec::new();
+        process_reader(encrypted.as_bytes(), &mut decrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), plain);
+        
+        // Headers and armor fall back to buffering the whole input
+        let armored = armor(&encrypted);
+        let mut decrypted = Vec::new();
+        process_reader(armored.as_bytes(), &mut decrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), plain);
+    }
+    
+    #[test]
+    fn test_process_reader_writes_nothing_on_failure() {
+        let password = SecretString::new("stream".to_string());
+        let opts = ProcessOptions::default();
+        let (encrypted, _) = process_file("A=1\nB=2\n", &password, ProcessMode::Encrypt, &opts).unwrap();
+        let broken = format!("{}C=bm90LWEtdmFsaWQtdmFsdWU=\n", encrypted);
+        
+        // --fail-fast=false needs every value checked before writing, so it buffers
+        let lenient = ProcessOptions { fail_fast: false, ..Default::default() };
+        assert!(lenient.needs_whole_file(ProcessMode::Decrypt));
+        let mut output = Vec::new();
+        assert!(process_reader(broken.as_bytes(), &mut output, &password, ProcessMode::Decrypt, &lenient).is_err());
+        assert!(output.is_empty());
+        
+        // Validation runs before the first line is written
+        let mut output = Vec::new();
+        let err = process_reader("# note\nA=plain\n".as_bytes(), &mut output, &password, ProcessMode::Decrypt, &opts);
+        assert_eq!(err.unwrap_err().to_string(), "This file appears to be unencrypted");
+        assert!(output.is_empty());
+        let err = process_reader("# only a comment\n".as_bytes(), &mut output, &password, ProcessMode::Decrypt, &opts);
+        assert_eq!(err.unwrap_err().to_string(), "File contains no environment variables");
+        assert!(output.is_empty());
+    }
+    
+    #[test]
+    fn test_classic_mac_line_endings() {
+        let password = SecretString::new("pw".to_string());
+        let opts = ProcessOptions::default();
+        let content = "# old Mac\rDB_HOST=localhost\rDB_PASS=secret\r";
+        
+        let (encrypted, keys) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(keys, vec!["DB_HOST", "DB_PASS"]);
+        assert!(!encrypted.contains('\n'));
+        assert_eq!(encrypted.matches('\r').count(), 3);
+        validate_encrypted_file(&encrypted, &opts).unwrap();
+        
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, content);
+        
+        // Headers written by wc-envc end in `\n` while the body keeps its `\r` endings
+        let hidden_keys = ProcessOptions { hide_keys: true, ..Default::default() };
+        let (hidden, _) = process_file(content, &password, ProcessMode::Encrypt, &hidden_keys).unwrap();
+        let (revealed, _) = process_file(&hidden, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(revealed, content);
+        let split: Vec<_> = lines("# wc-envc: shares=2-of-3\nA=1\rB=2\r").collect();
+        assert_eq!(split, vec!["# wc-envc: shares=2-of-3", "A=1", "B=2"]);
+    }
+    
+    #[test]
+    fn test_shares_header_roundtrip() {
+        let password = SecretString::new("generated".to_string());
+        let opts = ProcessOptions { shares: Some((2, 3)), ..Default::default() };
+        let (encrypted, _) = process_file("A=1\n", &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert!(encrypted.starts_with("# wc-envc: shares=2-of-3\n"));
+        
+        let named = add_name_header(&encrypted, ".env");
+        assert_eq!(shares_scheme(&named), Some((2, 3)));
+        assert_eq!(shares_scheme("A=1\n"), None);
+        
+        let (decrypted, _) = process_file(&named, &password, ProcessMode::Decrypt, &ProcessOptions::default()).unwrap();
+        assert_eq!(decrypted, "A=1\n");
+        validate_encrypted_file(&named, &ProcessOptions::default()).unwrap();
+    }
+    
+    #[test]
+    fn test_cipher_header_legacy_files() {
+        let password = SecretString::new("secret".to_string());
+        let legacy = format!("DB_HOST={}\n", derive_key(&password).encrypt("localhost"));
+        
+        // Files without a header are AES-256, whatever cipher is selected for encrypting
+        let opts = ProcessOptions { cipher: Cipher::Chacha20Poly1305, ..Default::default() };
+        let (decrypted, _) = process_file(&legacy, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, "DB_HOST=localhost\n");
+        
+        let unknown = format!("# wc-envc: cipher=rot13\n{}", legacy);
+        let err = process_file(&unknown, &password, ProcessMode::Decrypt, &opts).unwrap_err();
+        assert!(err.to_string().contains("Unknown cipher 'rot13'"));
+    }
+    
+    #[test]
+    fn test_hidden_keys_roundtrip() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { hide_keys: true, ..Default::default() };
+        let content = "# database\nSTRIPE_SECRET_KEY=sk_live_123\nexport DB_HOST = localhost\nSTRIPE_SECRET_KEY=sk_live_456";
+        
+        let (encrypted, keys) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(keys, vec!["STRIPE_SECRET_KEY", "export DB_HOST", "STRIPE_SECRET_KEY"]);
+        assert!(has_hidden_keys(&encrypted));
+        assert!(!encrypted.contains("STRIPE") && !encrypted.contains("DB_HOST"));
+        assert!(encrypted.contains("# database\n"));
+        assert!(validate_encrypted_file(&encrypted, &opts).is_ok());
+        
+        let plain_opts = ProcessOptions::default();
+        let (decrypted, keys) = process_file(&encrypted, &password, ProcessMode::Decrypt, &plain_opts).unwrap();
+        assert_eq!(decrypted, content.replace("DB_HOST = ", "DB_HOST="));
+        assert_eq!(keys, vec!["STRIPE_SECRET_KEY", "export DB_HOST", "STRIPE_SECRET_KEY"]);
+        
+        let wrong = SecretString::new("wrong".to_string());
+        assert!(process_file(&encrypted, &wrong, ProcessMode::Decrypt, &plain_opts).is_err());
+    }
+    
+    #[test]
+    fn test_inline_files_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret = [0u8, 159, 146, 150, 255, 10];
+        std::fs::create_dir(dir.path().join("certs")).unwrap();
+        std::fs::write(dir.path().join("certs/key.der"), secret).unwrap();
+        
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { inline_files: Some(dir.path().to_path_buf()), ..Default::default() };
+        let content = "TLS_KEY=@certs/key.der\n";
+        
+        let (encrypted, _) = process_file(content, &password, ProcessMode::Encrypt, &opts).unwrap();
+        verify_roundtrip(content, &encrypted, &password, &opts).unwrap();
+        std::fs::write(dir.path().join("certs/key.der"), "changed").unwrap();
+        let err = verify_roundtrip(content, &encrypted, &password, &opts).unwrap_err();
+        assert_eq!(err.to_string(), "Verification failed: inlined file 'certs/key.der' differs from the original");
+        let missing = process_file("KEY=@missing", &password, ProcessMode::Encrypt, &opts);
+        assert!(missing.unwrap_err().to_string().contains("Cannot inline 'missing'"));
+        
+        // Decrypting writes nothing; the caller takes the files out and writes them
+        std::fs::remove_file(dir.path().join("certs/key.der")).unwrap();
+        let (decrypted, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert!(!dir.path().join("certs/key.der").exists());
+        let (decrypted, files) = take_inline_files(&decrypted, &opts).unwrap();
+        assert_eq!(decrypted, content);
+        assert_eq!(files, [InlineFile { path: PathBuf::from("certs/key.der"), bytes: secret.to_vec() }]);
+        write_inline_files(dir.path(), &files).unwrap();
+        assert_eq!(std::fs::read(dir.path().join("certs/key.der")).unwrap(), secret);
+        
+        // Without --inline-files the Base64 contents are restored inline
+        let plain_opts = ProcessOptions::default();
+        let (inline, _) = process_file(&encrypted, &password, ProcessMode::Decrypt, &plain_opts).unwrap();
+        assert_eq!(inline, "TLS_KEY=AJ+Slv8K\n");
+        
+        assert!(check_relative_path("../outside").is_err());
+        assert!(check_relative_path("/etc/passwd").is_err());
+    }
+    
+    #[test]
+    fn test_allow_plaintext_passthrough() {
+        let password = SecretString::new("secret".to_string());
+        let content = format!(
+            "APP_NAME=demo\nDB_PASS={}\nEMPTY=\nGREETING=\"hello world\"\n",
+            derive_key(&password).encrypt("hunter2")
+        );
+        assert!(process_file(&content, &password, ProcessMode::Decrypt, &ProcessOptions::default()).is_err());
+        
+        let opts = ProcessOptions { allow_plaintext: true, ..Default::default() };
+        let (decrypted, keys) = process_file(&content, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(decrypted, "APP_NAME=demo\nDB_PASS=hunter2\nEMPTY=\nGREETING=\"hello world\"\n");
+        assert_eq!(keys, ["APP_NAME", "DB_PASS", "EMPTY", "GREETING"]);
+        
+        // Streamed stdin -> stdout, where the first value being plaintext must not fail the file
+        let mut streamed = Vec::new();
+        process_reader(content.as_bytes(), &mut streamed, &password, ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), decrypted);
+        
+        // Values that look encrypted are still decrypted, so a wrong password is still an error
+        let other = SecretString::new("other".to_string());
+        assert!(process_file(&content, &other, ProcessMode::Decrypt, &opts).is_err());
+    }
+    
+    #[test]
+    fn test_engine_error_variants() {
+        let password = SecretString::new("secret".to_string());
+        let other = SecretString::new("other".to_string());
+        let opts = ProcessOptions::default();
+        let decrypt = |content: &str| process_file(content, &password, ProcessMode::Decrypt, &opts).unwrap_err();
+        
+        let wrong = format!("# note\nDB_PASS={}\n", derive_key(&other).encrypt("hunter2"));
+        match decrypt(&wrong) {
+            EngineError::AtLine { line: 2, source } => assert!(matches!(*source, EngineError::WrongPassword(DecryptError::Invalid))),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        
+        let corrupt = format!("CERT={}\n", derive_key(&password).encrypt("@inline:no-separator"));
+        match decrypt(&corrupt) {
+            EngineError::AtLine { line: 1, source } => assert!(matches!(*source, EngineError::Corrupt(_))),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        
+        assert!(matches!(decrypt("# wc-envc: cipher=rot13\nA=1\n"), EngineError::UnsupportedVersion(_)));
+        
+        let dir = tempfile::tempdir().unwrap();
+        let inline = ProcessOptions { inline_files: Some(dir.path().to_path_buf()), ..Default::default() };
+        match process_file("KEY=@missing\n", &password, ProcessMode::Encrypt, &inline).unwrap_err() {
+            EngineError::AtLine { line: 1, source } => match *source {
+                EngineError::Io { source, .. } => assert_eq!(source.kind(), std::io::ErrorKind::NotFound),
+                e => panic!("unexpected error: {:?}", e),
+            },
+            e => panic!("unexpected error: {:?}", e),
+        }
+        
+        assert!(matches!(validate_encrypted_file("A=plain\n", &opts), Err(EngineError::Invalid(_))));
+        assert!(matches!(dearmor("\n-----BEGIN"), Err(EngineError::Parse { line: 2, .. })));
+        
+        // Header errors give the header's line in the file, counting the headers before it
+        let bad_salt = "# wc-envc: name=.env\n# wc-envc: shares=2-of-3\n# wc-envc: cipher=chacha20-poly1305 kdf=argon2id salt=***\nA=1\n";
+        match decrypt(bad_salt) {
+            EngineError::Parse { line, message } => {
+                assert_eq!((line, message.as_str()), (3, "Malformed salt in the cipher header"));
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(matches!(file_cipher(bad_salt), Err(EngineError::Parse { line: 3, .. })));
+        let bad_policy = decrypt("# wc-envc: name=.env\n# wc-envc: encrypted-keys=\nA=1\n");
+        assert!(bad_policy.to_string().starts_with("line 2: Malformed encrypted-keys header"), "{}", bad_policy);
+    }
+    
+    #[test]
+    fn test_engine_error_line_context() {
+        let password = SecretString::new("secret".to_string());
+        let other = SecretString::new("other".to_string());
+        let wrong = format!("A={}\nB={}\n", derive_key(&other).encrypt("1"), derive_key(&other).encrypt("2"));
+        let wrong_password = |e: &EngineError| matches!(e, EngineError::WrongPassword(DecryptError::Invalid));
+        
+        let fail_fast = ProcessOptions::default();
+        match process_file(&wrong, &password, ProcessMode::Decrypt, &fail_fast).unwrap_err() {
+            EngineError::AtLine { line: 1, source } => assert!(wrong_password(&source)),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        
+        let all = ProcessOptions { fail_fast: false, ..Default::default() };
+        match process_file(&wrong, &password, ProcessMode::Decrypt, &all).unwrap_err() {
+            EngineError::Failures(failures) => {
+                assert_eq!(failures.iter().map(|(line, key, _)| (*line, key.as_str())).collect::<Vec<_>>(), [(1, "A"), (2, "B")]);
+                assert!(failures.iter().all(|(_, _, e)| wrong_password(e)));
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+        
+        let mut output = Vec::new();
+        match process_reader(wrong.as_bytes(), &mut output, &password, ProcessMode::Decrypt, &fail_fast).unwrap_err() {
+            EngineError::AtLine { line: 1, source } => assert!(wrong_password(&source)),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
 }
@@ -0,0 +1,80 @@
+//! Encrypt a .env file and write a key-only `.env.example` that documents the variables
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use console::style;
+use secrecy::SecretString;
+
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::interactive::{self, RunOptions};
+use crate::scanner;
+
+/// Every key of `content` once, in order, with `placeholder` as its value
+/// Comments and values are left out so nothing secret reaches the example
+fn example_content(content: &str, placeholder: &str, opts: &ProcessOptions) -> String {
+    let mut keys: Vec<&str> = Vec::new();
+    for key in engine::keys(content, opts) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    
+    keys.iter().map(|key| format!("{}={}\n", key, placeholder)).collect()
+}
+
+/// Handle example command
+/// Encrypts `file` like a one-liner encrypt, then writes `<file>.example` next to it
+pub fn handle_example(
+    file: &Path,
+    password: Option<SecretString>,
+    placeholder: &str,
+    yes: bool,
+    opts: &RunOptions,
+) -> Result<()> {
+    let content = interactive::read_input(file, ProcessMode::Encrypt, opts)?;
+    let example_path = scanner::example_output_name(file);
+    if example_path.exists() && !yes {
+        interactive::confirm_overwrite(&example_path)?;
+    }
+    
+    let output = interactive::output_path(file, ProcessMode::Encrypt, opts)?;
+    interactive::run_one_liner(file.to_path_buf(), output, password, yes, ProcessMode::Encrypt, opts)?;
+    
+    fs::write(&example_path, example_content(&content, placeholder, &opts.process))?;
+    println!("{} Saved: {}", style("📄").cyan(), style(example_path.display()).cyan());
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_example_has_every_key_without_values() {
+        let content = "# database\nDB_HOST=localhost\nexport DB_PASS = hunter2\n\nDB_HOST=override\nAPI_KEY=\n";
+        let opts = ProcessOptions::default();
+        
+        assert_eq!(
+            example_content(content, "", &opts),
+            "DB_HOST=\nexport DB_PASS=\nAPI_KEY=\n"
+        );
+        assert_eq!(example_content("A=1\n", "changeme", &opts), "A=changeme\n");
+    }
+    
+    #[test]
+    fn test_example_command_writes_both_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join(".env");
+        fs::write(&input, "DB_HOST=localhost\nDB_PASS=hunter2\n").unwrap();
+        
+        handle_example(&input, Some(SecretString::new("pw".to_string())), "", true, &RunOptions::default()).unwrap();
+        
+        let example = fs::read_to_string(dir.path().join(".env.example")).unwrap();
+        assert_eq!(example, "DB_HOST=\nDB_PASS=\n");
+        let encrypted = fs::read_to_string(dir.path().join(".env.enc")).unwrap();
+        assert!(!encrypted.contains("hunter2"));
+    }
+}
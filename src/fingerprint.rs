@@ -0,0 +1,115 @@
+//! Compare secrets without sharing them: a keyed hash of the decrypted variables
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use console::style;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::interactive::{self, RunOptions};
+use crate::setenv;
+
+/// Environment variable holding a shared fingerprint key
+pub const FINGERPRINT_KEY_ENV_VAR: &str = "WC_ENVC_FINGERPRINT_KEY";
+
+/// Domain separation when the key is derived from the password
+const PASSWORD_KEY_CONTEXT: &[u8] = b"wc-envc fingerprint v1\0";
+
+/// HMAC-SHA256 over the sorted, length-prefixed KEY=VALUE set; the last definition of a key wins
+/// Comments, order, quoting and whitespace around values don't change the result
+fn fingerprint(content: &str, key: &[u8], opts: &ProcessOptions) -> Result<String> {
+    let opts = ProcessOptions { dedupe: true, ..opts.clone() };
+    let vars: BTreeMap<String, String> = setenv::parse_env_file(content, &opts).into_iter().collect();
+    
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| anyhow::anyhow!("Invalid fingerprint key: {}", e))?;
+    for (name, value) in &vars {
+        for part in [name, value] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part.as_bytes());
+        }
+    }
+    
+    // 128 bits in groups of four, short enough to read out over chat
+    let digest = mac.finalize().into_bytes();
+    let hex: Vec<String> = digest[..16].chunks(2).map(|pair| format!("{:02x}{:02x}", pair[0], pair[1])).collect();
+    Ok(hex.join(" "))
+}
+
+/// Fingerprint key: the shared key if given, otherwise derived from the password
+fn fingerprint_key(shared: Option<&str>, password: &SecretString) -> Vec<u8> {
+    match shared {
+        Some(key) => key.as_bytes().to_vec(),
+        None => Sha256::new()
+            .chain_update(PASSWORD_KEY_CONTEXT)
+            .chain_update(password.expose_secret().as_bytes())
+            .finalize()
+            .to_vec(),
+    }
+}
+
+/// Handle fingerprint command
+/// Decrypts in memory and prints a fingerprint teammates can compare; nothing is written
+pub fn handle_fingerprint(
+    file: &Path,
+    password: Option<String>,
+    key: Option<String>,
+    opts: &RunOptions,
+) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("File not found: {}", file.display());
+    }
+    
+    let content = interactive::read_input(file, ProcessMode::Decrypt, opts)?;
+    let password = match password {
+        Some(p) => interactive::password_from_arg(p, ProcessMode::Decrypt)?,
+        None => interactive::get_password_from_env_or_prompt(false)?,
+    };
+    
+    let (decrypted, keys) = engine::process_file(&content, &password, ProcessMode::Decrypt, &opts.process)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+    let printed = fingerprint(&decrypted, &fingerprint_key(key.as_deref(), &password), &opts.process)?;
+    
+    println!("{}", printed);
+    eprintln!(
+        "{} {} variable(s), keyed with {}",
+        style("🔏").cyan().for_stderr(),
+        keys.len(),
+        if key.is_some() { "the shared fingerprint key" } else { "the password" }
+    );
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_fingerprint_tracks_values_not_layout() {
+        let opts = ProcessOptions::default();
+        let key = b"team-key";
+        let original = fingerprint("DB_HOST=localhost\nDB_PASS=secret\n", key, &opts).unwrap();
+        
+        assert_eq!(original.len(), 39);
+        assert_eq!(fingerprint("# comment\nDB_PASS=\"secret\"\n\nDB_HOST = localhost", key, &opts).unwrap(), original);
+        assert_ne!(fingerprint("DB_HOST=localhost\nDB_PASS=secret2\n", key, &opts).unwrap(), original);
+        assert_ne!(fingerprint("DB_HOST=localhost\nDB_PASS=secret\n", b"other-key", &opts).unwrap(), original);
+        
+        // Length prefixes keep the boundary between key and value unambiguous
+        assert_ne!(fingerprint("A=BC\n", key, &opts).unwrap(), fingerprint("AB=C\n", key, &opts).unwrap());
+    }
+    
+    #[test]
+    fn test_fingerprint_key_from_password() {
+        let password = SecretString::new("secret".to_string());
+        assert_eq!(fingerprint_key(Some("shared"), &password), b"shared");
+        
+        let derived = fingerprint_key(None, &password);
+        assert_eq!(derived.len(), 32);
+        assert_ne!(derived, Sha256::digest(b"secret").to_vec());
+    }
+}
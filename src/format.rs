@@ -0,0 +1,217 @@
+//! Input format detection and conversion of JSON/YAML key-value files to .env,
+//! plus the strict KEY=VALUE form `docker run --env-file` reads
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::engine::{self, ProcessOptions};
+
+/// Format of a plain input file
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// Detect from content
+    #[default]
+    Auto,
+    /// KEY=VALUE lines
+    Dotenv,
+    /// Flat JSON object
+    Json,
+    /// Flat YAML mapping
+    Yaml,
+}
+
+/// Sniff the format of a file from its first meaningful line
+/// `{` means JSON, `---` or a `key: value` line means YAML; anything else (or ambiguity) is .env
+pub fn detect(content: &str) -> InputFormat {
+    let Some(first) = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+    else {
+        return InputFormat::Dotenv;
+    };
+    
+    if first.starts_with('{') {
+        return InputFormat::Json;
+    }
+    
+    if first == "---" {
+        return InputFormat::Yaml;
+    }
+    
+    // `key: value`, where the colon comes before any `=`
+    let is_mapping = match (first.find(':'), first.find('=')) {
+        (Some(colon), None) => colon > 0,
+        (Some(colon), Some(eq)) => colon > 0 && colon < eq && first[colon + 1..].starts_with(' '),
+        _ => false,
+    };
+    
+    if is_mapping {
+        InputFormat::Yaml
+    } else {
+        InputFormat::Dotenv
+    }
+}
+
+/// Convert content in the given format to .env text (detecting it first for `Auto`)
+pub fn to_dotenv(content: &str, format: InputFormat) -> Result<String> {
+    let format = match format {
+        InputFormat::Auto => detect(content),
+        format => format,
+    };
+    
+    match format {
+        InputFormat::Json => json_to_dotenv(content),
+        InputFormat::Yaml => yaml_to_dotenv(content),
+        InputFormat::Auto | InputFormat::Dotenv => Ok(content.to_string()),
+    }
+}
+
+/// Convert a flat JSON object to KEY=VALUE lines
+fn json_to_dotenv(content: &str) -> Result<String> {
+    let value: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| anyhow::anyhow!("Invalid JSON: {}", e))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("JSON input must be an object of key/value pairs"))?;
+    
+    let mut lines = Vec::new();
+    for (key, value) in object {
+        let value = match value {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::Bool(_) | serde_json::Value::Number(_) => value.to_string(),
+            _ => anyhow::bail!("JSON key '{}': nested values are not supported", key),
+        };
+        lines.push(format!("{}={}", key, value));
+    }
+    
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Convert a flat YAML mapping (`key: value` lines) to KEY=VALUE lines
+fn yaml_to_dotenv(content: &str) -> Result<String> {
+    let mut lines = Vec::new();
+    
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            continue;
+        }
+        
+        if line.starts_with([' ', '\t']) {
+            anyhow::bail!("line {}: nested YAML values are not supported", index + 1);
+        }
+        
+        let (key, value) = trimmed
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("line {}: expected 'key: value'", index + 1))?;
+        
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        lines.push(format!("{}={}", key.trim(), value));
+    }
+    
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Rewrite decrypted .env content for `docker run --env-file`
+/// Docker takes everything after `=` literally: quotes are kept and `#` does not start a comment,
+/// so values are unquoted, inline comments and `export` dropped, and only the last definition of a key kept
+pub fn to_docker_env_file(content: &str, opts: &ProcessOptions) -> Result<String> {
+    let mut vars: Vec<(&str, &str)> = Vec::new();
+    
+    for (index, line) in engine::lines(content).enumerate() {
+        if opts.is_ignored(line.trim()) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        
+        let key = engine::bare_key(key);
+        if !engine::is_valid_key(key) {
+            anyhow::bail!("line {}: '{}' is not a valid variable name for --env-file", index + 1, key);
+        }
+        
+        // A quote that opens a multi-line value would leave its other lines as stray entries
+        if let Some(quote) = unterminated_quote(value) {
+            anyhow::bail!(
+                "line {}: {} opens a {} quote that is not closed on the same line; --env-file values can't span lines",
+                index + 1,
+                key,
+                quote
+            );
+        }
+        
+        vars.retain(|(existing, _)| *existing != key);
+        vars.push((key, docker_value(value)));
+    }
+    
+    Ok(vars.iter().map(|(key, value)| format!("{}={}\n", key, value)).collect())
+}
+
+/// The quote a value opens without closing it on its own line, if any
+fn unterminated_quote(value: &str) -> Option<char> {
+    let value = value.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    (!value[1..].contains(quote)).then_some(quote)
+}
+
+/// Value as docker should see it: the text inside matching quotes, or up to an inline ` #` comment
+fn docker_value(value: &str) -> &str {
+    engine::unquoted_value(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_detect_formats() {
+        assert_eq!(detect("{\"DB_HOST\": \"localhost\"}"), InputFormat::Json);
+        assert_eq!(detect("---\nDB_HOST: localhost\n"), InputFormat::Yaml);
+        assert_eq!(detect("# config\nDB_HOST: localhost\n"), InputFormat::Yaml);
+        assert_eq!(detect("DB_HOST=localhost\n"), InputFormat::Dotenv);
+        assert_eq!(detect("URL=http://example.com\n"), InputFormat::Dotenv);
+        assert_eq!(detect(""), InputFormat::Dotenv);
+    }
+    
+    #[test]
+    fn test_to_dotenv() {
+        let json = "{\"DB_HOST\": \"localhost\", \"DB_PORT\": 5432}";
+        assert_eq!(to_dotenv(json, InputFormat::Auto).unwrap(), "DB_HOST=localhost\nDB_PORT=5432\n");
+        
+        let yaml = "---\nDB_HOST: \"localhost\"\nURL: http://example.com\n";
+        let expected = "DB_HOST=localhost\nURL=http://example.com\n";
+        assert_eq!(to_dotenv(yaml, InputFormat::Auto).unwrap(), expected);
+        
+        assert!(to_dotenv("{\"DB\": {\"HOST\": \"x\"}}", InputFormat::Auto).is_err());
+    }
+    
+    #[test]
+    fn test_docker_env_file_grammar() {
+        let content = "# database\r\nexport DB_HOST = localhost\r\n\nDB_PASS=\"p@ss # word\"  # prod\nGREETING='hi there'\nPORT=5432 # default\nDB_HOST=db.internal\nEMPTY=\n";
+        let output = to_docker_env_file(content, &ProcessOptions::default()).unwrap();
+        assert_eq!(output, "DB_PASS=p@ss # word\nGREETING=hi there\nPORT=5432\nDB_HOST=db.internal\nEMPTY=\n");
+        
+        // Docker's grammar: every line is NAME=VALUE with a POSIX name and nothing before it
+        for line in output.lines() {
+            let (key, _) = line.split_once('=').unwrap();
+            assert!(engine::is_valid_key(key), "{}", line);
+        }
+        
+        let err = to_docker_env_file("MY VAR=1\n", &ProcessOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("'MY VAR'"));
+        
+        let err = to_docker_env_file("A=1\nCERT=\"-----BEGIN\nMIIB\n-----END\"\n", &ProcessOptions::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: CERT opens a \" quote that is not closed on the same line; --env-file values can't span lines"
+        );
+    }
+}
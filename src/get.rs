@@ -0,0 +1,143 @@
+//! Decrypt a single key from an encrypted .env file
+
+use std::path::Path;
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::engine::{self, ProcessMode};
+use crate::interactive::{self, RunOptions};
+use crate::setenv;
+
+/// What a per-key command does when the key is not in the file
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum MissingKeyPolicy {
+    /// Fail with an error
+    #[default]
+    Error,
+    /// Print nothing and exit successfully
+    Skip,
+}
+
+/// Find the raw (still encrypted) value for a key; the last occurrence wins like in shells
+/// Returns tuple: (1-based line number, raw value)
+fn find_raw_value<'a>(
+    content: &'a str,
+    key: &str,
+    opts: &engine::ProcessOptions,
+) -> Option<(usize, &'a str)> {
+    engine::lines(content)
+        .enumerate()
+        .filter(|(_, line)| !opts.is_ignored(line.trim()))
+        .filter_map(|(index, line)| {
+            let (line_key, value) = line.split_once('=')?;
+            (line_key.trim() == key).then_some((index + 1, value))
+        })
+        .last()
+}
+
+/// Format an `export KEY='value'` line safe to `eval` in POSIX shells
+fn export_line(key: &str, value: &str) -> String {
+    format!("export {}={}", key, setenv::shell_quote(value))
+}
+
+/// Handle get command
+/// Decrypts only the requested key and prints its value (or an export line) to stdout
+pub fn handle_get(
+    file: &Path,
+    key: &str,
+    password: Option<String>,
+    export: bool,
+    on_missing_key: MissingKeyPolicy,
+    opts: &RunOptions,
+) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("File not found: {}", file.display());
+    }
+    
+    if export && !engine::is_valid_key(key) {
+        anyhow::bail!("'{}' is not a valid environment variable name", key);
+    }
+    
+    let file_content = interactive::read_input(file, ProcessMode::Decrypt, opts)?;
+    
+    // Get password from: arg > env > prompt
+    let password = match password {
+        Some(p) => interactive::password_from_arg(p, ProcessMode::Decrypt)?,
+        None => interactive::get_password_from_env_or_prompt(false)?,
+    };
+    
+    let (_, content) = engine::split_name_header(&file_content);
+    let (_, content) = engine::split_shares_header(content);
+    let (_, content) = engine::split_recipient_header(content);
+    let (policy, content) = engine::split_keys_header(content)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e.within(&file_content, content)))?;
+    let (cipher, content) = engine::split_cipher_header(content)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e.within(&file_content, content)))?;
+    let cipher = cipher.build(&password)?;
+    
+    // Hidden key names (--hide-keys) need the password before the key can be found
+    let content = engine::reveal_keys(content, &*cipher, &opts.process)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+    let Some((line, raw)) = find_raw_value(&content, key, &opts.process) else {
+        return match on_missing_key {
+            MissingKeyPolicy::Error => Err(anyhow::anyhow!("Key '{}' not found in {}", key, file.display())),
+            MissingKeyPolicy::Skip => Ok(()),
+        };
+    };
+    
+    // Keys outside the file's `.wc-envc-keys` policy were never encrypted
+    let value = match policy {
+        Some(policy) if !policy.matches(key) => raw.to_string(),
+        _ => cipher.decrypt(raw).map_err(|e| anyhow::anyhow!("{}: line {}: {}", file.display(), line, e))?,
+    };
+    let value = setenv::strip_quotes(value.trim());
+    
+    if export {
+        println!("{}", export_line(key, &value));
+    } else {
+        println!("{}", value);
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_export_line_quotes_special_characters() {
+        assert_eq!(export_line("DB_PASS", "p@ss word"), "export DB_PASS='p@ss word'");
+        assert_eq!(
+            export_line("DB_PASS", "it's $HOME `id` \"x\""),
+            "export DB_PASS='it'\\''s $HOME `id` \"x\"'"
+        );
+    }
+    
+    #[test]
+    fn test_find_raw_value_last_wins() {
+        let content = "# DB_PASS=commented\nDB_PASS=first\nDB_HOST=x\nDB_PASS=second\n";
+        let opts = engine::ProcessOptions::default();
+        
+        assert_eq!(find_raw_value(content, "DB_PASS", &opts), Some((4, "second")));
+        assert_eq!(find_raw_value(content, "MISSING", &opts), None);
+    }
+    
+    #[test]
+    fn test_missing_key_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".env.enc");
+        let password = secrecy::SecretString::new("correct".to_string());
+        let (encrypted, _) = engine::process_file("DB_HOST=localhost\n", &password, ProcessMode::Encrypt, &Default::default()).unwrap();
+        std::fs::write(&file, encrypted).unwrap();
+        let get = |key: &str, policy| {
+            handle_get(&file, key, Some("correct".to_string()), false, policy, &RunOptions::default())
+        };
+        
+        let err = get("MISSING", MissingKeyPolicy::Error).unwrap_err();
+        assert_eq!(err.to_string(), format!("Key 'MISSING' not found in {}", file.display()));
+        assert!(get("MISSING", MissingKeyPolicy::Skip).is_ok());
+        assert!(get("DB_HOST", MissingKeyPolicy::Skip).is_ok());
+    }
+}
@@ -0,0 +1,123 @@
+//! git `clean`/`smudge` filters: plaintext in the working tree, encrypted in the repository
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use console::style;
+use secrecy::SecretString;
+
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::{interactive, keypolicy};
+
+/// Whether `content` already decrypts with `password`, so cleaning it again must not double-encrypt
+fn decrypts_with(content: &str, password: &SecretString, opts: &ProcessOptions) -> bool {
+    engine::validate_encrypted_file(content, opts).is_ok()
+        && engine::process_file(content, password, ProcessMode::Decrypt, opts).is_ok()
+}
+
+/// Encrypt a staged file; content that is already encrypted with `password` passes through unchanged
+/// Encryption is deterministic, so cleaning the same plaintext twice gives the same blob and git sees no change
+pub fn clean<R: Read, W: Write>(mut reader: R, mut writer: W, password: &SecretString, opts: &ProcessOptions) -> Result<()> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    
+    if !decrypts_with(&content, password, opts) {
+        if let Some(policy) = &opts.key_policy {
+            keypolicy::coverage(policy, &content, opts)?;
+        }
+        content = engine::process_file(&content, password, ProcessMode::Encrypt, opts)?.0;
+    }
+    writer.write_all(content.as_bytes())?;
+    Ok(writer.flush()?)
+}
+
+/// Decrypt a checked-out file; content that isn't encrypted (committed before the filter) passes through
+/// Without a password the encrypted content is checked out as-is, so clones without the key still work
+pub fn smudge<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    password: Option<&SecretString>,
+    opts: &ProcessOptions,
+) -> Result<()> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    if engine::is_armored(&content) {
+        content = engine::dearmor(&content)?;
+    }
+    
+    if let Some(password) = password.filter(|_| engine::validate_encrypted_file(&content, opts).is_ok()) {
+        content = engine::process_file(&content, password, ProcessMode::Decrypt, opts)?.0;
+    }
+    writer.write_all(content.as_bytes())?;
+    Ok(writer.flush()?)
+}
+
+/// Handle clean command (git filter, stdin to stdout)
+/// Fails without a password rather than let plaintext reach the repository
+pub fn handle_clean(file: Option<&Path>, opts: &ProcessOptions) -> Result<()> {
+    let password = interactive::require_env_password("clean", ProcessMode::Encrypt)?;
+    let opts = keypolicy::options_for(file.unwrap_or(Path::new("-")), opts)?;
+    clean(io::stdin().lock(), io::stdout().lock(), &password, &opts)
+        .map_err(|e| with_file(e, file))
+}
+
+/// Handle smudge command (git filter, stdin to stdout)
+pub fn handle_smudge(file: Option<&Path>, opts: &ProcessOptions) -> Result<()> {
+    let password = match interactive::password_from_env() {
+        Some(_) => Some(interactive::require_env_password("smudge", ProcessMode::Decrypt)?),
+        None => {
+            eprintln!(
+                "{} {} not set; checking out {} encrypted",
+                style("⚠️").yellow().for_stderr(),
+                interactive::password_env_var(),
+                file.map_or("the file".to_string(), |file| file.display().to_string())
+            );
+            None
+        }
+    };
+    smudge(io::stdin().lock(), io::stdout().lock(), password.as_ref(), opts)
+        .map_err(|e| with_file(e, file))
+}
+
+/// Prefix an error with the path git passed as `%f`, if any
+fn with_file(e: anyhow::Error, file: Option<&Path>) -> anyhow::Error {
+    match file {
+        Some(file) => anyhow::anyhow!("{}: {}", file.display(), e),
+        None => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Run a filter over `input` the way git does, through a reader and a writer
+    fn filter(run: impl FnOnce(&[u8], &mut Vec<u8>) -> Result<()>, input: &str) -> String {
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+    
+    #[test]
+    fn test_clean_then_smudge_roundtrip() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions::default();
+        let plain = "# database\nDB_HOST=localhost\nDB_PASS=hunter2\n";
+        
+        let blob = filter(|r, w| clean(r, w, &password, &opts), plain);
+        assert!(!blob.contains("hunter2"));
+        assert!(engine::validate_encrypted_file(&blob, &opts).is_ok());
+        
+        // git re-cleans the working tree file and the staged blob; neither may change the result
+        assert_eq!(filter(|r, w| clean(r, w, &password, &opts), plain), blob);
+        assert_eq!(filter(|r, w| clean(r, w, &password, &opts), &blob), blob);
+        
+        assert_eq!(filter(|r, w| smudge(r, w, Some(&password), &opts), &blob), plain);
+        assert_eq!(filter(|r, w| smudge(r, w, None, &opts), &blob), blob);
+        assert_eq!(filter(|r, w| smudge(r, w, Some(&password), &opts), plain), plain);
+        
+        let other = SecretString::new("other".to_string());
+        assert!(smudge(blob.as_bytes(), Vec::new(), Some(&other), &opts).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! `# include: FILE` directives: compose an env file from others (`--follow-includes`)
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::engine::{self, ProcessOptions};
+
+/// Comment line naming a file to merge in, relative to the including file
+/// Targets must stay under the including file's directory: relative, no `..`, no symlink out
+pub const INCLUDE_PREFIX: &str = "# include:";
+
+/// Files named by the include directives in `content`, in order
+fn directives(content: &str) -> Vec<&str> {
+    engine::lines(content)
+        .filter_map(|line| line.trim().strip_prefix(INCLUDE_PREFIX))
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .collect()
+}
+
+/// Prepend the variables of every included file to `content`
+/// `load` reads an included file (decrypting it if needed); nested includes are followed too.
+/// The including file wins: included lines for keys it defines itself are dropped.
+/// Later includes override earlier ones the usual way, by coming later
+pub fn resolve(
+    path: &Path,
+    content: &str,
+    opts: &ProcessOptions,
+    load: &mut dyn FnMut(&Path) -> Result<String>,
+) -> Result<String> {
+    let mut visiting = vec![identity(path)];
+    resolve_from(path, content, opts, load, &mut visiting)
+}
+
+fn resolve_from(
+    path: &Path,
+    content: &str,
+    opts: &ProcessOptions,
+    load: &mut dyn FnMut(&Path) -> Result<String>,
+    visiting: &mut Vec<PathBuf>,
+) -> Result<String> {
+    let targets = directives(content);
+    if targets.is_empty() {
+        return Ok(content.to_string());
+    }
+    
+    let base = path.parent().unwrap_or(Path::new(""));
+    let root = identity(if base.as_os_str().is_empty() { Path::new(".") } else { base });
+    let overridden: HashSet<&str> = engine::keys(content, opts).collect();
+    let mut merged = String::new();
+    
+    for target in targets {
+        let relative = engine::check_relative_path(target).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        let included = base.join(relative);
+        let id = identity(&included);
+        if included.exists() && !id.starts_with(&root) {
+            anyhow::bail!("{}: include '{}' resolves outside {}", path.display(), target, base.display());
+        }
+        if visiting.contains(&id) {
+            let chain: Vec<String> = visiting.iter().chain([&id]).map(|p| p.display().to_string()).collect();
+            anyhow::bail!("Include cycle: {}", chain.join(" -> "));
+        }
+        
+        let loaded = load(&included).map_err(|e| anyhow::anyhow!("{}: {}", included.display(), e))?;
+        visiting.push(id);
+        let resolved = resolve_from(&included, &loaded, opts, load, visiting)?;
+        visiting.pop();
+        
+        let dropped: HashSet<usize> = engine::key_lines(&resolved, opts)
+            .filter(|(_, key)| overridden.contains(key))
+            .map(|(index, _)| index)
+            .collect();
+        for (index, line) in engine::lines_inclusive(&resolved).enumerate() {
+            if !dropped.contains(&index) {
+                merged.push_str(line);
+            }
+        }
+        if !merged.is_empty() && !merged.ends_with('\n') {
+            merged.push('\n');
+        }
+    }
+    
+    merged.push_str(content);
+    Ok(merged)
+}
+
+/// Canonical path for cycle detection (the path as given if it can't be resolved)
+fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Resolve `name` in `dir`, reading included files as they are
+    fn resolve_file(dir: &Path, name: &str) -> Result<String> {
+        let path = dir.join(name);
+        let content = fs::read_to_string(&path)?;
+        resolve(&path, &content, &ProcessOptions::default(), &mut |path| Ok(fs::read_to_string(path)?))
+    }
+    
+    #[test]
+    fn test_include_chain_and_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".env.base"), "LOG_LEVEL=info\nDB_HOST=localhost\n").unwrap();
+        fs::write(dir.path().join(".env.shared"), "# include: .env.base\nDB_PORT=5432\n").unwrap();
+        fs::write(dir.path().join(".env"), "# include: .env.shared\nDB_HOST=db.internal\n").unwrap();
+        
+        assert_eq!(
+            resolve_file(dir.path(), ".env").unwrap(),
+            "LOG_LEVEL=info\n# include: .env.base\nDB_PORT=5432\n# include: .env.shared\nDB_HOST=db.internal\n"
+        );
+        
+        fs::write(dir.path().join(".env.base"), "# include: .env\n").unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let chain: Vec<String> = [".env", ".env.shared", ".env.base", ".env"]
+            .iter()
+            .map(|name| root.join(name).display().to_string())
+            .collect();
+        assert_eq!(
+            resolve_file(dir.path(), ".env").unwrap_err().to_string(),
+            format!("Include cycle: {}", chain.join(" -> "))
+        );
+        
+        fs::write(dir.path().join(".env.base"), "# include: missing\n").unwrap();
+        assert!(resolve_file(dir.path(), ".env").unwrap_err().to_string().contains("missing"));
+    }
+    
+    #[test]
+    fn test_include_stays_under_the_including_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        fs::create_dir(&app).unwrap();
+        fs::write(dir.path().join(".env.outside"), "SECRET=1\n").unwrap();
+        
+        for target in ["../.env.outside", "/etc/passwd", "config/../../.env.outside"] {
+            fs::write(app.join(".env"), format!("# include: {}\nA=1\n", target)).unwrap();
+            let err = resolve_file(&app, ".env").unwrap_err().to_string();
+            assert!(err.contains("must be relative"), "{}: {}", target, err);
+        }
+        
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path().join(".env.outside"), app.join(".env.link")).unwrap();
+            fs::write(app.join(".env"), "# include: .env.link\nA=1\n").unwrap();
+            assert!(resolve_file(&app, ".env").unwrap_err().to_string().contains("resolves outside"));
+        }
+        
+        fs::create_dir(app.join("config")).unwrap();
+        fs::write(app.join("config/.env.base"), "B=2\n").unwrap();
+        fs::write(app.join(".env"), "# include: ./config/.env.base\nA=1\n").unwrap();
+        assert_eq!(resolve_file(&app, ".env").unwrap(), "B=2\n# include: ./config/.env.base\nA=1\n");
+    }
+}
@@ -0,0 +1,201 @@
+//! Describe an encrypted file from its headers and layout, without the password
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use console::style;
+use serde_json::json;
+
+use crate::engine::{self, FileCipher, ProcessOptions};
+
+/// What the headers and layout of an encrypted file say about it; never includes values
+#[derive(Debug, PartialEq)]
+struct FileInfo {
+    armored: bool,
+    /// `--store-name` original file name
+    name: Option<String>,
+    cipher: FileCipher,
+    /// Whether the whole file is one gzip blob (`--compress`) rather than one ciphertext per value
+    compressed: bool,
+    /// `--split` scheme (threshold, count)
+    shares: Option<(u8, u8)>,
+    /// `encrypt --recipient` public keys the file key is wrapped to
+    recipients: usize,
+    /// `.wc-envc-keys` patterns recorded in the header
+    key_policy: Option<String>,
+    hidden_keys: bool,
+    /// KEY=VALUE lines and how many of them look encrypted (None for a compressed blob)
+    variables: Option<(usize, usize)>,
+}
+
+impl FileInfo {
+    /// How the value key is derived
+    fn kdf(&self) -> &'static str {
+        engine::key_derivation(self.cipher.kdf(), self.recipients)
+    }
+    
+    fn layout(&self) -> &'static str {
+        if self.compressed { "compressed" } else { "per-value" }
+    }
+}
+
+/// Read the headers and count the variables of `content`
+fn inspect(content: &str, opts: &ProcessOptions) -> Result<FileInfo> {
+    let armored = engine::is_armored(content);
+    let dearmored;
+    let content = if armored {
+        dearmored = engine::dearmor(content)?;
+        dearmored.as_str()
+    } else {
+        content
+    };
+    
+    let (name, body) = engine::split_name_header(content);
+    let (shares, body) = engine::split_shares_header(body);
+    let (recipients, body) = engine::split_recipient_header(body);
+    let (key_policy, body) = engine::split_keys_header(body).map_err(|e| e.within(content, body))?;
+    let compressed = engine::is_compressed(body);
+    let (cipher, body) = engine::split_cipher_header(body).map_err(|e| e.within(content, body))?;
+    
+    let variables = (!compressed).then(|| {
+        let values: Vec<&str> = engine::lines(body)
+            .filter(|line| !opts.is_ignored(line.trim()))
+            .filter_map(|line| line.split_once('=').map(|(_, value)| value))
+            .collect();
+        (values.len(), values.iter().filter(|value| engine::is_likely_encrypted(value)).count())
+    });
+    
+    Ok(FileInfo {
+        armored,
+        name: name.map(str::to_string),
+        cipher,
+        compressed,
+        shares,
+        recipients: recipients.len(),
+        key_policy: key_policy.map(|policy| policy.to_string()),
+        hidden_keys: engine::has_hidden_keys(body),
+        variables,
+    })
+}
+
+/// Human-readable summary, one `field: value` line each
+fn render_text(file: &Path, info: &FileInfo) -> String {
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    let mut fields = vec![
+        ("format", "wc-envc".to_string()),
+        ("cipher", info.cipher.cipher.name().to_string()),
+        ("kdf", info.kdf().to_string()),
+        ("salt", "none".to_string()),
+        ("layout", info.layout().to_string()),
+        ("armored", yes_no(info.armored).to_string()),
+        ("hidden keys", yes_no(info.hidden_keys).to_string()),
+    ];
+    if let Some(name) = &info.name {
+        fields.push(("stored name", name.clone()));
+    }
+    if let Some((threshold, count)) = info.shares {
+        fields.push(("shares", format!("{}-of-{}", threshold, count)));
+    }
+    if info.recipients > 0 {
+        fields.push(("recipients", info.recipients.to_string()));
+    }
+    if let Some(policy) = &info.key_policy {
+        fields.push(("encrypted keys", policy.clone()));
+    }
+    if let Some((total, encrypted)) = info.variables {
+        fields.push(("variables", format!("{} ({} encrypted)", total, encrypted)));
+    }
+    
+    let mut output = format!("{} {}\n", style("📄").cyan(), style(file.display()).cyan());
+    for (field, value) in fields {
+        output.push_str(&format!("  {:<15} {}\n", format!("{}:", field), value));
+    }
+    output
+}
+
+/// Pretty-printed JSON summary
+fn render_json(file: &Path, info: &FileInfo) -> String {
+    let summary = json!({
+        "file": file.display().to_string(),
+        "format": "wc-envc",
+        "cipher": info.cipher.cipher.name(),
+        "kdf": info.kdf(),
+        "salt": false,
+        "layout": info.layout(),
+        "armored": info.armored,
+        "hidden_keys": info.hidden_keys,
+        "stored_name": info.name,
+        "shares": info.shares.map(|(threshold, count)| json!({ "threshold": threshold, "count": count })),
+        "recipients": info.recipients,
+        "encrypted_keys": info.key_policy,
+        "variables": info.variables.map(|(total, _)| total),
+        "encrypted_values": info.variables.map(|(_, encrypted)| encrypted),
+    });
+    format!("{:#}\n", summary)
+}
+
+/// Handle info command
+/// Prints what the file's headers say; needs no password and never prints values
+pub fn handle_info(file: &Path, json: bool, opts: &ProcessOptions) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("File not found: {}", file.display());
+    }
+    
+    let content = fs::read_to_string(file).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+    
+    // Other tools' formats have headers of their own; only name the format
+    if let Some(decoder) = engine::find_decoder(&content) {
+        if json {
+            println!("{:#}", json!({ "file": file.display().to_string(), "format": decoder.name() }));
+        } else {
+            println!("{} {}\n  {:<15} {}", style("📄").cyan(), style(file.display()).cyan(), "format:", decoder.name());
+        }
+        return Ok(());
+    }
+    let info = inspect(&content, opts).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+    
+    if json {
+        print!("{}", render_json(file, &info));
+    } else {
+        print!("{}", render_text(file, &info));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Cipher, ProcessMode};
+    use secrecy::SecretString;
+    
+    #[test]
+    fn test_info_reports_cipher_and_layout() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions { shares: Some((2, 3)), ..Default::default() };
+        let (encrypted, _) = engine::process_file("A=1\nB=2\n", &password, ProcessMode::Encrypt, &opts).unwrap();
+        let encrypted = engine::add_name_header(&encrypted, ".env");
+        
+        let info = inspect(&engine::armor(&encrypted), &ProcessOptions::default()).unwrap();
+        assert_eq!(info.cipher.cipher, Cipher::Aes256);
+        assert_eq!(info.shares, Some((2, 3)));
+        assert_eq!(info.name.as_deref(), Some(".env"));
+        assert!(info.armored && !info.compressed);
+        assert_eq!(info.variables, Some((2, 2)));
+        
+        // The cipher comes from the header alone, even when this build can't decrypt it
+        let chacha = "# wc-envc: cipher=chacha20-poly1305\n# note\nA=Zm9vYmFyYmF6cXV4\nB=plain\n";
+        let info = inspect(chacha, &ProcessOptions::default()).unwrap();
+        assert_eq!(info.cipher.cipher, Cipher::Chacha20Poly1305);
+        assert_eq!(info.variables, Some((2, 1)));
+        assert!(info.kdf().contains("no salt"));
+        
+        let salted = chacha.replacen("\n", " kdf=argon2id salt=c2FsdHNhbHRzYWx0c2FsdA==\n", 1);
+        assert_eq!(inspect(&salted, &ProcessOptions::default()).unwrap().kdf(), "Argon2id with a per-file salt");
+        
+        let summary: serde_json::Value = serde_json::from_str(&render_json(Path::new(".env.enc"), &info)).unwrap();
+        assert_eq!(summary["cipher"], "chacha20-poly1305");
+        assert_eq!(summary["layout"], "per-value");
+        assert_eq!(summary["recipients"], 0);
+    }
+}
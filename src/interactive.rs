@@ -1,49 +1,177 @@
 use std::path::{Path, PathBuf};
 use std::env;
-use std::io::Write;
-use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use clap::ValueEnum;
 use console::style;
 use dialoguer::{Confirm, MultiSelect, Password, Select};
-use secrecy::SecretString;
+use filetime::FileTime;
+use rayon::prelude::*;
+use secrecy::zeroize::Zeroize;
+use secrecy::{ExposeSecret, SecretString};
 
-use crate::engine::{self, ProcessMode};
+use crate::agent;
+use crate::cache;
+use crate::cancel;
+use crate::clock::TimeZone;
+use crate::diff;
+use crate::engine::{self, FileCipher, InlineFile, ProcessMode, ProcessOptions};
+use crate::format;
+use crate::include;
+use crate::interpolate::{self, Interpolation};
+use crate::keypolicy;
+use crate::report::{self, FileReport, FileStatus};
 use crate::scanner;
+use crate::shares;
 
-/// Environment variable name for password
-const PASSWORD_ENV_VAR: &str = "WC_ENVC_PASSWORD";
+/// Default environment variable name for password
+pub const PASSWORD_ENV_VAR: &str = "WC_ENVC_PASSWORD";
+
+/// Environment variable consulted for the password (set once from --password-env)
+static PASSWORD_ENV: OnceLock<String> = OnceLock::new();
+
+/// Password prompts allowed before giving up, unless --password-attempts says otherwise
+pub const DEFAULT_PASSWORD_ATTEMPTS: usize = 3;
+
+/// Password prompts allowed before giving up (set once from --password-attempts)
+static PASSWORD_ATTEMPTS: OnceLock<usize> = OnceLock::new();
+
+/// Shortest password accepted from any source (set once from --password-min-length)
+static PASSWORD_MIN_LENGTH: OnceLock<usize> = OnceLock::new();
+
+/// Keychain service name under which project passwords are stored
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const KEYCHAIN_SERVICE: &str = "wc-envc";
+
+/// Key derivation faster than this is cheap to brute-force (`--key-derivation-check`)
+const MIN_KEY_DERIVATION: Duration = Duration::from_millis(100);
+
+/// Default comment heading the block of plain files added to .gitignore
+const GITIGNORE_COMMENT: &str = "# Plain .env files (secrets - do not commit)";
+
+/// What to do when an output file already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OverwritePolicy {
+    /// Ask before overwriting (`--yes` overwrites without asking)
+    #[default]
+    Prompt,
+    /// Overwrite without asking
+    Always,
+    /// Leave existing files alone and skip their inputs
+    Never,
+}
+
+/// Options shared by the encrypt/decrypt flows
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// Write (encrypt) or verify (decrypt) a `.sha256` sidecar file
+    pub checksum: bool,
+    /// Print key derivation and processing timings to stderr
+    pub time: bool,
+    /// Delete the encrypted source after a successful decrypt
+    pub remove_source: bool,
+    /// Wrap encrypted output in an armored text block
+    pub armor: bool,
+    /// Refuse inputs larger than this many bytes (0 = no limit)
+    pub max_file_size: u64,
+    /// Write through a symlinked output instead of replacing the link
+    pub dereference: bool,
+    /// Give outputs the modification time of their inputs
+    pub preserve_mtime: bool,
+    /// Resolve `${VAR}` references in decrypted values (`--interpolate`)
+    pub interpolate: Option<Interpolation>,
+    /// Rewrite decrypted output in the strict form `docker run --env-file` reads
+    pub docker_env_file: bool,
+    /// Worker threads for batch processing (0 = automatic)
+    pub threads: usize,
+    /// Custom .gitignore block comment (empty string = no comment)
+    pub gitignore_comment: Option<String>,
+    /// Re-encrypt files even if unchanged since the last run
+    pub force: bool,
+    /// Decrypt encrypted output in memory and compare with the input before writing
+    pub verify: bool,
+    /// Project name of a password stored in the OS keychain
+    pub keychain: Option<String>,
+    /// Shell command whose stdout (trimmed) is the password, e.g. a secret manager CLI
+    pub password_command: Option<String>,
+    /// Output file name template (`{name}`, `{ext}`, `{date}`, `{ts}`)
+    pub output_template: Option<String>,
+    /// Environment name for `.env` <-> `.env.<name>.enc` output naming
+    pub environment: Option<String>,
+    /// Clock for `{date}` / `{ts}` in output templates
+    pub time_zone: TimeZone,
+    /// The password was given with `-p` rather than the environment or a prompt
+    pub password_from_arg: bool,
+    /// Suppress warnings
+    pub quiet: bool,
+    /// Keep processing the rest of a batch after a file fails, then report all failures
+    pub keep_going: bool,
+    /// Record the input's file name in a plain-text header so decrypt can restore it
+    pub store_name: bool,
+    /// Overwrite existing outputs: prompt, always, or never (skip)
+    pub overwrite: OverwritePolicy,
+    /// Ask about each existing output separately (overwrite / skip / overwrite all / quit)
+    pub overwrite_per_file: bool,
+    /// Show a masked diff of an existing output before overwriting it
+    pub diff: bool,
+    /// Write a JSON summary of the batch to this path (no values, only counts and statuses)
+    pub report: Option<PathBuf>,
+    /// Print status lines to stderr, keeping stdout for the result (`--to-memory` prints a path)
+    pub status_to_stderr: bool,
+    /// Parsing options passed to the engine
+    pub process: ProcessOptions,
+}
+
+impl RunOptions {
+    /// Whether a run needs the whole input before writing: the engine's reasons plus checksums,
+    /// timings, diffs and the post-processing in `process_timed`
+    pub fn needs_whole_file(&self, mode: ProcessMode) -> bool {
+        self.checksum
+            || self.time
+            || self.diff
+            || self.interpolate.is_some()
+            || self.docker_env_file
+            || self.process.needs_whole_file(mode)
+    }
+    
+    /// Whether the password comes from `--keychain` or `--password-command` rather than `-p`/env/prompt
+    pub fn has_external_password(&self) -> bool {
+        self.keychain.is_some() || self.password_command.is_some()
+    }
+}
 
 /// Run interactive encrypt flow
-pub fn run_interactive_encrypt(input_file: Option<PathBuf>) -> Result<()> {
+pub fn run_interactive_encrypt(input_file: Option<PathBuf>, opts: &RunOptions) -> Result<()> {
     println!();
     
     // Step 1: Select file(s)
     let input_paths = match input_file {
         Some(path) => {
-            if !path.exists() {
-                anyhow::bail!("File not found: {}", path.display());
-            }
+            scanner::check_input_file(&path)?;
             vec![path]
         }
-        None => select_files(ProcessMode::Encrypt)?,
+        None => select_files(ProcessMode::Encrypt, opts)?,
     };
     
     // Show selected files
     println!("{} Selected {} file(s):", style("✅").green(), input_paths.len());
     for path in &input_paths {
-        let var_count = scanner::count_variables(path);
-        println!("  • {} ({} vars)", 
+        println!("  • {} ({})", 
             style(path.file_name().unwrap_or_default().to_string_lossy()).cyan(),
-            var_count
+            scanner::variables_label(path, &opts.process)
         );
     }
     
     // Step 2: Confirm output files
     let output_paths: Vec<PathBuf> = input_paths
         .iter()
-        .map(|p| scanner::default_output_name(p, ProcessMode::Encrypt))
-        .collect();
+        .map(|p| output_path(p, ProcessMode::Encrypt, opts))
+        .collect::<Result<_>>()?;
     
     println!();
     println!("{} Output files:", style("📝").cyan());
@@ -57,89 +185,79 @@ pub fn run_interactive_encrypt(input_file: Option<PathBuf>) -> Result<()> {
         .interact()?;
     
     if !confirmed {
-        anyhow::bail!("Operation cancelled");
+        return Err(cancel::Cancelled::ByUser.into());
     }
     
     // Step 3: Check for existing files
-    let existing: Vec<&PathBuf> = output_paths.iter().filter(|p| p.exists()).collect();
-    if !existing.is_empty() {
-        println!();
-        println!("{} The following files already exist:", style("⚠️").yellow());
-        for path in &existing {
-            println!("  • {}", style(path.file_name().unwrap_or_default().to_string_lossy()).red());
-        }
-        
-        let confirmed = Confirm::new()
-            .with_prompt("Overwrite these files?")
-            .default(false)
-            .interact()?;
-        
-        if !confirmed {
-            anyhow::bail!("Operation cancelled");
-        }
+    let (input_paths, output_paths) = confirm_existing(input_paths, output_paths, opts, ask_overwrite)?;
+    if input_paths.is_empty() {
+        println!("{} Every file was skipped, nothing to do", style("ℹ️").cyan());
+        return Ok(());
     }
     
     // Step 4: Get password
-    let password = get_password_with_confirm()?;
+    let password = match external_password(ProcessMode::Encrypt, opts)? {
+        Some(password) => password,
+        None => get_password_with_confirm()?,
+    };
     
     // Step 5: Process all files
     println!();
     println!("{} Encrypting {} file(s)...", style("⏳").cyan(), input_paths.len());
     
-    for (input, output) in input_paths.iter().zip(output_paths.iter()) {
-        process_and_save_quiet(input, output, &password, ProcessMode::Encrypt)?;
-    }
+    let written = process_batch(&input_paths, &output_paths, &password, ProcessMode::Encrypt, false, opts)?;
     
-    println!();
-    println!("{} Done! Encrypted {} file(s)", style("✅").green(), input_paths.len());
+    if written > 0 {
+        println!();
+        println!("{} Done! Encrypted {} file(s)", style("✅").green(), written);
+    }
     
     // Step 6: Offer to add original files to .gitignore
-    offer_gitignore(&input_paths)?;
+    offer_gitignore(&input_paths, &gitignore_comment(opts.gitignore_comment.as_deref()))?;
     
     // Show tip
     println!();
     println!("{} Tip: To skip password prompt next time:", style("💡").yellow());
-    println!("   export {}=\"your_password\"", PASSWORD_ENV_VAR);
+    println!("   export {}=\"your_password\"", password_env_var());
     
     Ok(())
 }
 
 /// Run interactive decrypt flow
-pub fn run_interactive_decrypt(input_file: Option<PathBuf>) -> Result<()> {
+pub fn run_interactive_decrypt(input_file: Option<PathBuf>, opts: &RunOptions) -> Result<()> {
     println!();
     
     // Step 1: Select file(s)
     let input_paths = match input_file {
         Some(path) => {
-            if !path.exists() {
-                anyhow::bail!("File not found: {}", path.display());
-            }
+            scanner::check_input_file(&path)?;
             vec![path]
         }
-        None => select_files(ProcessMode::Decrypt)?,
+        None => select_files(ProcessMode::Decrypt, opts)?,
     };
     
-    // Validate all files
-    for path in &input_paths {
-        let content = std::fs::read_to_string(path)?;
-        engine::validate_encrypted_file(&content)?;
+    // Validate all files (with --keep-going, invalid files are reported by the batch instead)
+    if !opts.keep_going {
+        for path in &input_paths {
+            let content = read_input(path, ProcessMode::Decrypt, opts)?;
+            engine::validate_encrypted_file(&content, &opts.process)?;
+        }
     }
     
     // Show selected files
     println!("{} Selected {} file(s):", style("✅").green(), input_paths.len());
     for path in &input_paths {
-        let var_count = scanner::count_variables(path);
-        println!("  • {} ({} vars)", 
+        println!("  • {} ({})", 
             style(path.file_name().unwrap_or_default().to_string_lossy()).cyan(),
-            var_count
+            scanner::variables_label(path, &opts.process)
         );
     }
     
     // Step 2: Confirm output files
     let output_paths: Vec<PathBuf> = input_paths
         .iter()
-        .map(|p| scanner::default_output_name(p, ProcessMode::Decrypt))
-        .collect();
+        .map(|p| output_path(p, ProcessMode::Decrypt, opts))
+        .collect::<Result<_>>()?;
     
     println!();
     println!("{} Output files:", style("📝").cyan());
@@ -153,12 +271,77 @@ pub fn run_interactive_decrypt(input_file: Option<PathBuf>) -> Result<()> {
         .interact()?;
     
     if !confirmed {
-        anyhow::bail!("Operation cancelled");
+        return Err(cancel::Cancelled::ByUser.into());
     }
     
     // Step 3: Check for existing files
-    let existing: Vec<&PathBuf> = output_paths.iter().filter(|p| p.exists()).collect();
-    if !existing.is_empty() {
+    let (input_paths, output_paths) = confirm_existing(input_paths, output_paths, opts, ask_overwrite)?;
+    if input_paths.is_empty() {
+        println!("{} Every file was skipped, nothing to do", style("ℹ️").cyan());
+        return Ok(());
+    }
+    
+    if opts.remove_source {
+        confirm_remove_source(&input_paths)?;
+    }
+    
+    // Step 4: Get password (a --split file's comes from its shares)
+    let password = match password_from_split_file(&input_paths, opts.max_file_size)? {
+        Some(password) => password,
+        None => match external_password(ProcessMode::Decrypt, opts)? {
+            Some(password) => password,
+            None => get_password()?,
+        },
+    };
+    
+    // Step 5: Process all files
+    println!();
+    println!("{} Decrypting {} file(s)...", style("⏳").cyan(), input_paths.len());
+    
+    let written = process_batch(&input_paths, &output_paths, &password, ProcessMode::Decrypt, false, opts)?;
+    
+    if written > 0 {
+        println!();
+        println!("{} Done! Decrypted {} file(s)", style("✅").green(), written);
+    }
+    
+    Ok(())
+}
+
+/// Answer to the per-file overwrite question (`--interactive-overwrite-per-file`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OverwriteChoice {
+    Overwrite,
+    Skip,
+    OverwriteAll,
+    Quit,
+}
+
+/// Ask about one existing output file
+fn ask_overwrite(path: &Path) -> Result<OverwriteChoice> {
+    let choices = [OverwriteChoice::Overwrite, OverwriteChoice::Skip, OverwriteChoice::OverwriteAll, OverwriteChoice::Quit];
+    let selection = Select::new()
+        .with_prompt(format!("{} already exists", style(path.file_name().unwrap_or_default().to_string_lossy()).red()))
+        .items(&["Overwrite", "Skip", "Overwrite all remaining", "Quit"])
+        .default(1)
+        .interact()?;
+    Ok(choices[selection])
+}
+
+/// Confirm overwriting the outputs that already exist, all at once or one by one through `ask`
+/// Returns the input/output pairs left to process; skipped files are dropped from both lists
+fn confirm_existing(
+    input_paths: Vec<PathBuf>,
+    output_paths: Vec<PathBuf>,
+    opts: &RunOptions,
+    mut ask: impl FnMut(&Path) -> Result<OverwriteChoice>,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let existing: Vec<&PathBuf> = output_paths.iter().filter(|p| scanner::output_exists(p)).collect();
+    if existing.is_empty() || !prompt_overwrite(false, opts) {
+        return Ok((input_paths, output_paths));
+    }
+    
+    if !opts.overwrite_per_file {
         println!();
         println!("{} The following files already exist:", style("⚠️").yellow());
         for path in &existing {
@@ -171,23 +354,69 @@ pub fn run_interactive_decrypt(input_file: Option<PathBuf>) -> Result<()> {
             .interact()?;
         
         if !confirmed {
-            anyhow::bail!("Operation cancelled");
+            return Err(cancel::Cancelled::ByUser.into());
         }
+        return Ok((input_paths, output_paths));
     }
     
-    // Step 4: Get password
-    let password = get_password()?;
-    
-    // Step 5: Process all files
     println!();
-    println!("{} Decrypting {} file(s)...", style("⏳").cyan(), input_paths.len());
+    let mut overwrite_all = false;
+    let mut kept = (Vec::new(), Vec::new());
+    for (input, output) in input_paths.into_iter().zip(output_paths) {
+        if !overwrite_all && scanner::output_exists(&output) {
+            match ask(&output)? {
+                OverwriteChoice::Overwrite => {}
+                OverwriteChoice::Skip => continue,
+                OverwriteChoice::OverwriteAll => overwrite_all = true,
+                OverwriteChoice::Quit => return Err(cancel::Cancelled::ByUser.into()),
+            }
+        }
+        kept.0.push(input);
+        kept.1.push(output);
+    }
+    Ok(kept)
+}
+
+/// Process an explicit file list (`--files-from`) without scanning or file selection
+/// Missing files fail like any other file, so `--keep-going` skips past them
+pub fn run_file_list(
+    inputs: Vec<PathBuf>,
+    password: Option<SecretString>,
+    skip_confirm: bool,
+    mode: ProcessMode,
+    opts: &RunOptions,
+) -> Result<()> {
+    if inputs.is_empty() {
+        anyhow::bail!("The file list is empty");
+    }
+    
+    let outputs: Vec<PathBuf> = inputs
+        .iter()
+        .map(|p| output_path(p, mode, opts))
+        .collect::<Result<_>>()?;
     
-    for (input, output) in input_paths.iter().zip(output_paths.iter()) {
-        process_and_save_quiet(input, output, &password, ProcessMode::Decrypt)?;
+    if prompt_overwrite(skip_confirm, opts) {
+        for output in outputs.iter().filter(|p| scanner::output_exists(p)) {
+            confirm_overwrite(output)?;
+        }
     }
     
-    println!();
-    println!("{} Done! Decrypted {} file(s)", style("✅").green(), input_paths.len());
+    if opts.remove_source && mode == ProcessMode::Decrypt && !skip_confirm {
+        confirm_remove_source(&inputs)?;
+    }
+    
+    let password = resolve_password(password, mode, opts)?;
+    
+    let written = process_batch(&inputs, &outputs, &password, mode, skip_confirm, opts)?;
+    if written == 0 {
+        return Ok(());
+    }
+    
+    let verb = match mode {
+        ProcessMode::Encrypt => "Encrypted",
+        ProcessMode::Decrypt => "Decrypted",
+    };
+    println!("{} Done! {} {} file(s)", style("✅").green(), verb, written);
     
     Ok(())
 }
@@ -196,56 +425,183 @@ pub fn run_interactive_decrypt(input_file: Option<PathBuf>) -> Result<()> {
 pub fn run_one_liner(
     input: PathBuf,
     output: PathBuf,
-    password: Option<String>,
+    password: Option<SecretString>,
     skip_confirm: bool,
     mode: ProcessMode,
+    opts: &RunOptions,
 ) -> Result<()> {
-    // Validate input exists
-    if !input.exists() {
-        anyhow::bail!("File not found: {}", input.display());
+    // Validate input exists (stdin and URLs are read directly)
+    let local_input = !scanner::is_stdio(&input) && !scanner::is_url(&input);
+    if local_input {
+        scanner::check_input_file(&input)?;
+    }
+    
+    if opts.remove_source && !local_input {
+        anyhow::bail!("--remove-source requires a local input file");
     }
     
+    // A plain stdin -> stdout decrypt streams line by line instead of reading everything first
+    if streams(&input, &output, mode, opts) {
+        let password = resolve_password(password, mode, opts)?;
+        return stream_stdio(&password, mode, opts);
+    }
+    
+    // Read once: stdin and remote inputs cannot be read twice
+    let content = read_input(&input, mode, opts)?;
+    
     // For decrypt, validate file
     if mode == ProcessMode::Decrypt {
-        let content = std::fs::read_to_string(&input)?;
-        engine::validate_encrypted_file(&content)?;
+        engine::validate_encrypted_file(&content, &opts.process)?;
     }
     
     // Check overwrite
-    if output.exists() && !skip_confirm {
-        confirm_overwrite(&output)?;
+    if scanner::output_exists(&output) {
+        if opts.overwrite == OverwritePolicy::Never {
+            println!("{} {} exists, skipped", style("⏭️").dim(), style(output.display()).cyan());
+            return Ok(());
+        }
+        if prompt_overwrite(skip_confirm, opts) {
+            confirm_overwrite(&output)?;
+        }
     }
     
-    // Get password from: arg > env > prompt
-    let password = match password {
-        Some(p) => SecretString::new(p),
-        None => get_password_from_env_or_prompt(mode == ProcessMode::Encrypt)?,
-    };
+    if opts.remove_source && mode == ProcessMode::Decrypt && !skip_confirm {
+        confirm_remove_source(std::slice::from_ref(&input))?;
+    }
+    
+    let password = resolve_password(password, mode, opts)?;
     
-    process_and_save(&input, &output, &password, mode)?;
+    process_and_save(&input, &content, &output, &password, mode, skip_confirm, opts)?;
     
     Ok(())
 }
 
-/// Select multiple files from list with "All files" option
-fn select_files(mode: ProcessMode) -> Result<Vec<PathBuf>> {
-    let current_dir = env::current_dir()?;
-    let files = scanner::find_env_files(&current_dir, mode);
+/// Password of a `--split` file among `inputs`, rebuilt from prompted shares
+/// Every split file has its own random password, so one can only be decrypted on its own
+fn password_from_split_file(inputs: &[PathBuf], max_size: u64) -> Result<Option<SecretString>> {
+    let mut split = Vec::new();
+    for path in inputs {
+        if shares::split_scheme(path, max_size)?.is_some() {
+            split.push(path);
+        }
+    }
+    match (split.as_slice(), inputs.len()) {
+        ([], _) => Ok(None),
+        ([path], 1) => shares::password_from_shares(path, Vec::new(), max_size),
+        ([path, ..], _) => anyhow::bail!(
+            "{} was encrypted with --split; decrypt it on its own so its shares can be collected",
+            path.display()
+        ),
+    }
+}
+
+/// Password for a non-interactive run, from: keychain or command > arg > env > prompt
+fn resolve_password(password: Option<SecretString>, mode: ProcessMode, opts: &RunOptions) -> Result<SecretString> {
+    if let Some(warning) = cli_password_warning(opts) {
+        eprintln!("{} {}", style("⚠️").yellow().for_stderr(), style(warning).yellow().for_stderr());
+    }
     
-    if files.is_empty() {
-        let file_type = match mode {
-            ProcessMode::Encrypt => ".env",
-            ProcessMode::Decrypt => ".env.enc",
-        };
-        anyhow::bail!("No {} files found in current directory", file_type);
+    match (external_password(mode, opts)?, password) {
+        (Some(password), _) => Ok(password),
+        (None, Some(p)) if opts.password_from_arg => checked(p, "-p/--password", password_min_length(mode)),
+        (None, Some(p)) => checked(p, password_env_var(), password_min_length(mode)),
+        (None, None) => get_password_from_env_or_prompt(mode == ProcessMode::Encrypt),
+    }
+}
+
+/// Whether a one-liner can stream: a stdin -> stdout decrypt with no option that needs the whole file
+fn streams(input: &Path, output: &Path, mode: ProcessMode, opts: &RunOptions) -> bool {
+    mode == ProcessMode::Decrypt
+        && scanner::is_stdio(input)
+        && scanner::is_stdio(output)
+        && !opts.needs_whole_file(mode)
+}
+
+/// Decrypt stdin to stdout one line at a time, keeping memory flat for large inputs
+fn stream_stdio(password: &SecretString, mode: ProcessMode, opts: &RunOptions) -> Result<()> {
+    let limit = if opts.max_file_size == 0 { u64::MAX } else { opts.max_file_size };
+    let reader = std::io::BufReader::new(SizeLimited { inner: std::io::stdin().lock(), limit, remaining: limit });
+    let writer = std::io::BufWriter::new(std::io::stdout().lock());
+    
+    engine::process_reader(reader, writer, password, mode, &opts.process)
+        .map_err(|e| anyhow::anyhow!("stdin: {}", e))?;
+    Ok(())
+}
+
+/// Reader that fails as soon as more than `remaining` bytes come through, so a stream stops
+/// before anything past `--max-file-size` is processed or written
+struct SizeLimited<R> {
+    inner: R,
+    limit: u64,
+    remaining: u64,
+}
+
+impl<R: Read> Read for SizeLimited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.remaining = self.remaining.checked_sub(read as u64).ok_or_else(|| {
+            let message = format!("input is larger than --max-file-size ({} bytes)", self.limit);
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+        })?;
+        Ok(read)
+    }
+}
+
+/// Default output path for an input: environment naming, the output template or the plain default
+/// Decrypt restores a name stored with `--store-name` unless a template is given
+pub fn output_path(input: &Path, mode: ProcessMode, opts: &RunOptions) -> Result<PathBuf> {
+    if let Some(environment) = &opts.environment {
+        return scanner::env_output_name(input, mode, environment);
+    }
+    
+    if mode == ProcessMode::Decrypt && opts.output_template.is_none() {
+        if let Some(stored) = stored_output_path(input) {
+            return Ok(stored);
+        }
+    }
+    
+    scanner::output_name(input, mode, opts.output_template.as_deref(), opts.time_zone)
+}
+
+/// Output path next to a local encrypted input, from its `--store-name` header
+/// The header only ever names a file in the input's own directory, and never the input itself
+fn stored_output_path(input: &Path) -> Option<PathBuf> {
+    if scanner::is_stdio(input) || scanner::is_url(input) {
+        return None;
     }
     
+    let content = fs::read_to_string(input).ok()?;
+    let content = if engine::is_armored(&content) { engine::dearmor(&content).ok()? } else { content };
+    let name = engine::split_name_header(&content).0?;
+    let output = input.with_file_name(name);
+    (output.parent() == input.parent() && output.file_name() != input.file_name()).then_some(output)
+}
+
+/// Warning for a password passed with `-p`, unless `--quiet`
+fn cli_password_warning(opts: &RunOptions) -> Option<String> {
+    (opts.password_from_arg && !opts.quiet).then(|| {
+        format!(
+            "-p/--password leaks into shell history and process listings; prefer {} or --keychain",
+            password_env_var()
+        )
+    })
+}
+
+/// Select multiple files from list with "All files" option
+fn select_files(mode: ProcessMode, opts: &RunOptions) -> Result<Vec<PathBuf>> {
+    let current_dir = env::current_dir()?;
+    let kind = match mode {
+        ProcessMode::Encrypt => ".env files",
+        ProcessMode::Decrypt => ".env.enc files",
+    };
+    let files = scanner::require_files(scanner::Scanner::new(&current_dir, mode).scan(), kind)?;
+    
     // Show found files
     println!("{} Found {} .env file(s) in current directory:", style("📂").cyan(), files.len());
     for file in &files {
         let name = file.file_name().unwrap_or_default().to_string_lossy();
-        let vars = scanner::count_variables(file);
-        println!("  • {} ({} vars)", style(&name).cyan(), vars);
+        let vars = scanner::variables_label(file, &opts.process);
+        println!("  • {} ({})", style(&name).cyan(), vars);
     }
     println!();
     
@@ -272,8 +628,8 @@ fn select_files(mode: ProcessMode) -> Result<Vec<PathBuf>> {
             // Individual selection
             let file_options: Vec<String> = files.iter().map(|p| {
                 let name = p.file_name().unwrap_or_default().to_string_lossy().to_string();
-                let vars = scanner::count_variables(p);
-                format!("{} ({} vars)", name, vars)
+                let vars = scanner::variables_label(p, &opts.process);
+                format!("{} ({})", name, vars)
             }).collect();
             
             let selections = MultiSelect::new()
@@ -294,13 +650,51 @@ fn select_files(mode: ProcessMode) -> Result<Vec<PathBuf>> {
         }
         _ => {
             // Quit
-            anyhow::bail!("Operation cancelled");
+            Err(cancel::Cancelled::ByUser.into())
+        }
+    }
+}
+
+/// Whether existing outputs need a prompt: `--overwrite prompt` without `--yes`
+/// With `--diff` the question is asked after showing the changes instead
+fn prompt_overwrite(skip_confirm: bool, opts: &RunOptions) -> bool {
+    opts.overwrite == OverwritePolicy::Prompt && !skip_confirm && !opts.diff
+}
+
+/// Show what overwriting `output` changes (`--diff`), then confirm under `--overwrite prompt`
+fn confirm_changes(output: &Path, result: &str, opts: &RunOptions) -> Result<()> {
+    if !opts.diff || scanner::is_stream(output) {
+        return Ok(());
+    }
+    let Ok(existing) = fs::read_to_string(output) else {
+        return Ok(());
+    };
+    let existing = if engine::is_armored(&existing) { engine::dearmor(&existing)? } else { existing };
+    
+    if existing == result {
+        println!("{} {} is unchanged", style("ℹ️").cyan(), style(output.display()).cyan());
+        return Ok(());
+    }
+    
+    println!("{} Changes to {}:", style("📝").cyan(), style(output.display()).cyan());
+    print!("{}", diff::masked_diff(&existing, result, &opts.process));
+    
+    if opts.overwrite == OverwritePolicy::Prompt {
+        let confirmed = Confirm::new()
+            .with_prompt("Apply these changes?")
+            .default(false)
+            .interact()?;
+        
+        if !confirmed {
+            return Err(cancel::Cancelled::ByUser.into());
         }
     }
+    
+    Ok(())
 }
 
 /// Confirm file overwrite
-fn confirm_overwrite(path: &Path) -> Result<()> {
+pub fn confirm_overwrite(path: &Path) -> Result<()> {
     println!("{} File {} already exists!", 
         style("⚠️").yellow(),
         style(path.display()).cyan()
@@ -312,150 +706,945 @@ fn confirm_overwrite(path: &Path) -> Result<()> {
         .interact()?;
     
     if !confirmed {
-        anyhow::bail!("Operation cancelled");
+        return Err(cancel::Cancelled::ByUser.into());
     }
     
     Ok(())
 }
 
-/// Get password with confirmation (for encrypt)
-fn get_password_with_confirm() -> Result<SecretString> {
-    // Check env var first
-    if let Ok(pwd) = env::var(PASSWORD_ENV_VAR) {
-        if !pwd.is_empty() {
-            println!("{} Using password from {}", style("🔐").cyan(), PASSWORD_ENV_VAR);
-            return Ok(SecretString::new(pwd));
+/// Warn about keys that are not valid environment variable names
+/// Errors instead when `--strict` is set; warnings go to stderr when the result is written to stdout
+fn warn_key_issues(input: &Path, content: &str, output: &Path, opts: &RunOptions) -> Result<()> {
+    let invalid = engine::validate_key_names(content, &opts.process)
+        .map_err(|e| anyhow::anyhow!("{}: {}", input.display(), e))?;
+    let warn = |message: String| {
+        let line = format!("{} {} {}", style("⚠️").yellow(), style(input.display()).cyan(), message);
+        if scanner::is_stdio(output) || opts.status_to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
         }
-    }
+    };
     
-    loop {
-        let password = Password::new()
-            .with_prompt(format!("{} Enter encryption password", style("🔐").cyan()))
-            .interact()?;
-        
-        if password.is_empty() {
-            println!("{} Password cannot be empty", style("❌").red());
-            continue;
-        }
-        
-        let confirm = Password::new()
-            .with_prompt(format!("{} Confirm password", style("🔐").cyan()))
-            .interact()?;
-        
-        if password != confirm {
-            println!("{} Passwords do not match, please try again", style("❌").red());
-            continue;
-        }
-        
-        return Ok(SecretString::new(password));
-    }
-}
-
-/// Get password without confirmation (for decrypt)
-fn get_password() -> Result<SecretString> {
-    // Check env var first
-    if let Ok(pwd) = env::var(PASSWORD_ENV_VAR) {
-        if !pwd.is_empty() {
-            println!("{} Using password from {}", style("🔐").cyan(), PASSWORD_ENV_VAR);
-            return Ok(SecretString::new(pwd));
-        }
+    for (line, key) in &invalid {
+        warn(format!("line {}: '{}' is not a valid environment variable name", line, key));
     }
     
-    let password = Password::new()
-        .with_prompt(format!("{} Enter decryption password", style("🔐").cyan()))
-        .interact()?;
-    
-    if password.is_empty() {
-        anyhow::bail!("Password cannot be empty");
+    for (key, lines) in engine::find_duplicate_keys(content, &opts.process) {
+        warn(format!("'{}' is defined {} times (lines {}){}",
+            key,
+            lines.len(),
+            join_line_numbers(&lines),
+            if opts.process.dedupe { ", keeping the last" } else { "" }
+        ));
     }
     
-    Ok(SecretString::new(password))
+    Ok(())
 }
 
-/// Get password from env var or prompt
-fn get_password_from_env_or_prompt(with_confirm: bool) -> Result<SecretString> {
-    if let Ok(pwd) = env::var(PASSWORD_ENV_VAR) {
-        if !pwd.is_empty() {
-            return Ok(SecretString::new(pwd));
-        }
-    }
-    
-    if with_confirm {
-        get_password_with_confirm()
-    } else {
-        get_password()
-    }
+/// Format line numbers as a comma-separated list
+fn join_line_numbers(lines: &[usize]) -> String {
+    lines.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ")
 }
 
-/// Process file and save result (verbose, for single file)
-fn process_and_save(
+/// Warn when decrypted output still looks encrypted and confirm before writing
+fn confirm_suspicious_output(
     input: &Path,
-    output: &Path,
-    password: &SecretString,
-    mode: ProcessMode,
+    result: &str,
+    skip_confirm: bool,
+    opts: &RunOptions,
 ) -> Result<()> {
-    let content = std::fs::read_to_string(input)?;
-    
-    let action = match mode {
-        ProcessMode::Encrypt => "Encrypting",
-        ProcessMode::Decrypt => "Decrypting",
-    };
-    
-    println!();
-    println!("{} {}...", style("⏳").cyan(), action);
+    if !engine::looks_still_encrypted(result, &opts.process) {
+        return Ok(());
+    }
     
-    let (result, keys) = engine::process_file(&content, password, mode)?;
+    let warning = format!("{} Decrypted values from {} still look encrypted (wrong password or format?)",
+        style("⚠️").yellow(),
+        style(input.display()).cyan()
+    );
+    if opts.status_to_stderr {
+        eprintln!("{}", warning);
+    } else {
+        println!("{}", warning);
+    }
     
-    // Show processed keys
-    for key in &keys {
-        println!("  {} {}", style("✓").green(), key);
+    if skip_confirm {
+        return Ok(());
     }
     
-    // Write output file
-    let mut file = std::fs::File::create(output)?;
-    file.write_all(result.as_bytes())?;
+    let confirmed = Confirm::new()
+        .with_prompt("Write the output anyway?")
+        .default(false)
+        .interact()?;
     
-    println!();
-    println!("{} Done! Saved: {}", 
-        style("✅").green(),
-        style(output.display()).cyan()
-    );
+    if !confirmed {
+        return Err(cancel::Cancelled::ByUser.into());
+    }
     
     Ok(())
 }
 
-/// Process file and save result (quiet, for batch processing)
-fn process_and_save_quiet(
-    input: &Path,
-    output: &Path,
-    password: &SecretString,
-    mode: ProcessMode,
-) -> Result<()> {
-    let content = std::fs::read_to_string(input)?;
-    let (result, keys) = engine::process_file(&content, password, mode)?;
+/// Confirm deleting encrypted source files after decrypt
+fn confirm_remove_source(inputs: &[PathBuf]) -> Result<()> {
+    println!("{} The following encrypted files will be deleted after decrypting:", style("⚠️").yellow());
+    for path in inputs {
+        println!("  • {}", style(path.file_name().unwrap_or_default().to_string_lossy()).red());
+    }
     
-    // Write output file
-    let mut file = std::fs::File::create(output)?;
-    file.write_all(result.as_bytes())?;
+    let confirmed = Confirm::new()
+        .with_prompt("Delete these files?")
+        .default(false)
+        .interact()?;
     
-    // Show summary for this file
-    let input_name = input.file_name().unwrap_or_default().to_string_lossy();
-    let output_name = output.file_name().unwrap_or_default().to_string_lossy();
-    println!("  {} {} → {} ({} vars)", 
-        style("✓").green(),
-        style(&input_name).cyan(),
-        style(&output_name).yellow(),
-        keys.len()
-    );
+    if !confirmed {
+        return Err(cancel::Cancelled::ByUser.into());
+    }
     
     Ok(())
 }
 
-/// Offer to add encrypted source files to .gitignore
-fn offer_gitignore(input_files: &[PathBuf]) -> Result<()> {
-    // Get filenames to potentially add to gitignore
-    let filenames: Vec<String> = input_files
-        .iter()
+/// Use a custom environment variable for the password instead of `WC_ENVC_PASSWORD`
+pub fn set_password_env(name: &str) {
+    let _ = PASSWORD_ENV.set(name.to_string());
+}
+
+/// Name of the environment variable consulted for the password
+pub fn password_env_var() -> &'static str {
+    PASSWORD_ENV.get().map(String::as_str).unwrap_or(PASSWORD_ENV_VAR)
+}
+
+/// Reject passwords shorter than `minimum` characters (0 disables the check)
+pub fn set_password_min_length(minimum: usize) {
+    let _ = PASSWORD_MIN_LENGTH.set(minimum);
+}
+
+/// Cap the password prompt loops at `attempts` tries (0 never prompts)
+pub fn set_password_attempts(attempts: usize) {
+    let _ = PASSWORD_ATTEMPTS.set(attempts);
+}
+
+/// Configured --password-attempts
+fn password_attempts() -> usize {
+    PASSWORD_ATTEMPTS.get().copied().unwrap_or(DEFAULT_PASSWORD_ATTEMPTS)
+}
+
+/// Configured --password-min-length as it applies to a run in `mode`
+fn password_min_length(mode: ProcessMode) -> usize {
+    minimum_for(mode, PASSWORD_MIN_LENGTH.get().copied().unwrap_or(0))
+}
+
+/// The `configured` minimum only guards new encryptions, so files encrypted before it was
+/// raised can still be decrypted
+fn minimum_for(mode: ProcessMode, configured: usize) -> usize {
+    match mode {
+        ProcessMode::Encrypt => configured,
+        ProcessMode::Decrypt => 0,
+    }
+}
+
+/// Error if `password` (taken from `source`) is shorter than `minimum` characters
+fn check_length(password: &str, source: &str, minimum: usize) -> Result<()> {
+    let length = password.chars().count();
+    if length < minimum {
+        anyhow::bail!(
+            "Password from {} is too short: {} characters, --password-min-length requires {}",
+            source,
+            length,
+            minimum
+        );
+    }
+    Ok(())
+}
+
+/// A password from `source`, after checking it against `minimum`
+fn checked(password: SecretString, source: &str, minimum: usize) -> Result<SecretString> {
+    check_length(password.expose_secret(), source, minimum)?;
+    Ok(password)
+}
+
+/// Password given with -p/--password, checked against --password-min-length when encrypting
+pub fn password_from_arg(password: String, mode: ProcessMode) -> Result<SecretString> {
+    checked(SecretString::new(password), "-p/--password", password_min_length(mode))
+}
+
+/// Password from the configured environment variable, checked against `minimum`
+fn checked_env_password(password: String, minimum: usize) -> Result<SecretString> {
+    checked(SecretString::new(password), password_env_var(), minimum)
+}
+
+/// Read the password from the configured environment variable (empty counts as unset)
+pub fn password_from_env() -> Option<String> {
+    read_password_env(password_env_var())
+}
+
+/// Password from the environment only, for commands git runs without a way to prompt
+pub fn require_env_password(command: &str, mode: ProcessMode) -> Result<SecretString> {
+    let password = password_from_env().ok_or_else(|| {
+        anyhow::anyhow!("{} reads the password from {}; set it before running git", command, password_env_var())
+    })?;
+    checked_env_password(password, password_min_length(mode))
+}
+
+/// Read a non-empty password from the named environment variable
+fn read_password_env(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|pwd| !pwd.is_empty())
+}
+
+/// Ask a running `wc-envc agent` for the password, checked against `minimum`
+/// Reports on stderr so stdout of `get` and `-o -` stays clean
+fn password_from_agent(minimum: usize) -> Result<Option<SecretString>> {
+    let Some(password) = agent::fetch_password() else {
+        return Ok(None);
+    };
+    eprintln!("{} Using password from wc-envc agent", style("🔐").cyan().for_stderr());
+    check_agent_password(password, minimum).map(Some)
+}
+
+/// Check a password the agent served against `minimum`
+fn check_agent_password(password: SecretString, minimum: usize) -> Result<SecretString> {
+    checked(password, "the wc-envc agent", minimum)
+}
+
+/// Get password with confirmation (for encrypt)
+fn get_password_with_confirm() -> Result<SecretString> {
+    let minimum = password_min_length(ProcessMode::Encrypt);
+    
+    // Check env var first
+    if let Some(pwd) = password_from_env() {
+        println!("{} Using password from {}", style("🔐").cyan(), password_env_var());
+        return checked_env_password(pwd, minimum);
+    }
+    
+    if let Some(password) = password_from_agent(minimum)? {
+        return Ok(password);
+    }
+    
+    prompt_with_confirm(password_attempts(), minimum, |prompt| {
+        Ok(Password::new().with_prompt(prompt).interact()?)
+    })
+}
+
+/// Ask for a password and its confirmation through `ask`, giving up after `attempts` failed tries
+/// so a CI job that reaches the prompt fails instead of hanging
+fn prompt_with_confirm(
+    attempts: usize,
+    minimum: usize,
+    mut ask: impl FnMut(&str) -> Result<String>,
+) -> Result<SecretString> {
+    for _ in 0..attempts {
+        let password = ask(&format!("{} Enter encryption password", style("🔐").cyan()))?;
+        
+        if password.is_empty() {
+            println!("{} Password cannot be empty", style("❌").red());
+            continue;
+        }
+        
+        if let Err(e) = check_length(&password, "the prompt", minimum) {
+            println!("{} {}", style("❌").red(), e);
+            continue;
+        }
+        
+        let confirm = ask(&format!("{} Confirm password", style("🔐").cyan()))?;
+        
+        if password != confirm {
+            println!("{} Passwords do not match, please try again", style("❌").red());
+            continue;
+        }
+        
+        return Ok(SecretString::new(password));
+    }
+    
+    anyhow::bail!("No password confirmed after {} attempt(s) (see --password-attempts)", attempts)
+}
+
+/// Get password without confirmation (for decrypt, so --password-min-length does not apply)
+fn get_password() -> Result<SecretString> {
+    // Check env var first
+    if let Some(pwd) = password_from_env() {
+        println!("{} Using password from {}", style("🔐").cyan(), password_env_var());
+        return Ok(SecretString::new(pwd));
+    }
+    
+    if let Some(password) = password_from_agent(0)? {
+        return Ok(password);
+    }
+    
+    // A single try; --password-attempts only matters here when it rules out prompting
+    if password_attempts() == 0 {
+        anyhow::bail!("No password given and --password-attempts is 0");
+    }
+    
+    let password = Password::new()
+        .with_prompt(format!("{} Enter decryption password", style("🔐").cyan()))
+        .interact()?;
+    
+    if password.is_empty() {
+        anyhow::bail!("Password cannot be empty");
+    }
+    
+    Ok(SecretString::new(password))
+}
+
+/// Get password from env var or prompt; `with_confirm` is for encrypting and applies --password-min-length
+pub fn get_password_from_env_or_prompt(with_confirm: bool) -> Result<SecretString> {
+    if let Some(pwd) = password_from_env() {
+        let mode = if with_confirm { ProcessMode::Encrypt } else { ProcessMode::Decrypt };
+        return checked_env_password(pwd, password_min_length(mode));
+    }
+    
+    if with_confirm {
+        get_password_with_confirm()
+    } else {
+        get_password()
+    }
+}
+
+/// Password from `--keychain` or `--password-command`, if either was given
+fn external_password(mode: ProcessMode, opts: &RunOptions) -> Result<Option<SecretString>> {
+    let minimum = password_min_length(mode);
+    match (&opts.keychain, &opts.password_command) {
+        (Some(project), _) => get_password_from_keychain(project, minimum).map(Some),
+        (None, Some(command)) => get_password_from_command(command, minimum).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Run a shell command and use its trimmed stdout as the password, checked against `minimum`
+/// stderr and stdin stay attached so the tool can prompt or report errors itself
+fn get_password_from_command(command: &str, minimum: usize) -> Result<SecretString> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    
+    let output = shell
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run --password-command: {}", e))?;
+    if !output.status.success() {
+        anyhow::bail!("--password-command failed ({})", output.status);
+    }
+    
+    let mut stdout = String::from_utf8(output.stdout)
+        .map_err(|_| anyhow::anyhow!("--password-command printed a password that is not valid UTF-8"))?;
+    let password = stdout.trim().to_string();
+    stdout.zeroize();
+    
+    if password.is_empty() {
+        anyhow::bail!("--password-command printed an empty password");
+    }
+    checked(SecretString::new(password), "--password-command", minimum)
+}
+
+/// Read a project's password from the OS keychain (macOS Keychain, Windows Credential Manager)
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn get_password_from_keychain(project: &str, minimum: usize) -> Result<SecretString> {
+    let password = keyring::Entry::new(KEYCHAIN_SERVICE, project)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| anyhow::anyhow!("Keychain entry '{}' for {}: {}", project, KEYCHAIN_SERVICE, e))?;
+    
+    checked(SecretString::new(password), "the keychain", minimum)
+}
+
+/// No native password store on this platform
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn get_password_from_keychain(project: &str, _minimum: usize) -> Result<SecretString> {
+    anyhow::bail!(
+        "--keychain {} is only supported on macOS and Windows; use -p or {} instead",
+        project,
+        password_env_var()
+    )
+}
+
+/// Process file and save result (verbose, for single file)
+/// Status output is suppressed when writing the result to stdout
+fn process_and_save(
+    input: &Path,
+    content: &str,
+    output: &Path,
+    password: &SecretString,
+    mode: ProcessMode,
+    skip_confirm: bool,
+    opts: &RunOptions,
+) -> Result<()> {
+    let verbose = !scanner::is_stdio(output);
+    let status = |line: String| {
+        if opts.status_to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+    warn_key_issues(input, content, output, opts)?;
+    
+    let action = match mode {
+        ProcessMode::Encrypt => "Encrypting",
+        ProcessMode::Decrypt => "Decrypting",
+    };
+    
+    if verbose {
+        status(String::new());
+        status(format!("{} {}...", style("⏳").cyan(), action));
+    }
+    
+    let Processed { result, keys, inlined } = process_timed(input, content, password, mode, opts)?;
+    
+    // Show processed keys
+    if verbose {
+        for key in &keys {
+            status(format!("  {} {}", style("✓").green(), key));
+        }
+    }
+    
+    if mode == ProcessMode::Decrypt {
+        confirm_suspicious_output(input, &result, skip_confirm, opts)?;
+    }
+    
+    confirm_changes(output, &result, opts)?;
+    
+    let _guard = cancel::WriteGuard::new();
+    write_output(output, &result, mode, opts)?;
+    write_inline_files(&inlined, opts)?;
+    preserve_mtime(input, output, opts)?;
+    
+    if verbose {
+        status(String::new());
+        status(format!("{} Done! Saved: {}", style("✅").green(), style(output.display()).cyan()));
+    }
+    
+    if opts.remove_source && mode == ProcessMode::Decrypt {
+        std::fs::remove_file(input)?;
+        if verbose {
+            status(format!("{} Removed: {}", style("🗑️").dim(), style(input.display()).cyan()));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Build the worker pool for batch processing (0 threads = rayon's automatic choice)
+fn build_pool(threads: usize) -> Result<rayon::ThreadPool> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()?;
+    Ok(pool)
+}
+
+/// Process files in parallel, then save results one by one in input order
+/// Prompts, writes and summaries stay sequential; the first failure stops further writes
+/// unless `keep_going` is set, in which case failures are collected and reported at the end
+fn process_batch(
+    inputs: &[PathBuf],
+    outputs: &[PathBuf],
+    password: &SecretString,
+    mode: ProcessMode,
+    skip_confirm: bool,
+    opts: &RunOptions,
+) -> Result<usize> {
+    process_batch_until(inputs, outputs, password, mode, skip_confirm, opts, &cancel::is_cancelled)
+}
+
+/// Batch processing that checks `cancelled` before saving each file
+fn process_batch_until(
+    inputs: &[PathBuf],
+    outputs: &[PathBuf],
+    password: &SecretString,
+    mode: ProcessMode,
+    skip_confirm: bool,
+    opts: &RunOptions,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<usize> {
+    let mut reports = Vec::new();
+    
+    // Skip sources unchanged since the last encrypt (see cache.rs) and, with
+    // `--overwrite never`, inputs whose output already exists
+    let mut pending = Vec::new();
+    let mut skipped = Vec::new();
+    for (input, output) in inputs.iter().zip(outputs) {
+        if opts.overwrite == OverwritePolicy::Never && scanner::output_exists(output) {
+            skipped.push(format!("  {} {} exists, skipped", 
+                style("⏭️").dim(),
+                style(output.file_name().unwrap_or_default().to_string_lossy()).cyan()
+            ));
+            reports.push(file_report(input, output, 0, FileStatus::Skipped, Duration::ZERO));
+            continue;
+        }
+        // Taken before processing, so a source edited meanwhile is re-encrypted next time
+        let entry = (mode == ProcessMode::Encrypt).then(|| cache::entry(input, output, password, opts)).flatten();
+        if let (false, Some(entry)) = (opts.force, &entry) {
+            if cache::is_unchanged(input, output, entry) {
+                skipped.push(format!("  {} {} unchanged, skipped", 
+                    style("⏭️").dim(),
+                    style(input.file_name().unwrap_or_default().to_string_lossy()).cyan()
+                ));
+                reports.push(file_report(input, output, 0, FileStatus::Skipped, Duration::ZERO));
+                continue;
+            }
+        }
+        pending.push((input, output, entry));
+    }
+    
+    // Nothing would be written: one line instead of a skip line per file
+    if pending.is_empty() && !skipped.is_empty() {
+        if let Some(path) = &opts.report {
+            report::write_report(path, mode, &reports, opts.time_zone)?;
+        }
+        if !opts.quiet {
+            println!("{} {}", style("ℹ️").cyan(), nothing_to_do(skipped.len()));
+        }
+        return Ok(0);
+    }
+    for line in skipped {
+        println!("{}", line);
+    }
+    
+    let pool = build_pool(opts.threads)?;
+    let processed: Vec<_> = pool.install(|| {
+        pending
+            .par_iter()
+            .map(|(input, _, _)| {
+                let started = Instant::now();
+                let processed = read_input(input, mode, opts).and_then(|content| {
+                    if mode == ProcessMode::Decrypt {
+                        engine::validate_encrypted_file(&content, &opts.process)
+                            .map_err(|e| anyhow::anyhow!("{}: {}", input.display(), e))?;
+                    }
+                    let processed = process_timed(input, &content, password, mode, opts)?;
+                    Ok((content, processed))
+                });
+                (processed, started.elapsed())
+            })
+            .collect()
+    });
+    
+    let total = pending.len();
+    let mut written = 0;
+    let mut failed = Vec::new();
+    let mut stopped = None;
+    
+    let _guard = cancel::WriteGuard::new();
+    for ((input, output, entry), (processed, elapsed)) in pending.into_iter().zip(processed) {
+        if cancelled() {
+            stopped = Some(cancel::Cancelled::Interrupted.into());
+            break;
+        }
+        
+        let saved = processed.and_then(|(content, processed)| {
+            save_quiet(input, output, &content, &processed, mode, skip_confirm, opts)?;
+            if let Some(entry) = &entry {
+                cache::record(input, entry)?;
+            }
+            Ok(processed.keys.len())
+        });
+        
+        match saved {
+            Ok(vars) => {
+                reports.push(file_report(input, output, vars, FileStatus::Ok, elapsed));
+                written += 1;
+            }
+            Err(e) => {
+                let status = failed_status(&e);
+                let declined = status == FileStatus::Skipped;
+                reports.push(file_report(input, output, 0, status, elapsed));
+                if !opts.keep_going {
+                    stopped = Some(e);
+                    break;
+                }
+                if declined {
+                    println!("  {} {} declined, skipped",
+                        style("⏭️").dim(),
+                        style(input.file_name().unwrap_or_default().to_string_lossy()).cyan()
+                    );
+                    continue;
+                }
+                eprintln!("  {} {}", style("✗").red().for_stderr(), style(&e).red().for_stderr());
+                failed.push(input.file_name().unwrap_or_default().to_string_lossy().to_string());
+            }
+        }
+    }
+    
+    // The report covers failed and interrupted runs too
+    if let Some(path) = &opts.report {
+        report::write_report(path, mode, &reports, opts.time_zone)?;
+    }
+    
+    if let Some(e) = stopped {
+        return Err(e);
+    }
+    
+    if !failed.is_empty() {
+        anyhow::bail!("{} of {} file(s) failed: {}", failed.len(), total, failed.join(", "));
+    }
+    
+    Ok(written)
+}
+
+/// Report status for a file that was not saved: declining one of its prompts skips it,
+/// anything else fails it
+fn failed_status(e: &anyhow::Error) -> FileStatus {
+    match e.downcast_ref::<cancel::Cancelled>() {
+        Some(cancel::Cancelled::ByUser) => FileStatus::Skipped,
+        _ => FileStatus::Failed(e.to_string()),
+    }
+}
+
+/// Summary for a batch where every file was skipped
+fn nothing_to_do(skipped: usize) -> String {
+    format!("Nothing to do: {} file(s) already up to date", skipped)
+}
+
+/// Report entry for one batch file
+fn file_report(input: &Path, output: &Path, vars: usize, status: FileStatus, elapsed: Duration) -> FileReport {
+    FileReport { input: input.to_path_buf(), output: output.to_path_buf(), vars, status, elapsed }
+}
+
+/// Save a processed file (quiet, for batch processing)
+fn save_quiet(
+    input: &Path,
+    output: &Path,
+    content: &str,
+    processed: &Processed,
+    mode: ProcessMode,
+    skip_confirm: bool,
+    opts: &RunOptions,
+) -> Result<()> {
+    let result = &processed.result;
+    warn_key_issues(input, content, output, opts)?;
+    
+    if mode == ProcessMode::Decrypt {
+        confirm_suspicious_output(input, result, skip_confirm, opts)?;
+    }
+    
+    confirm_changes(output, result, opts)?;
+    write_output(output, result, mode, opts)?;
+    write_inline_files(&processed.inlined, opts)?;
+    preserve_mtime(input, output, opts)?;
+    
+    if opts.remove_source && mode == ProcessMode::Decrypt {
+        std::fs::remove_file(input)?;
+    }
+    
+    // Show summary for this file
+    let input_name = input.file_name().unwrap_or_default().to_string_lossy();
+    let output_name = output.file_name().unwrap_or_default().to_string_lossy();
+    println!("  {} {} → {} ({} vars)", 
+        style("✓").green(),
+        style(&input_name).cyan(),
+        style(&output_name).yellow(),
+        processed.keys.len()
+    );
+    
+    Ok(())
+}
+
+/// File name to record with `--store-name` when encrypting a local file
+fn stored_name<'a>(input: &'a Path, mode: ProcessMode, opts: &RunOptions) -> Option<&'a str> {
+    if !opts.store_name || mode != ProcessMode::Encrypt || scanner::is_stdio(input) {
+        return None;
+    }
+    input.file_name()?.to_str()
+}
+
+/// Output of `process_timed`
+struct Processed {
+    result: String,
+    keys: Vec<String>,
+    /// `--inline-files` files to write along with the output
+    inlined: Vec<InlineFile>,
+}
+
+/// Run `engine::process_file`, reporting timings to stderr if requested
+/// Errors are prefixed with the input path so batch failures are easy to locate
+fn process_timed(
+    input: &Path,
+    content: &str,
+    password: &SecretString,
+    mode: ProcessMode,
+    opts: &RunOptions,
+) -> Result<Processed> {
+    let run = || {
+        // A `.wc-envc-keys` file next to the input decides which values get encrypted
+        let process = match mode {
+            ProcessMode::Encrypt => keypolicy::options_for(input, &opts.process)?,
+            ProcessMode::Decrypt => opts.process.clone(),
+        };
+        if let Some(policy) = &process.key_policy {
+            let (encrypted, plaintext) = keypolicy::coverage(policy, content, &process)?;
+            if !opts.quiet {
+                eprintln!(
+                    "{} {} applied to {}: {} value(s) encrypted, {} left in plaintext",
+                    style("🔑").cyan().for_stderr(),
+                    keypolicy::POLICY_FILE,
+                    style(input.display()).cyan().for_stderr(),
+                    encrypted,
+                    plaintext
+                );
+            }
+        }
+        engine::process_file(content, password, mode, &process)
+            .map_err(anyhow::Error::from)
+            .and_then(|(result, keys)| {
+                if opts.verify && mode == ProcessMode::Encrypt {
+                    engine::verify_roundtrip(content, &result, password, &process)?;
+                }
+                let (result, inlined) = match mode {
+                    ProcessMode::Decrypt => engine::take_inline_files(&result, &process)?,
+                    ProcessMode::Encrypt => (result, Vec::new()),
+                };
+                let result = if mode == ProcessMode::Decrypt && opts.process.follow_includes {
+                    with_includes(input, &result, password, opts)?
+                } else {
+                    result
+                };
+                let result = match opts.interpolate.filter(|_| mode == ProcessMode::Decrypt) {
+                    Some(settings) => interpolate::apply(input, &result, settings, opts.quiet, &opts.process)?,
+                    None => result,
+                };
+                let result = if mode == ProcessMode::Decrypt && opts.docker_env_file {
+                    format::to_docker_env_file(&result, &opts.process)?
+                } else {
+                    result
+                };
+                let result = match stored_name(input, mode, opts) {
+                    Some(name) => engine::add_name_header(&result, name),
+                    None => result,
+                };
+                // Last, so includes, interpolation and --env-file-stdout can't undo --final-newline
+                let result = opts.process.final_newline.apply(&result);
+                Ok(Processed { result, keys, inlined })
+            })
+            .map_err(|e| anyhow::anyhow!("{}: {}", input.display(), e))
+    };
+    
+    if !opts.time {
+        return run();
+    }
+    
+    // Only the key derivations this file really does, so --time adds no work of its own
+    let started = Instant::now();
+    let (processed, key_elapsed) = engine::with_key_derivation_time(run);
+    let processed = processed?;
+    let total_elapsed = started.elapsed();
+    
+    if !opts.quiet {
+        let name = input.file_name().unwrap_or_default().to_string_lossy();
+        eprintln!("{}", format_timing(&name, key_elapsed, total_elapsed));
+    }
+    Ok(processed)
+}
+
+/// Format the `--time` report line
+fn format_timing(name: &str, key_elapsed: Duration, total_elapsed: Duration) -> String {
+    format!("⏱️  {}: key derivation {:.2?}, total {:.2?}", name, key_elapsed, total_elapsed)
+}
+
+/// Ciphers to time for a decrypt `--key-derivation-check`: the ones the inputs' headers name
+/// (one per key derivation); the default when there are no inputs yet or only stdin, which can't be read twice
+pub fn header_ciphers(inputs: &[PathBuf], opts: &RunOptions) -> Result<Vec<FileCipher>> {
+    let mut ciphers: Vec<FileCipher> = Vec::new();
+    for input in inputs.iter().filter(|input| !scanner::is_stdio(input)) {
+        let content = read_input(input, ProcessMode::Decrypt, opts)?;
+        let cipher = engine::file_cipher(&content).map_err(|e| anyhow::anyhow!("{}: {}", input.display(), e))?;
+        if !ciphers.iter().any(|seen| seen.kdf() == cipher.kdf()) {
+            ciphers.push(cipher);
+        }
+    }
+    if ciphers.is_empty() {
+        ciphers.push(FileCipher::default());
+    }
+    Ok(ciphers)
+}
+
+/// Time the key derivation of `cipher` on this machine and warn if it is too cheap
+/// The cost does not depend on the password, so a fixed sample password is used
+pub fn check_key_derivation(cipher: &FileCipher) -> Result<()> {
+    let sample = SecretString::new("wc-envc key derivation check".to_string());
+    let started = Instant::now();
+    cipher.build(&sample)?;
+    let elapsed = started.elapsed();
+    
+    eprintln!("⏱️  {} key derivation ({}): {:.2?}", cipher.cipher.name(), cipher.kdf(), elapsed);
+    if let Some(warning) = key_derivation_warning(elapsed, MIN_KEY_DERIVATION) {
+        eprintln!("{} {}", style("⚠️").yellow().for_stderr(), style(warning).yellow().for_stderr());
+    }
+    
+    Ok(())
+}
+
+/// Warning for a key derivation that took less than `minimum`
+fn key_derivation_warning(elapsed: Duration, minimum: Duration) -> Option<String> {
+    (elapsed < minimum).then(|| {
+        format!(
+            "Key derivation took {:.2?}, below {:.0?}: guessing passwords is cheap, so use a long random \
+             password (20+ characters)",
+            elapsed, minimum
+        )
+    })
+}
+
+/// Read raw input from a file, stdin (`-`) or an http(s) URL
+/// Inputs larger than `max_size` bytes are rejected (0 = no limit)
+fn read_source(input: &Path, max_size: u64) -> Result<String> {
+    let limit = if max_size == 0 { u64::MAX } else { max_size };
+    
+    if scanner::is_stdio(input) {
+        return read_stream(std::io::stdin(), max_size);
+    }
+    
+    if scanner::is_url(input) {
+        return fetch_url(&input.to_string_lossy(), limit);
+    }
+    
+    scanner::check_input_file(input)?;
+    scanner::check_file_size(input, max_size)?;
+    Ok(std::fs::read_to_string(input)?)
+}
+
+/// Read piped input (stdin) in full; there is no file to check or size up front
+fn read_stream(reader: impl Read, max_size: u64) -> Result<String> {
+    let limit = if max_size == 0 { u64::MAX } else { max_size };
+    let mut content = String::new();
+    reader.take(limit.saturating_add(1)).read_to_string(&mut content)?;
+    if content.len() as u64 > limit {
+        anyhow::bail!("stdin: input is larger than --max-file-size ({} bytes)", max_size);
+    }
+    Ok(content)
+}
+
+/// Fetch a remote file over HTTP(S)
+#[cfg(feature = "network")]
+fn fetch_url(url: &str, limit: u64) -> Result<String> {
+    let content = ureq::get(url)
+        .config()
+        .timeout_global(Some(Duration::from_secs(30)))
+        .build()
+        .call()
+        .map_err(|e| anyhow::anyhow!("Failed to fetch {}: {}", url, e))?
+        .into_body()
+        .with_config()
+        .limit(limit)
+        .read_to_string()
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", url, e))?;
+    
+    Ok(content)
+}
+
+/// URL inputs need the optional `network` feature
+#[cfg(not(feature = "network"))]
+fn fetch_url(url: &str, _limit: u64) -> Result<String> {
+    anyhow::bail!("Cannot fetch {}: rebuild with `--features network` to read from URLs", url)
+}
+
+/// Merge the files named by `# include:` lines into decrypted `content` (`--follow-includes`)
+/// Included `.env*.enc` files are decrypted with the same password, others are read as they are
+fn with_includes(input: &Path, content: &str, password: &SecretString, opts: &RunOptions) -> Result<String> {
+    let process = ProcessOptions { follow_includes: false, normalize: false, ..opts.process.clone() };
+    let read_opts = RunOptions { checksum: false, ..opts.clone() };
+    let merged = include::resolve(input, content, &process, &mut |path| {
+        let content = read_input(path, ProcessMode::Decrypt, &read_opts)?;
+        let encrypted = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| scanner::is_env_file_for(name, ProcessMode::Decrypt));
+        if !encrypted {
+            return Ok(content);
+        }
+        Ok(engine::process_file(&content, password, ProcessMode::Decrypt, &process)?.0)
+    })?;
+    
+    Ok(if opts.process.normalize { engine::normalize(&merged, &opts.process) } else { merged })
+}
+
+/// Read input for processing
+/// On encrypt, converts JSON/YAML input to .env; on decrypt, verifies the checksum sidecar
+/// if requested and unwraps armored input
+pub fn read_input(input: &Path, mode: ProcessMode, opts: &RunOptions) -> Result<String> {
+    let content = read_source(input, opts.max_file_size)?;
+    
+    if mode == ProcessMode::Encrypt {
+        return format::to_dotenv(&content, opts.process.input_format)
+            .map_err(|e| anyhow::anyhow!("{}: {}", input.display(), e));
+    }
+    
+    if opts.checksum {
+        let sidecar = scanner::checksum_path(input);
+        let checksum = std::fs::read_to_string(&sidecar)
+            .map_err(|_| anyhow::anyhow!("Checksum file not found: {}", sidecar.display()))?;
+        engine::verify_checksum(content.as_bytes(), &checksum)?;
+    }
+    
+    if engine::is_armored(&content) {
+        return Ok(engine::dearmor(&content)?);
+    }
+    
+    Ok(content)
+}
+
+/// Write output file, armoring it and adding a checksum sidecar on encrypt if requested
+fn write_output(output: &Path, result: &str, mode: ProcessMode, opts: &RunOptions) -> Result<()> {
+    let armored;
+    let result = if opts.armor && mode == ProcessMode::Encrypt {
+        armored = engine::armor(result);
+        armored.as_str()
+    } else {
+        result
+    };
+    
+    if scanner::is_stdio(output) {
+        std::io::stdout().write_all(result.as_bytes())?;
+        return Ok(());
+    }
+    
+    // Replace a symlinked output with a regular file so a shared target is never overwritten
+    let is_symlink = std::fs::symlink_metadata(output)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false);
+    if is_symlink && !opts.dereference {
+        std::fs::remove_file(output)?;
+    }
+    
+    let mut file = std::fs::File::create(output)?;
+    file.write_all(result.as_bytes())?;
+    
+    if opts.checksum && mode == ProcessMode::Encrypt {
+        let filename = output.file_name().unwrap_or_default().to_string_lossy();
+        let line = engine::checksum_line(result.as_bytes(), &filename);
+        std::fs::write(scanner::checksum_path(output), line)?;
+    }
+    
+    Ok(())
+}
+
+/// Write the files decoded from `--inline-files` values, once the output itself is written
+fn write_inline_files(files: &[InlineFile], opts: &RunOptions) -> Result<()> {
+    if let (Some(base), false) = (&opts.process.inline_files, files.is_empty()) {
+        engine::write_inline_files(base, files)?;
+    }
+    Ok(())
+}
+
+/// Copy the input's modification time to the output (`--preserve-mtime`)
+/// Streams and URLs have no file time to copy, so they are left alone
+fn preserve_mtime(input: &Path, output: &Path, opts: &RunOptions) -> Result<()> {
+    if !opts.preserve_mtime || scanner::is_stream(output) || scanner::is_stdio(input) || scanner::is_url(input) {
+        return Ok(());
+    }
+    
+    let mtime = FileTime::from_last_modification_time(&std::fs::metadata(input)?);
+    filetime::set_file_mtime(output, mtime)?;
+    Ok(())
+}
+
+/// Offer to add encrypted source files to .gitignore
+fn offer_gitignore(input_files: &[PathBuf], comment: &str) -> Result<()> {
+    // Get filenames to potentially add to gitignore
+    let filenames: Vec<String> = input_files
+        .iter()
         .filter_map(|p| p.file_name())
         .map(|n| n.to_string_lossy().to_string())
         .collect();
@@ -500,25 +1689,750 @@ fn offer_gitignore(input_files: &[PathBuf]) -> Result<()> {
         return Ok(());
     }
     
-    // Append to .gitignore
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&gitignore_path)?;
+    fs::write(&gitignore_path, update_gitignore(&existing_content, &missing, comment))?;
     
-    // Add newline if file doesn't end with one
-    if !existing_content.is_empty() && !existing_content.ends_with('\n') {
-        writeln!(file)?;
-    }
+    println!("{} Added {} file(s) to .gitignore", style("✅").green(), missing.len());
     
-    // Add comment and files
-    writeln!(file, "\n# Plain .env files (secrets - do not commit)")?;
-    for f in &missing {
-        writeln!(file, "{}", f)?;
+    Ok(())
+}
+
+/// Normalize the .gitignore comment option into a full comment line (or none)
+fn gitignore_comment(text: Option<&str>) -> String {
+    match text.map(str::trim) {
+        None => GITIGNORE_COMMENT.to_string(),
+        Some("") => String::new(),
+        Some(text) if text.starts_with('#') => text.to_string(),
+        Some(text) => format!("# {}", text),
     }
+}
+
+/// Add entries to .gitignore content
+/// Entries join the existing block under `comment` if present, otherwise a new block is appended
+fn update_gitignore(existing: &str, entries: &[&String], comment: &str) -> String {
+    let mut lines: Vec<String> = existing.lines().map(String::from).collect();
+    let block_start = if comment.is_empty() {
+        None
+    } else {
+        lines.iter().position(|line| line.trim() == comment)
+    };
     
-    println!("{} Added {} file(s) to .gitignore", style("✅").green(), missing.len());
+    match block_start {
+        Some(start) => {
+            // The block ends at the next blank line
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| line.trim().is_empty())
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            lines.splice(end..end, entries.iter().map(|e| e.to_string()));
+        }
+        None => {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            if !comment.is_empty() {
+                lines.push(comment.to_string());
+            }
+            lines.extend(entries.iter().map(|e| e.to_string()));
+        }
+    }
     
-    Ok(())
+    lines.join("\n") + "\n"
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ValueCipher;
+    
+    fn write_encrypted(dir: &Path, password: &SecretString) -> PathBuf {
+        let input = dir.join(".env.enc");
+        let content = format!("DB_HOST={}", engine::derive_key(password).encrypt("localhost"));
+        fs::write(&input, content).unwrap();
+        input
+    }
+    
+    #[test]
+    fn test_encrypt_stdin_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let output = dir.path().join(".env.enc");
+        let opts = RunOptions::default();
+        
+        let content = read_stream("A=1\nB=two\n".as_bytes(), opts.max_file_size).unwrap();
+        process_and_save(Path::new("-"), &content, &output, &password, ProcessMode::Encrypt, true, &opts).unwrap();
+        
+        let encrypted = read_input(&output, ProcessMode::Decrypt, &opts).unwrap();
+        let (decrypted, _) = engine::process_file(&encrypted, &password, ProcessMode::Decrypt, &opts.process).unwrap();
+        assert_eq!(decrypted, "A=1\nB=two\n");
+        assert!(read_stream("A=1\n".as_bytes(), 2).is_err());
+    }
+    
+    #[test]
+    #[cfg(unix)]
+    fn test_decrypt_into_fifo() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let input = write_encrypted(dir.path(), &password);
+        let fifo = dir.path().join("out");
+        assert!(std::process::Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        assert!(scanner::is_stream(&fifo));
+        assert!(!scanner::output_exists(&fifo));
+        
+        let reader = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || fs::read_to_string(fifo).unwrap())
+        };
+        
+        // --diff must not try to read the existing "output" back, and --preserve-mtime leaves the FIFO alone
+        let old = FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(&input, old).unwrap();
+        let opts = RunOptions { diff: true, preserve_mtime: true, ..Default::default() };
+        let content = fs::read_to_string(&input).unwrap();
+        process_and_save(&input, &content, &fifo, &password, ProcessMode::Decrypt, true, &opts).unwrap();
+        
+        assert_eq!(reader.join().unwrap(), "DB_HOST=localhost");
+        assert_ne!(FileTime::from_last_modification_time(&fs::metadata(&fifo).unwrap()), old);
+    }
+    
+    #[test]
+    fn test_remove_source_on_success() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let input = write_encrypted(dir.path(), &password);
+        let output = dir.path().join(".env");
+        let opts = RunOptions { remove_source: true, ..Default::default() };
+        
+        process_batch(
+            std::slice::from_ref(&input),
+            std::slice::from_ref(&output),
+            &password,
+            ProcessMode::Decrypt,
+            false,
+            &opts,
+        )
+        .unwrap();
+        
+        assert!(!input.exists());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "DB_HOST=localhost");
+    }
+    
+    #[test]
+    fn test_remove_source_kept_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = write_encrypted(dir.path(), &SecretString::new("correct".to_string()));
+        let output = dir.path().join(".env");
+        let opts = RunOptions { remove_source: true, ..Default::default() };
+        let wrong = SecretString::new("wrong".to_string());
+        
+        assert!(process_batch(
+            std::slice::from_ref(&input),
+            std::slice::from_ref(&output),
+            &wrong,
+            ProcessMode::Decrypt,
+            false,
+            &opts,
+        )
+        .is_err());
+        
+        assert!(input.exists());
+        assert!(!output.exists());
+    }
+    
+    #[test]
+    fn test_single_thread_batch() {
+        assert_eq!(build_pool(1).unwrap().current_num_threads(), 1);
+        assert!(build_pool(0).unwrap().current_num_threads() >= 1);
+        
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let input = write_encrypted(dir.path(), &password);
+        let output = dir.path().join(".env");
+        let opts = RunOptions { threads: 1, ..Default::default() };
+        
+        process_batch(&[input], std::slice::from_ref(&output), &password, ProcessMode::Decrypt, false, &opts)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "DB_HOST=localhost");
+    }
+    
+    #[test]
+    fn test_cancel_between_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let first = write_encrypted(dir.path(), &password);
+        let second = dir.path().join(".env.staging.enc");
+        fs::copy(&first, &second).unwrap();
+        
+        let inputs = [first, second];
+        let outputs = [dir.path().join(".env"), dir.path().join(".env.staging")];
+        
+        // Ctrl-C arrives while the first file is being saved
+        let checks = std::cell::Cell::new(0);
+        let cancelled = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 1
+        };
+        let opts = RunOptions::default();
+        let mode = ProcessMode::Decrypt;
+        let err = process_batch_until(&inputs, &outputs, &password, mode, false, &opts, &cancelled)
+            .unwrap_err();
+        
+        assert!(err.downcast_ref::<cancel::Cancelled>().is_some());
+        assert!(outputs[0].exists());
+        assert!(!outputs[1].exists());
+    }
+    
+    #[test]
+    fn test_gitignore_comment_not_duplicated() {
+        let comment = gitignore_comment(None);
+        let first = update_gitignore("target/\n", &[&".env".to_string()], &comment);
+        let second = update_gitignore(&first, &[&".env.local".to_string()], &comment);
+        
+        assert_eq!(second.matches(GITIGNORE_COMMENT).count(), 1);
+        assert_eq!(second, format!("target/\n\n{}\n.env\n.env.local\n", GITIGNORE_COMMENT));
+        
+        let bare = update_gitignore("", &[&".env".to_string()], &gitignore_comment(Some("")));
+        assert_eq!(bare, ".env\n");
+    }
+    
+    #[test]
+    fn test_unchanged_file_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("secret".to_string());
+        let input = dir.path().join(".env");
+        let output = dir.path().join(".env.enc");
+        fs::write(&input, "DB_HOST=localhost").unwrap();
+        let (inputs, outputs) = ([input.clone()], [output.clone()]);
+        let opts = RunOptions::default();
+        
+        assert_eq!(process_batch(&inputs, &outputs, &password, ProcessMode::Encrypt, false, &opts).unwrap(), 1);
+        let first = fs::read_to_string(&output).unwrap();
+        
+        // Unchanged: the existing ciphertext (random IV per run) is left untouched
+        assert_eq!(process_batch(&inputs, &outputs, &password, ProcessMode::Encrypt, false, &opts).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&output).unwrap(), first);
+        
+        let forced = RunOptions { force: true, ..Default::default() };
+        fs::write(&output, "stale").unwrap();
+        process_batch(&inputs, &outputs, &password, ProcessMode::Encrypt, false, &forced).unwrap();
+        assert_ne!(fs::read_to_string(&output).unwrap(), "stale");
+    }
+    
+    #[test]
+    fn test_noop_batch_is_one_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("secret".to_string());
+        let inputs = [dir.path().join(".env"), dir.path().join(".env.staging")];
+        let outputs = [dir.path().join(".env.enc"), dir.path().join(".env.staging.enc")];
+        for input in &inputs {
+            fs::write(input, "DB_HOST=localhost").unwrap();
+        }
+        let report = dir.path().join("report.json");
+        let opts = RunOptions { report: Some(report.clone()), ..Default::default() };
+        
+        assert_eq!(process_batch(&inputs, &outputs, &password, ProcessMode::Encrypt, false, &opts).unwrap(), 2);
+        assert_eq!(process_batch(&inputs, &outputs, &password, ProcessMode::Encrypt, false, &opts).unwrap(), 0);
+        assert_eq!(nothing_to_do(2), "Nothing to do: 2 file(s) already up to date");
+        assert!(fs::read_to_string(&report).unwrap().contains("skipped"));
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_password_from_command() {
+        let password = get_password_from_command("printf ' s3cret\\n'", 0).unwrap();
+        assert_eq!(password.expose_secret(), "s3cret");
+        
+        assert!(get_password_from_command("exit 3", 0).unwrap_err().to_string().contains("failed"));
+        assert!(get_password_from_command("true", 0).unwrap_err().to_string().contains("empty"));
+    }
+    
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    #[test]
+    fn test_keychain_unsupported() {
+        let err = get_password_from_keychain("my-project", 0).unwrap_err();
+        assert!(err.to_string().contains("only supported on macOS and Windows"));
+    }
+    
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    #[test]
+    #[ignore = "requires access to the OS keychain"]
+    fn test_keychain_missing_entry() {
+        assert!(get_password_from_keychain("wc-envc-test-missing-entry").is_err());
+    }
+    
+    #[test]
+    fn test_custom_password_env() {
+        std::env::set_var("WC_ENVC_TEST_CI_ENV_PASSWORD", "from-ci");
+        std::env::set_var("WC_ENVC_TEST_EMPTY_PASSWORD", "");
+        
+        assert_eq!(read_password_env("WC_ENVC_TEST_CI_ENV_PASSWORD").as_deref(), Some("from-ci"));
+        assert_eq!(read_password_env("WC_ENVC_TEST_EMPTY_PASSWORD"), None);
+        assert_eq!(read_password_env("WC_ENVC_TEST_UNSET_PASSWORD"), None);
+    }
+    
+    #[test]
+    fn test_format_timing() {
+        let line = format_timing(".env", Duration::from_millis(3), Duration::from_millis(12));
+        
+        assert!(line.contains(".env"));
+        assert!(line.contains("key derivation 3.00ms"));
+        assert!(line.contains("total 12.00ms"));
+    }
+    
+    #[test]
+    fn test_key_derivation_check_follows_the_file_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let aes = dir.path().join(".env.enc");
+        let chacha = dir.path().join(".env.production.enc");
+        fs::write(&aes, "A=Zm9vYmFyYmF6cXV4\n").unwrap();
+        fs::write(&chacha, "# wc-envc: cipher=chacha20-poly1305 kdf=argon2id salt=c2FsdHNhbHRzYWx0c2FsdA==\nA=Zm9vYmFyYmF6cXV4\n").unwrap();
+        let opts = RunOptions::default();
+        
+        let ciphers = header_ciphers(&[PathBuf::from("-"), aes.clone(), chacha, aes], &opts).unwrap();
+        let kdfs: Vec<&str> = ciphers.iter().map(FileCipher::kdf).collect();
+        assert_eq!(kdfs, ["SHA-256 of the password (no salt)", "Argon2id with a per-file salt"]);
+        assert_eq!(header_ciphers(&[], &opts).unwrap(), [FileCipher::default()]);
+    }
+    
+    #[test]
+    fn test_key_derivation_warning() {
+        let warning = key_derivation_warning(Duration::from_micros(40), MIN_KEY_DERIVATION).unwrap();
+        assert!(warning.contains("took 40.00µs, below 100ms"));
+        assert!(key_derivation_warning(Duration::from_millis(250), MIN_KEY_DERIVATION).is_none());
+    }
+    
+    #[cfg(feature = "network")]
+    #[test]
+    fn test_read_source_from_url() {
+        use std::net::TcpListener;
+        
+        let body = "DB_HOST=abc123==";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        
+        let url = PathBuf::from(format!("http://{}/.env.enc", addr));
+        assert_eq!(read_source(&url, 0).unwrap(), body);
+        server.join().unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_output_replaced_unless_dereference() {
+        use std::os::unix::fs::symlink;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("shared.env");
+        let output = dir.path().join(".env");
+        
+        std::fs::write(&target, "SHARED=1\n").unwrap();
+        symlink(&target, &output).unwrap();
+        write_output(&output, "A=1\n", ProcessMode::Decrypt, &RunOptions::default()).unwrap();
+        assert!(!std::fs::symlink_metadata(&output).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "SHARED=1\n");
+        
+        std::fs::remove_file(&output).unwrap();
+        symlink(&target, &output).unwrap();
+        let opts = RunOptions { dereference: true, ..Default::default() };
+        write_output(&output, "B=2\n", ProcessMode::Decrypt, &opts).unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "B=2\n");
+    }
+    
+    #[test]
+    fn test_cli_password_warning() {
+        let from_arg = RunOptions { password_from_arg: true, ..Default::default() };
+        assert!(cli_password_warning(&from_arg).unwrap().contains("--password"));
+        
+        let quiet = RunOptions { quiet: true, ..from_arg };
+        assert!(cli_password_warning(&quiet).is_none());
+        assert!(cli_password_warning(&RunOptions::default()).is_none());
+    }
+    
+    #[test]
+    fn test_keep_going_past_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let first = write_encrypted(dir.path(), &password);
+        let other = SecretString::new("other".to_string());
+        let broken = dir.path().join(".env.broken.enc");
+        fs::write(&broken, format!("DB_HOST={}", engine::derive_key(&other).encrypt("x"))).unwrap();
+        let third = dir.path().join(".env.staging.enc");
+        fs::copy(&first, &third).unwrap();
+        
+        let inputs = [first, broken, third];
+        let outputs = [
+            dir.path().join(".env"),
+            dir.path().join(".env.broken"),
+            dir.path().join(".env.staging"),
+        ];
+        let opts = RunOptions { keep_going: true, ..Default::default() };
+        
+        let err = process_batch(&inputs, &outputs, &password, ProcessMode::Decrypt, false, &opts).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 3 file(s) failed: .env.broken.enc");
+        assert_eq!(fs::read_to_string(&outputs[0]).unwrap(), "DB_HOST=localhost");
+        assert!(!outputs[1].exists());
+        assert_eq!(fs::read_to_string(&outputs[2]).unwrap(), "DB_HOST=localhost");
+    }
+    
+    #[test]
+    fn test_declined_file_is_skipped_not_failed() {
+        let declined = anyhow::Error::new(cancel::Cancelled::ByUser).context(".env.enc");
+        assert_eq!(failed_status(&declined), FileStatus::Skipped);
+        let interrupted = anyhow::Error::new(cancel::Cancelled::Interrupted);
+        assert_eq!(failed_status(&interrupted), FileStatus::Failed("Cancelled: stopped before the next file".to_string()));
+        assert_eq!(failed_status(&anyhow::anyhow!("bad")), FileStatus::Failed("bad".to_string()));
+    }
+    
+    #[test]
+    fn test_batch_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let first = write_encrypted(dir.path(), &password);
+        let broken = dir.path().join(".env.broken.enc");
+        fs::write(&broken, "DB_HOST=not-encrypted-at-all!").unwrap();
+        
+        let inputs = [first, broken];
+        let outputs = [dir.path().join(".env"), dir.path().join(".env.broken")];
+        let report_path = dir.path().join("report.json");
+        let opts = RunOptions { keep_going: true, report: Some(report_path.clone()), ..Default::default() };
+        
+        assert!(process_batch(&inputs, &outputs, &password, ProcessMode::Decrypt, false, &opts).is_err());
+        
+        let raw = fs::read_to_string(&report_path).unwrap();
+        assert!(!raw.contains("localhost"));
+        let report: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        assert_eq!(report["mode"], "decrypt");
+        assert_eq!(report["total"], 2);
+        assert_eq!(report["failed"], 1);
+        
+        let files = report["files"].as_array().unwrap();
+        assert_eq!(files[0]["status"], "ok");
+        assert_eq!(files[0]["vars"], 1);
+        assert!(files[0]["input"].as_str().unwrap().ends_with(".env.enc"));
+        assert_eq!(files[1]["status"], "failed");
+        assert!(files[1]["error"].is_string());
+    }
+    
+    #[test]
+    fn test_store_name_survives_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let input = dir.path().join(".env.production");
+        fs::write(&input, "DB_HOST=localhost\n").unwrap();
+        let encrypted = dir.path().join(".env.production.enc");
+        
+        let opts = RunOptions { store_name: true, ..Default::default() };
+        let (inputs, outputs) = (std::slice::from_ref(&input), std::slice::from_ref(&encrypted));
+        process_batch(inputs, outputs, &password, ProcessMode::Encrypt, false, &opts).unwrap();
+        fs::remove_file(&input).unwrap();
+        
+        let renamed = dir.path().join("secrets.bin");
+        fs::rename(&encrypted, &renamed).unwrap();
+        
+        let output = output_path(&renamed, ProcessMode::Decrypt, &RunOptions::default()).unwrap();
+        assert_eq!(output, input);
+        process_batch(&[renamed], &[output], &password, ProcessMode::Decrypt, false, &RunOptions::default()).unwrap();
+        assert_eq!(fs::read_to_string(&input).unwrap(), "DB_HOST=localhost\n");
+    }
+    
+    #[test]
+    fn test_stored_name_stays_next_to_the_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("secrets.bin");
+        
+        // A header naming the input itself would overwrite it; other names land beside it
+        for (name, expected) in [("secrets.bin", None), ("../.env", None), ("sub\\.env", None), (".env.local", Some(".env.local"))] {
+            fs::write(&input, engine::add_name_header("DB_HOST=bG9jYWxob3N0\n", name)).unwrap();
+            assert_eq!(stored_output_path(&input), expected.map(|name| dir.path().join(name)), "{}", name);
+        }
+    }
+    
+    #[test]
+    fn test_final_newline_is_applied_last() {
+        let password = SecretString::new("correct".to_string());
+        let decrypt = |plain: &str, final_newline, opts: RunOptions| {
+            let (encrypted, _) = engine::process_file(plain, &password, ProcessMode::Encrypt, &ProcessOptions::default()).unwrap();
+            let opts = RunOptions { process: ProcessOptions { final_newline, ..opts.process.clone() }, ..opts };
+            process_timed(Path::new(".env.enc"), &encrypted, &password, ProcessMode::Decrypt, &opts).unwrap().result
+        };
+        
+        // --env-file-stdout always ends its lines, and interpolation keeps the file's own ending
+        let docker = RunOptions { docker_env_file: true, ..Default::default() };
+        assert_eq!(decrypt("A=1\nB=\"2\"\n", engine::FinalNewline::Never, docker.clone()), "A=1\nB=2");
+        assert_eq!(decrypt("A=1\nB=2", engine::FinalNewline::Preserve, docker), "A=1\nB=2\n");
+        let interpolated = RunOptions { interpolate: Some(Interpolation::default()), ..Default::default() };
+        assert_eq!(decrypt("A=1\nB=$A", engine::FinalNewline::Always, interpolated), "A=1\nB=1\n");
+    }
+    
+    #[test]
+    fn test_split_file_is_decrypted_on_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join(".env.enc");
+        let split = dir.path().join(".env.split.enc");
+        fs::write(&plain, "A=bG9jYWxob3N0\n").unwrap();
+        fs::write(&split, "# wc-envc: shares=2-of-3\nA=bG9jYWxob3N0\n").unwrap();
+        
+        assert!(password_from_split_file(std::slice::from_ref(&plain), 0).unwrap().is_none());
+        let err = password_from_split_file(&[plain.clone(), split.clone()], 0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{} was encrypted with --split; decrypt it on its own so its shares can be collected", split.display())
+        );
+        
+        // The header sniff respects --max-file-size and reports unreadable files
+        let err = password_from_split_file(std::slice::from_ref(&plain), 4).unwrap_err();
+        assert!(err.to_string().contains("--max-file-size"));
+        assert!(password_from_split_file(&[dir.path().join("missing.enc")], 0).is_err());
+    }
+    
+    #[test]
+    fn test_preserve_mtime_copies_input_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let input = dir.path().join(".env");
+        let encrypted = dir.path().join(".env.enc");
+        let decrypted = dir.path().join(".env.copy");
+        fs::write(&input, "DB_HOST=localhost\n").unwrap();
+        let old = FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(&input, old).unwrap();
+        let mtime = |path: &Path| FileTime::from_last_modification_time(&fs::metadata(path).unwrap());
+        let run = |from: &PathBuf, to: &PathBuf, mode, opts: &RunOptions| {
+            process_batch(std::slice::from_ref(from), std::slice::from_ref(to), &password, mode, false, opts).unwrap();
+        };
+        
+        let opts = RunOptions { preserve_mtime: true, ..Default::default() };
+        run(&input, &encrypted, ProcessMode::Encrypt, &opts);
+        assert_eq!(mtime(&encrypted), old);
+        run(&encrypted, &decrypted, ProcessMode::Decrypt, &opts);
+        assert_eq!(mtime(&decrypted), old);
+        
+        fs::remove_file(&encrypted).unwrap();
+        run(&input, &encrypted, ProcessMode::Encrypt, &RunOptions::default());
+        assert_ne!(mtime(&encrypted), old);
+    }
+    
+    #[test]
+    fn test_overwrite_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join(".env");
+        fs::write(&input, "DB_HOST=localhost\n").unwrap();
+        let output = dir.path().join(".env.enc");
+        let run = |overwrite| {
+            fs::write(&output, "old").unwrap();
+            let opts = RunOptions { overwrite, ..Default::default() };
+            run_one_liner(input.clone(), output.clone(), Some(SecretString::new("pw".to_string())), false, ProcessMode::Encrypt, &opts)
+                .unwrap();
+            fs::read_to_string(&output).unwrap()
+        };
+        
+        assert_eq!(run(OverwritePolicy::Never), "old");
+        assert!(run(OverwritePolicy::Always).starts_with("DB_HOST="));
+        
+        // Prompt asks unless --yes is given; the other policies never ask
+        let prompt = RunOptions::default();
+        assert!(prompt_overwrite(false, &prompt));
+        assert!(!prompt_overwrite(true, &prompt));
+        for overwrite in [OverwritePolicy::Always, OverwritePolicy::Never] {
+            assert!(!prompt_overwrite(false, &RunOptions { overwrite, ..Default::default() }));
+        }
+    }
+    
+    #[test]
+    fn test_overwrite_never_skips_in_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let inputs = [dir.path().join(".env"), dir.path().join(".env.local")];
+        let outputs = [dir.path().join(".env.enc"), dir.path().join(".env.local.enc")];
+        fs::write(&inputs[0], "A=1\n").unwrap();
+        fs::write(&inputs[1], "B=2\n").unwrap();
+        fs::write(&outputs[0], "existing").unwrap();
+        
+        let opts = RunOptions { overwrite: OverwritePolicy::Never, ..Default::default() };
+        process_batch(&inputs, &outputs, &password, ProcessMode::Encrypt, false, &opts).unwrap();
+        assert_eq!(fs::read_to_string(&outputs[0]).unwrap(), "existing");
+        assert!(fs::read_to_string(&outputs[1]).unwrap().starts_with("B="));
+    }
+    
+    #[test]
+    fn test_overwrite_per_file_choices() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = [".env", ".env.local", ".env.staging", ".env.test"];
+        let inputs: Vec<PathBuf> = names.iter().map(|name| dir.path().join(name)).collect();
+        let outputs: Vec<PathBuf> = names.iter().map(|name| dir.path().join(format!("{}.enc", name))).collect();
+        for output in &outputs[..3] {
+            fs::write(output, "existing").unwrap();
+        }
+        let opts = RunOptions { overwrite_per_file: true, ..Default::default() };
+        
+        // Skip the first, overwrite all from the second on: only one question per answer needed
+        let mut answers = vec![OverwriteChoice::Skip, OverwriteChoice::OverwriteAll].into_iter();
+        let (kept_inputs, kept_outputs) =
+            confirm_existing(inputs.clone(), outputs.clone(), &opts, |_| Ok(answers.next().unwrap())).unwrap();
+        assert_eq!(kept_inputs, inputs[1..]);
+        assert_eq!(kept_outputs, outputs[1..]);
+        assert_eq!(answers.next(), None);
+        
+        let mut asked = Vec::new();
+        let err = confirm_existing(inputs.clone(), outputs.clone(), &opts, |path| {
+            asked.push(path.to_path_buf());
+            Ok(if asked.len() == 1 { OverwriteChoice::Overwrite } else { OverwriteChoice::Quit })
+        })
+        .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(cancel::Cancelled::ByUser)));
+        assert_eq!(asked, outputs[..2]);
+    }
+    
+    #[test]
+    fn test_size_limited_reader() {
+        let mut read = String::new();
+        SizeLimited { inner: "A=1\n".as_bytes(), limit: 4, remaining: 4 }.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "A=1\n");
+        
+        let mut limited = std::io::BufReader::new(SizeLimited { inner: "A=1\nB=2\n".as_bytes(), limit: 4, remaining: 4 });
+        let mut output = Vec::new();
+        let password = SecretString::new("pw".to_string());
+        let err = engine::process_reader(&mut limited, &mut output, &password, ProcessMode::Encrypt, &ProcessOptions::default());
+        match err.unwrap_err() {
+            engine::EngineError::Io { source, .. } => assert_eq!(source.to_string(), "input is larger than --max-file-size (4 bytes)"),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert!(output.is_empty());
+    }
+    
+    #[test]
+    fn test_file_list_with_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        let first = write_encrypted(dir.path(), &password);
+        let second = dir.path().join(".env.staging.enc");
+        fs::copy(&first, &second).unwrap();
+        let mode = ProcessMode::Decrypt;
+        let pw = || Some(SecretString::new("correct".to_string()));
+        
+        run_file_list(vec![first.clone(), second], pw(), true, mode, &RunOptions::default()).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join(".env")).unwrap(), "DB_HOST=localhost");
+        assert_eq!(fs::read_to_string(dir.path().join(".env.staging")).unwrap(), "DB_HOST=localhost");
+        
+        let missing = dir.path().join(".env.missing.enc");
+        let opts = RunOptions { keep_going: true, ..Default::default() };
+        let err = run_file_list(vec![missing, first.clone()], pw(), true, mode, &opts).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 file(s) failed: .env.missing.enc");
+        
+        // Without --yes, --remove-source asks first (and fails without a terminal) instead of deleting
+        if !console::Term::stderr().is_term() {
+            let opts = RunOptions { remove_source: true, overwrite: OverwritePolicy::Always, ..Default::default() };
+            assert!(run_file_list(vec![first.clone()], pw(), false, mode, &opts).is_err());
+            assert!(first.exists());
+        }
+    }
+    
+    #[test]
+    fn test_file_list_yes_writes_suspicious_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let password = SecretString::new("correct".to_string());
+        // Encrypted twice, so one decrypt still leaves an encrypted-looking value
+        let key = engine::derive_key(&password);
+        let input = dir.path().join(".env.enc");
+        fs::write(&input, format!("DB_HOST={}", key.encrypt(&key.encrypt("localhost")))).unwrap();
+        
+        // --yes must not ask "Write the output anyway?", which fails without a terminal
+        run_file_list(vec![input], Some(password), true, ProcessMode::Decrypt, &RunOptions::default()).unwrap();
+        let written = fs::read_to_string(dir.path().join(".env")).unwrap();
+        assert_eq!(written, format!("DB_HOST={}", key.encrypt("localhost")));
+    }
+    
+    #[test]
+    fn test_directory_input_is_explained() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("config");
+        fs::create_dir(&input).unwrap();
+        let output = dir.path().join("config.enc");
+        
+        let opts = RunOptions::default();
+        let err = run_one_liner(input, output.clone(), Some(SecretString::new("pw".to_string())), true, ProcessMode::Encrypt, &opts)
+            .unwrap_err();
+        assert!(err.to_string().contains("config is a directory, expected a file"));
+        assert!(!output.exists());
+    }
+    
+    #[test]
+    fn test_password_min_length_applies_to_every_source() {
+        let too_short = |source: &str| format!("Password from {} is too short: 5 characters, --password-min-length requires 8", source);
+        
+        assert_eq!(checked(SecretString::new("short".to_string()), "-p/--password", 8).unwrap_err().to_string(), too_short("-p/--password"));
+        
+        std::env::set_var("WC_ENVC_TEST_SHORT_PASSWORD", "short");
+        let from_env = read_password_env("WC_ENVC_TEST_SHORT_PASSWORD").unwrap();
+        assert_eq!(checked_env_password(from_env.clone(), 8).unwrap_err().to_string(), too_short(PASSWORD_ENV_VAR));
+        assert!(checked_env_password(from_env, 0).is_ok());
+        
+        assert_eq!(get_password_from_command("echo short", 8).unwrap_err().to_string(), too_short("--password-command"));
+        assert_eq!(get_password_from_command("echo short", 0).unwrap().expose_secret(), "short");
+        
+        // Length counts characters, not bytes; 0 disables the check
+        assert!(check_length("pässwörd", "the prompt", 8).is_ok());
+        assert!(check_length("x", "the prompt", 0).is_ok());
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_password_min_length_applies_to_the_agent() {
+        use std::os::unix::fs::DirBuilderExt;
+        use std::os::unix::net::UnixListener;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let sockets = dir.path().join("agent");
+        fs::DirBuilder::new().mode(0o700).create(&sockets).unwrap();
+        let listener = UnixListener::bind(sockets.join("wc-envc-agent.sock")).unwrap();
+        let server = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.write_all(b"short").unwrap();
+            }
+        });
+        
+        let served = agent::fetch_password_from(&sockets).unwrap();
+        assert_eq!(
+            check_agent_password(served, 8).unwrap_err().to_string(),
+            "Password from the wc-envc agent is too short: 5 characters, --password-min-length requires 8"
+        );
+        let served = agent::fetch_password_from(&sockets).unwrap();
+        assert_eq!(check_agent_password(served, 0).unwrap().expose_secret(), "short");
+        server.join().unwrap();
+    }
+    
+    #[test]
+    fn test_password_min_length_only_guards_encryption() {
+        assert_eq!(minimum_for(ProcessMode::Encrypt, 8), 8);
+        assert_eq!(minimum_for(ProcessMode::Decrypt, 8), 0);
+        assert!(checked(SecretString::new("short".to_string()), "-p/--password", minimum_for(ProcessMode::Decrypt, 8)).is_ok());
+    }
+    
+    #[test]
+    fn test_password_prompt_gives_up_after_attempts() {
+        let mut asked = 0;
+        let err = prompt_with_confirm(3, 0, |_| {
+            asked += 1;
+            Ok(format!("try {}", asked))
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "No password confirmed after 3 attempt(s) (see --password-attempts)");
+        assert_eq!(asked, 6);
+        
+        // Empty and too-short entries use up attempts too; 0 attempts never prompts
+        let mut answers = ["", "short", "long enough", "long enough"].into_iter();
+        let password = prompt_with_confirm(3, 8, |_| Ok(answers.next().unwrap().to_string())).unwrap();
+        assert_eq!(password.expose_secret(), "long enough");
+        assert!(prompt_with_confirm(0, 0, |_| panic!("prompted")).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! `${VAR}` / `$VAR` references in decrypted values, resolved against earlier keys (`--interpolate`)
+
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use console::style;
+
+use crate::engine::{self, ProcessOptions};
+
+/// What to do with a reference to a variable that is not defined
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Undefined {
+    /// Leave the reference as it is and warn
+    #[default]
+    Warn,
+    /// Fail the file
+    Error,
+}
+
+/// `--interpolate` settings
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Interpolation {
+    /// Fall back to the process environment for names the file does not define earlier
+    pub env: bool,
+    pub undefined: Undefined,
+}
+
+/// A reference nothing resolved
+#[derive(Debug, PartialEq)]
+pub struct Unresolved {
+    /// 1-based line number
+    pub line: usize,
+    pub name: String,
+}
+
+/// Resolve references in the values of `content`, top to bottom
+/// A key sees the keys defined above it (with their references already resolved), then the
+/// process environment if `settings.env` is set. Single-quoted values and `\$` are left alone,
+/// as are undefined references, which are returned for the caller to warn about or fail on
+pub fn resolve(content: &str, settings: Interpolation, opts: &ProcessOptions) -> (String, Vec<Unresolved>) {
+    let mut defined: HashMap<String, String> = HashMap::new();
+    let mut unresolved = Vec::new();
+    let mut output = String::with_capacity(content.len());
+    
+    for (index, line) in engine::lines_inclusive(content).enumerate() {
+        let Some(eq_pos) = line.find('=').filter(|_| !opts.is_ignored(line.trim())) else {
+            output.push_str(line);
+            continue;
+        };
+        
+        // Inline comments are kept as they are and never become part of a value
+        let (pair, rest) = line.split_at(eq_pos + 1);
+        let (value, comment) = engine::split_inline_comment(rest);
+        let value = if value.trim_start().starts_with('\'') {
+            value.to_string()
+        } else {
+            substitute(value, &mut |name| {
+                let found = defined.get(name).cloned().or_else(|| settings.env.then(|| env::var(name).ok()).flatten());
+                if found.is_none() {
+                    unresolved.push(Unresolved { line: index + 1, name: name.to_string() });
+                }
+                found
+            })
+        };
+        
+        defined.insert(engine::bare_key(&pair[..eq_pos]).to_string(), engine::unquoted_value(&value).to_string());
+        output.push_str(pair);
+        output.push_str(&value);
+        output.push_str(comment);
+    }
+    
+    (output, unresolved)
+}
+
+/// Apply `--interpolate` to the decrypted content of `input`, warning about or failing on undefined references
+pub fn apply(input: &Path, content: &str, settings: Interpolation, quiet: bool, opts: &ProcessOptions) -> Result<String> {
+    let (resolved, unresolved) = resolve(content, settings, opts);
+    if unresolved.is_empty() {
+        return Ok(resolved);
+    }
+    
+    let listed: Vec<String> = unresolved.iter().map(|u| format!("${{{}}} (line {})", u.name, u.line)).collect();
+    if settings.undefined == Undefined::Error {
+        anyhow::bail!("Undefined variable(s) referenced: {}", listed.join(", "));
+    }
+    if !quiet {
+        eprintln!(
+            "{} {} left undefined reference(s) as they are: {}",
+            style("⚠️").yellow().for_stderr(),
+            style(input.display()).cyan().for_stderr(),
+            listed.join(", ")
+        );
+    }
+    Ok(resolved)
+}
+
+/// Replace each `${NAME}` / `$NAME` in `value` with `lookup(NAME)`, keeping it when that is None
+fn substitute(value: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    
+    while let Some(dollar) = rest.find('$') {
+        let escaped = rest[..dollar].ends_with('\\');
+        output.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        
+        let (name, reference_len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) if is_name(&braced[..end]) => (&braced[..end], end + 2),
+                _ => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                if is_name(&after[..end]) { (&after[..end], end) } else { ("", 0) }
+            }
+        };
+        
+        let reference = &rest[dollar..dollar + 1 + reference_len];
+        let resolved = if escaped || name.is_empty() { None } else { lookup(name) };
+        output.push_str(resolved.as_deref().unwrap_or(reference));
+        rest = &rest[dollar + 1 + reference_len..];
+    }
+    
+    output.push_str(rest);
+    output
+}
+
+/// A variable name: a letter or underscore, then letters, digits and underscores
+fn is_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Resolve against the file only
+    fn resolve_file(content: &str) -> (String, Vec<Unresolved>) {
+        resolve(content, Interpolation::default(), &ProcessOptions::default())
+    }
+    
+    #[test]
+    fn test_interpolate_earlier_keys() {
+        let content = "HOST=db.internal\nPORT=5432\n# $HOST stays\nURL=\"${HOST}:$PORT/app\"\nRAW='${HOST}'\nCOST=\\$HOST $5\n";
+        let (resolved, unresolved) = resolve_file(content);
+        assert_eq!(
+            resolved,
+            "HOST=db.internal\nPORT=5432\n# $HOST stays\nURL=\"db.internal:5432/app\"\nRAW='${HOST}'\nCOST=\\$HOST $5\n"
+        );
+        assert_eq!(unresolved, []);
+        
+        // Only keys above count, and resolved values feed later ones
+        let (resolved, _) = resolve_file("A=$B\nB=1\nC=${B}2\nD=$C\n");
+        assert_eq!(resolved, "A=$B\nB=1\nC=12\nD=12\n");
+    }
+    
+    #[test]
+    fn test_interpolate_export_and_inline_comments() {
+        let content = "export HOST=db.internal\nPORT=5432 # default $HOST\nTAG=\"a # b\" # note\nURL=$HOST:$PORT # built\nNOTE=\"${TAG}!\"\n";
+        let (resolved, unresolved) = resolve_file(content);
+        assert_eq!(
+            resolved,
+            "export HOST=db.internal\nPORT=5432 # default $HOST\nTAG=\"a # b\" # note\nURL=db.internal:5432 # built\nNOTE=\"a # b!\"\n"
+        );
+        assert_eq!(unresolved, []);
+    }
+    
+    #[test]
+    fn test_interpolate_undefined_reference() {
+        let (resolved, unresolved) = resolve_file("URL=${HOST}:80\n");
+        assert_eq!(resolved, "URL=${HOST}:80\n");
+        assert_eq!(unresolved, [Unresolved { line: 1, name: "HOST".to_string() }]);
+        
+        let opts = ProcessOptions::default();
+        let warn = Interpolation::default();
+        assert_eq!(apply(Path::new(".env"), "URL=${HOST}:80\n", warn, true, &opts).unwrap(), "URL=${HOST}:80\n");
+        let error = Interpolation { undefined: Undefined::Error, ..warn };
+        assert_eq!(
+            apply(Path::new(".env"), "URL=${HOST}:80\n", error, true, &opts).unwrap_err().to_string(),
+            "Undefined variable(s) referenced: ${HOST} (line 1)"
+        );
+        
+        let from_env = Interpolation { env: true, ..error };
+        assert_eq!(apply(Path::new(".env"), "P=$PATH\n", from_env, true, &opts).unwrap(), format!("P={}\n", env::var("PATH").unwrap()));
+    }
+}
@@ -0,0 +1,157 @@
+//! `.wc-envc-keys`: a committed list of the keys to encrypt, so every other value stays plaintext and reviewable
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use glob::Pattern;
+
+use crate::engine::{self, ProcessOptions};
+use crate::scanner;
+
+/// Policy file name, looked up next to the file being encrypted
+pub const POLICY_FILE: &str = ".wc-envc-keys";
+
+/// Key names or globs (`*`, `?`) whose values are encrypted
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyPolicy {
+    patterns: Vec<Pattern>,
+}
+
+impl KeyPolicy {
+    /// Parse a policy file: one key name or glob per line, `#` comments and blank lines ignored
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            patterns.push(pattern(line).map_err(|e| anyhow::anyhow!("line {}: {}", index + 1, e))?);
+        }
+        
+        if patterns.is_empty() {
+            anyhow::bail!("lists no keys to encrypt");
+        }
+        Ok(Self { patterns })
+    }
+    
+    /// Parse the comma-separated form stored in an encrypted file's header
+    pub fn from_header(list: &str) -> Result<Self> {
+        let patterns = list.split(',').map(|p| pattern(p.trim())).collect::<Result<Vec<_>>>()?;
+        Ok(Self { patterns })
+    }
+    
+    /// Whether the value of `key` is encrypted under this policy
+    pub fn matches(&self, key: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(key))
+    }
+}
+
+/// Comma-separated patterns, as stored in the header
+impl fmt::Display for KeyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let patterns: Vec<&str> = self.patterns.iter().map(Pattern::as_str).collect();
+        write!(f, "{}", patterns.join(","))
+    }
+}
+
+/// Validate one pattern: key name characters plus the `*` and `?` wildcards
+fn pattern(text: &str) -> Result<Pattern> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '*' | '?')) {
+        anyhow::bail!("'{}' is not a key name or glob (letters, digits, '_', '*' and '?')", text);
+    }
+    Ok(Pattern::new(text)?)
+}
+
+/// Policy next to `source` (the current directory for stdin), if there is one
+pub fn find(source: &Path) -> Result<Option<KeyPolicy>> {
+    let dir = if scanner::is_stdio(source) || scanner::is_url(source) {
+        Path::new("")
+    } else {
+        source.parent().unwrap_or(Path::new(""))
+    };
+    let path = dir.join(POLICY_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    
+    let content = fs::read_to_string(&path)?;
+    KeyPolicy::parse(&content)
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// How many of the keys in `content` the policy encrypts and how many it leaves in plaintext
+/// A policy that matches none of them is refused: a typo or a stray policy file would otherwise
+/// write every value in the clear
+pub fn coverage(policy: &KeyPolicy, content: &str, opts: &ProcessOptions) -> Result<(usize, usize)> {
+    let (encrypted, plaintext) = engine::keys(content, opts).fold((0, 0), |(encrypted, plaintext), key| {
+        if policy.matches(key) {
+            (encrypted + 1, plaintext)
+        } else {
+            (encrypted, plaintext + 1)
+        }
+    });
+    if encrypted == 0 && plaintext > 0 {
+        anyhow::bail!(
+            "{} ({}) matches none of the file's keys; refusing to leave every value in plaintext",
+            POLICY_FILE,
+            policy
+        );
+    }
+    Ok((encrypted, plaintext))
+}
+
+/// `opts` with the policy for `source` applied, if one is found
+pub fn options_for(source: &Path, opts: &ProcessOptions) -> Result<ProcessOptions> {
+    Ok(ProcessOptions { key_policy: find(source)?, ..opts.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ProcessMode;
+    use secrecy::SecretString;
+    
+    #[test]
+    fn test_policy_encrypts_listed_keys_only() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(POLICY_FILE), "# Keys to encrypt\n*_SECRET\nDB_PASSWORD\n").unwrap();
+        let source = dir.path().join(".env");
+        let opts = options_for(&source, &ProcessOptions::default()).unwrap();
+        
+        let password = SecretString::new("secret".to_string());
+        let plain = "APP_NAME=demo\nAPI_SECRET=abc123\nDB_PASSWORD=hunter2\nLOG_LEVEL=info\n";
+        let (encrypted, _) = engine::process_file(plain, &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert!(encrypted.starts_with("# wc-envc: encrypted-keys=*_SECRET,DB_PASSWORD\n"));
+        assert!(encrypted.contains("\nAPP_NAME=demo\n"));
+        assert!(encrypted.contains("\nLOG_LEVEL=info\n"));
+        assert!(!encrypted.contains("abc123") && !encrypted.contains("hunter2"));
+        
+        // Decryption follows the header, wherever the file is; plaintext keys don't trip validation
+        let strict = ProcessOptions { fail_on_plaintext: true, ..Default::default() };
+        assert!(engine::validate_encrypted_file(&encrypted, &strict).is_ok());
+        let (decrypted, _) = engine::process_file(&encrypted, &password, ProcessMode::Decrypt, &strict).unwrap();
+        assert_eq!(decrypted, plain);
+        
+        let policy = opts.key_policy.unwrap();
+        assert_eq!(coverage(&policy, plain, &ProcessOptions::default()).unwrap(), (2, 2));
+        let err = coverage(&policy, "NAME=demo
+API_SECRT=typo
+", &ProcessOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("matches none of the file's keys"));
+    }
+    
+    #[test]
+    fn test_policy_file_validation() {
+        assert!(KeyPolicy::parse("API_*\n\n# comment\nTOKEN?\n").unwrap().matches("TOKEN1"));
+        assert_eq!(
+            KeyPolicy::parse("API_KEY\nDB PASS\n").unwrap_err().to_string(),
+            "line 2: 'DB PASS' is not a key name or glob (letters, digits, '_', '*' and '?')"
+        );
+        assert!(KeyPolicy::parse("API_KEY=1\n").is_err());
+        assert!(KeyPolicy::parse("# nothing\n").is_err());
+    }
+}
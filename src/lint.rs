@@ -0,0 +1,273 @@
+//! Check env files for common mistakes without encrypting or decrypting anything
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use console::style;
+
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::scanner::{self, Scanner};
+
+/// Problems `lint` looks for
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Rule {
+    /// A key is defined more than once
+    DuplicateKey,
+    /// A key is not a valid environment variable name
+    InvalidKey,
+    /// An unquoted value contains whitespace
+    UnquotedSpace,
+    /// A line ends in spaces or tabs
+    TrailingWhitespace,
+    /// The file does not end with a newline
+    MissingNewline,
+    /// An encrypted file holds a value that is not encrypted
+    PlaintextValue,
+}
+
+impl Rule {
+    /// Name used on the command line and in reports
+    fn name(self) -> String {
+        self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+    }
+    
+    /// Severity without --deny/--warn/--allow: cosmetic issues only warn
+    fn default_level(self) -> Level {
+        match self {
+            Rule::TrailingWhitespace | Rule::MissingNewline => Level::Warning,
+            _ => Level::Error,
+        }
+    }
+}
+
+/// How a finding is reported
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Level {
+    /// Reported and fails the run
+    Error,
+    /// Reported only
+    Warning,
+    /// Not reported
+    Allow,
+}
+
+/// Severity overrides; when a rule is listed more than once, deny beats warn beats allow
+#[derive(Clone, Default)]
+pub struct Severities {
+    pub deny: Vec<Rule>,
+    pub warn: Vec<Rule>,
+    pub allow: Vec<Rule>,
+}
+
+impl Severities {
+    /// Effective level of `rule`
+    pub fn level(&self, rule: Rule) -> Level {
+        if self.deny.contains(&rule) {
+            Level::Error
+        } else if self.warn.contains(&rule) {
+            Level::Warning
+        } else if self.allow.contains(&rule) {
+            Level::Allow
+        } else {
+            rule.default_level()
+        }
+    }
+}
+
+/// One problem found in a file
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    /// 1-based line number
+    pub line: usize,
+    pub rule: Rule,
+    pub message: String,
+}
+
+/// Lint .env content; `encrypted` also checks that every value is encrypted
+/// Findings are sorted by line
+pub fn lint(content: &str, encrypted: bool, opts: &ProcessOptions) -> Result<Vec<Finding>> {
+    let dearmored;
+    let content = if encrypted && engine::is_armored(content) {
+        dearmored = engine::dearmor(content)?;
+        dearmored.as_str()
+    } else {
+        content
+    };
+    let mut findings = Vec::new();
+    
+    let lenient = ProcessOptions { strict: false, ..opts.clone() };
+    for (line, key) in engine::validate_key_names(content, &lenient)? {
+        findings.push(Finding {
+            line,
+            rule: Rule::InvalidKey,
+            message: format!("'{}' is not a valid environment variable name", key),
+        });
+    }
+    
+    for (key, lines) in engine::find_duplicate_keys(content, opts) {
+        let numbers: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        findings.push(Finding {
+            line: lines[1],
+            rule: Rule::DuplicateKey,
+            message: format!("'{}' is defined {} times (lines {})", key, lines.len(), numbers.join(", ")),
+        });
+    }
+    
+    // A compressed blob is one ciphertext; its lines have no values to check
+    let check_values = !(encrypted && engine::is_compressed(content));
+    let policy = engine::key_policy(content);
+    let mut count = 0;
+    for (index, line) in engine::lines(content).enumerate() {
+        count = index + 1;
+        if line.trim_end().len() != line.len() {
+            findings.push(Finding { line: count, rule: Rule::TrailingWhitespace, message: "trailing whitespace".to_string() });
+        }
+        
+        if !check_values || opts.is_ignored(line.trim()) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        
+        let quoted = value.starts_with('"') || value.starts_with('\'');
+        if !quoted && value.contains(char::is_whitespace) {
+            findings.push(Finding {
+                line: count,
+                rule: Rule::UnquotedSpace,
+                message: format!("value of '{}' contains whitespace but is not quoted", key),
+            });
+        }
+        let listed = policy.as_ref().is_none_or(|policy| policy.matches(key));
+        if encrypted && listed && !value.is_empty() && !engine::is_likely_encrypted(value) {
+            findings.push(Finding {
+                line: count,
+                rule: Rule::PlaintextValue,
+                message: format!("value of '{}' is not encrypted", key),
+            });
+        }
+    }
+    
+    if !content.is_empty() && !content.ends_with(['\n', '\r']) {
+        findings.push(Finding { line: count, rule: Rule::MissingNewline, message: "no newline at end of file".to_string() });
+    }
+    
+    findings.sort_by_key(|finding| finding.line);
+    Ok(findings)
+}
+
+/// Handle lint command
+/// Checks `files` (default: the plain and encrypted env files in the current directory)
+/// and fails if any finding is an error
+pub fn handle_lint(files: Vec<PathBuf>, severities: &Severities, opts: &ProcessOptions) -> Result<()> {
+    let files = if files.is_empty() {
+        let mut found = Scanner::new(".", ProcessMode::Encrypt).scan();
+        found.extend(Scanner::new(".", ProcessMode::Decrypt).scan());
+        scanner::require_files(found, ".env files")?
+    } else {
+        files
+    };
+    
+    let (mut errors, mut warnings) = (0, 0);
+    for file in &files {
+        for finding in lint_file(file, opts)? {
+            let (icon, level) = match severities.level(finding.rule) {
+                Level::Allow => continue,
+                Level::Error => {
+                    errors += 1;
+                    (style("❌").red(), style("error").red())
+                }
+                Level::Warning => {
+                    warnings += 1;
+                    (style("⚠️").yellow(), style("warning").yellow())
+                }
+            };
+            println!("{} {}:{} {}: {} [{}]",
+                icon,
+                style(file.display()).cyan(),
+                finding.line,
+                level,
+                finding.message,
+                finding.rule.name()
+            );
+        }
+    }
+    
+    if errors > 0 {
+        anyhow::bail!("{} error(s) and {} warning(s) in {} file(s)", errors, warnings, files.len());
+    }
+    
+    if warnings > 0 {
+        println!("{} {} warning(s) in {} file(s)", style("⚠️").yellow(), warnings, files.len());
+    } else {
+        println!("{} {} file(s) checked, no problems found", style("✅").green(), files.len());
+    }
+    
+    Ok(())
+}
+
+/// Lint one file, treating `.env*.enc` names as encrypted
+fn lint_file(file: &Path, opts: &ProcessOptions) -> Result<Vec<Finding>> {
+    let content = fs::read_to_string(file).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+    let encrypted = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| scanner::is_env_file_for(name, ProcessMode::Decrypt));
+    lint(&content, encrypted, opts).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// (line, rule) of every finding
+    fn rules(content: &str, encrypted: bool) -> Vec<(usize, Rule)> {
+        lint(content, encrypted, &ProcessOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|finding| (finding.line, finding.rule))
+            .collect()
+    }
+    
+    #[test]
+    fn test_lint_rules() {
+        assert_eq!(rules("# ok\nDB_HOST=localhost\nGREETING=\"hello world\"\n", false), []);
+        assert_eq!(rules("A=1\nB=2\nA=3\n", false), [(3, Rule::DuplicateKey)]);
+        assert_eq!(rules("1BAD=x\nGOOD-NAME=y\n", false), [(1, Rule::InvalidKey), (2, Rule::InvalidKey)]);
+        assert_eq!(rules("GREETING=hello world\n", false), [(1, Rule::UnquotedSpace)]);
+        assert_eq!(rules("A=1 \n# note\t\n", false), [(1, Rule::TrailingWhitespace), (2, Rule::TrailingWhitespace)]);
+        assert_eq!(rules("A=1\nB=2", false), [(2, Rule::MissingNewline)]);
+        assert_eq!(rules("A=1\r\n", false), []);
+    }
+    
+    #[test]
+    fn test_lint_plaintext_in_encrypted_file() {
+        let password = secrecy::SecretString::new("pw".to_string());
+        let opts = ProcessOptions::default();
+        let (encrypted, _) = engine::process_file("A=1\nB=\n", &password, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(rules(&encrypted, true), []);
+        
+        let leaked = format!("{}DB_PASS=hunter2\n", encrypted);
+        assert_eq!(rules(&leaked, true), [(3, Rule::PlaintextValue)]);
+        assert_eq!(rules(&leaked, false), []);
+    }
+    
+    #[test]
+    fn test_lint_severities() {
+        let severities = Severities { warn: vec![Rule::DuplicateKey], allow: vec![Rule::DuplicateKey, Rule::MissingNewline], ..Default::default() };
+        assert_eq!(severities.level(Rule::DuplicateKey), Level::Warning);
+        assert_eq!(severities.level(Rule::MissingNewline), Level::Allow);
+        assert_eq!(severities.level(Rule::TrailingWhitespace), Level::Warning);
+        assert_eq!(severities.level(Rule::PlaintextValue), Level::Error);
+        
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".env");
+        fs::write(&file, "A=1\nA=2").unwrap();
+        let opts = ProcessOptions::default();
+        assert!(handle_lint(vec![file.clone()], &Severities::default(), &opts).is_err());
+        assert!(handle_lint(vec![file], &severities, &opts).is_ok());
+    }
+}
@@ -1,16 +1,46 @@
+mod agent;
+mod cache;
+mod cancel;
+mod clock;
+mod completion;
+mod decoders;
+mod diff;
+mod edit;
 mod engine;
+mod example;
+mod fingerprint;
+mod format;
+mod get;
+mod gitfilter;
+mod include;
+mod info;
 mod interactive;
+mod interpolate;
+mod keypolicy;
+mod lint;
+mod memfile;
+mod plan;
+mod recipient;
+mod report;
 mod scanner;
 mod setenv;
+mod shares;
+mod update;
+mod vault;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use console::style;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use console::{style, Term};
+use secrecy::SecretString;
 
-use engine::ProcessMode;
+use clock::TimeZone;
+use engine::{Cipher, FinalNewline, ProcessMode, ProcessOptions};
+use format::InputFormat;
+use interactive::{OverwritePolicy, RunOptions};
+use interpolate::{Interpolation, Undefined};
 
 /// wc-envc - Encrypt/decrypt .env files securely
 #[derive(Parser)]
@@ -20,6 +50,56 @@ use engine::ProcessMode;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    
+    /// When to use colored output (honors NO_COLOR in auto mode)
+    #[arg(long, global = true, value_enum, default_value = "auto")]
+    color: ColorChoice,
+    
+    /// Environment variable holding the password
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        env = "WC_ENVC_PASSWORD_ENV",
+        default_value = interactive::PASSWORD_ENV_VAR
+    )]
+    password_env: String,
+    
+    /// Reject encryption passwords shorter than N characters, whatever their source (0 = no minimum)
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        env = "WC_ENVC_PASSWORD_MIN_LENGTH",
+        default_value = "0"
+    )]
+    password_min_length: usize,
+    
+    /// Give up after N failed password prompts (empty, too short or mismatched); 0 never prompts
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        env = "WC_ENVC_PASSWORD_ATTEMPTS",
+        alias = "ask-password-twice-on-mismatch-limit",
+        default_value_t = interactive::DEFAULT_PASSWORD_ATTEMPTS
+    )]
+    password_attempts: usize,
+    
+    /// Suppress warnings (e.g. about passing the password on the command line)
+    #[arg(short, long, global = true, default_value = "false")]
+    quiet: bool,
+}
+
+/// Color output policy
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    /// Color only when writing to a terminal and NO_COLOR is unset
+    Auto,
+    /// Always emit ANSI colors
+    Always,
+    /// Never emit ANSI colors
+    Never,
 }
 
 #[derive(Subcommand)]
@@ -27,47 +107,401 @@ enum Commands {
     /// Encrypt .env file
     Encrypt {
         /// Input file (optional in interactive mode)
-        #[arg(value_name = "FILE")]
+        #[arg(value_name = "FILE", add = completion::env_files(ProcessMode::Encrypt))]
         file: Option<PathBuf>,
         
-        /// Password for encryption
-        #[arg(short, long, env = "WC_ENVC_PASSWORD")]
+        /// Password for encryption (default: from the --password-env variable)
+        #[arg(short, long)]
         password: Option<String>,
         
+        /// Read the password stored under this project name in the OS keychain
+        #[arg(long, value_name = "PROJECT")]
+        keychain: Option<String>,
+        
+        /// Run this command and use its trimmed stdout as the password (e.g. "pass show app/env")
+        #[arg(long, value_name = "CMD", conflicts_with_all = ["password", "keychain"])]
+        password_command: Option<String>,
+        
         /// Input file path
-        #[arg(short, long)]
+        #[arg(short, long, add = completion::env_files(ProcessMode::Encrypt))]
         input: Option<PathBuf>,
         
         /// Output file path
         #[arg(short, long)]
         output: Option<PathBuf>,
         
+        /// Default output name template: {name}, {ext}, {date}, {ts} (e.g. "{name}.{date}.{ext}")
+        #[arg(long, value_name = "TEMPLATE", conflicts_with = "output")]
+        output_template: Option<String>,
+        
+        /// Use local time instead of UTC for {date} and {ts}
+        #[arg(long, default_value = "false", requires = "output_template")]
+        local_time: bool,
+        
+        /// Name the output for an environment: .env -> .env.<NAME>.enc
+        #[arg(long = "env", value_name = "NAME", conflicts_with_all = ["output", "output_template"])]
+        environment: Option<String>,
+        
         /// Skip confirmation prompts (overwrite files)
         #[arg(short, long, default_value = "false")]
         yes: bool,
+        
+        /// Existing output files: prompt (default), always overwrite, or never (skip them)
+        #[arg(long, value_enum, default_value = "prompt")]
+        overwrite: OverwritePolicy,
+        
+        /// In the interactive batch flow, ask about each existing output: overwrite, skip, overwrite all or quit
+        #[arg(long, default_value = "false", conflicts_with = "yes")]
+        interactive_overwrite_per_file: bool,
+        
+        /// Show a diff (values masked) of an existing output before overwriting it
+        #[arg(long, default_value = "false")]
+        diff: bool,
+        
+        /// Write a .sha256 checksum file next to the output
+        #[arg(long, default_value = "false")]
+        checksum: bool,
+        
+        /// Wrap the output in a copy-paste safe armored text block
+        #[arg(long, default_value = "false")]
+        armor: bool,
+        
+        /// Print key derivation and processing timings to stderr (silenced by --quiet)
+        #[arg(long, default_value = "false")]
+        time: bool,
+        
+        /// Measure key derivation on this machine and warn if it is under 100ms
+        #[arg(long, default_value = "false")]
+        key_derivation_check: bool,
+        
+        /// Print the resolved plan (files, cipher, password source, overwrite policy, options) first
+        #[arg(long, default_value = "false")]
+        show_plan: bool,
+        
+        /// Print the plan and exit without reading or writing any file
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+        
+        /// Refuse input files larger than this many bytes (0 = no limit)
+        #[arg(long, value_name = "BYTES", default_value_t = scanner::DEFAULT_MAX_FILE_SIZE)]
+        max_file_size: u64,
+        
+        /// Write through a symlinked output file instead of replacing the link
+        #[arg(long, default_value = "false")]
+        dereference: bool,
+        
+        /// Give each output the modification time of its input
+        #[arg(long, default_value = "false")]
+        preserve_mtime: bool,
+        
+        /// Worker threads for batch processing (0 = automatic)
+        #[arg(long, value_name = "N", default_value = "0")]
+        threads: usize,
+        
+        /// Comment heading entries added to .gitignore (empty to omit)
+        #[arg(long, value_name = "TEXT")]
+        gitignore_comment: Option<String>,
+        
+        /// Re-encrypt files even if unchanged since the last run
+        #[arg(long, default_value = "false")]
+        force: bool,
+        
+        /// Decrypt the result in memory and compare with the input before writing
+        #[arg(long, default_value = "false")]
+        verify: bool,
+        
+        /// Keep whitespace around values byte-exact instead of trimming it
+        #[arg(long, default_value = "false")]
+        no_trim: bool,
+        
+        /// Write keys as `KEY=`, dropping whitespace around the key; --trim-keys=false keeps it as-is
+        #[arg(long, value_name = "BOOL", default_value = "true", action = ArgAction::Set)]
+        trim_keys: bool,
+        
+        /// End the output with a newline as the input does, always, or never
+        #[arg(long, value_enum, value_name = "POLICY", default_value = "preserve")]
+        final_newline: FinalNewline,
+        
+        /// Gzip the whole file before encrypting it as one blob (requires `compress` feature)
+        #[arg(long, default_value = "false")]
+        compress: bool,
+        
+        /// Replace key names with hashes; decrypt restores them from an encrypted mapping
+        #[arg(long, default_value = "false")]
+        hide_keys: bool,
+        
+        /// Record the file name in the header so decrypt restores it after a rename (stored in plain text)
+        #[arg(long, default_value = "false")]
+        store_name: bool,
+        
+        /// Cipher for new values; chacha20-poly1305 requires the `chacha20` feature
+        #[arg(long, value_enum, default_value = "aes256")]
+        cipher: Cipher,
+        
+        /// Encrypt under a random password split into N shares (`shamir` feature)
+        #[arg(
+            long,
+            value_name = "N",
+            conflicts_with_all = ["password", "keychain", "password_command", "files_from", "glob", "only_changed"]
+        )]
+        split: Option<u8>,
+        
+        /// Shares needed to decrypt a --split file
+        #[arg(long, value_name = "K", default_value = "2", requires = "split")]
+        threshold: u8,
+        
+        /// Encrypt to an age public key (age1...) instead of a password (repeatable; any one decrypts, `age` feature)
+        #[arg(
+            long = "recipient",
+            value_name = "PUBKEY",
+            conflicts_with_all = ["password", "keychain", "password_command", "split", "files_from", "glob", "only_changed"]
+        )]
+        recipients: Vec<String>,
+        
+        /// Encrypt the contents of files referenced as KEY=@path (relative to the current directory)
+        #[arg(long, default_value = "false")]
+        inline_files: bool,
+        
+        /// Encrypt exactly the files listed in FILE, one path per line ('-' for stdin)
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["file", "input", "output"])]
+        files_from: Option<PathBuf>,
+        
+        /// Encrypt every file matching a glob such as "config/**/.env" (relative to the current directory)
+        #[arg(long, value_name = "PATTERN", conflicts_with_all = ["file", "input", "output", "files_from"])]
+        glob: Option<String>,
+        
+        /// Encrypt only .env files git reports as staged, modified or untracked, plus gitignored ones newer than their .enc (for commit hooks)
+        #[arg(long, default_value = "false", conflicts_with_all = ["file", "input", "output", "files_from", "glob"])]
+        only_changed: bool,
+        
+        /// Keep encrypting the remaining files when one fails; exit non-zero at the end
+        #[arg(long, default_value = "false")]
+        keep_going: bool,
+        
+        /// Write a JSON summary of batch runs (files, var counts, statuses, timings; no values)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = report::DEFAULT_REPORT_FILE)]
+        report: Option<PathBuf>,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+        
+        /// Treat invalid environment variable names as errors
+        #[arg(long, default_value = "false")]
+        strict: bool,
+        
+        /// Collapse duplicate keys, keeping the last occurrence
+        #[arg(long, default_value = "false")]
+        dedupe: bool,
+        
+        /// Tidy the layout: collapse blank lines, use `\n` endings and one final newline, trim whitespace outside values
+        #[arg(long, default_value = "false")]
+        normalize: bool,
+        
+        /// Input format; auto detects JSON (`{`), YAML (`---` or `key: value`) and falls back to .env
+        #[arg(long, value_enum, default_value = "auto")]
+        input_format: InputFormat,
     },
     
     /// Decrypt .env.enc file
     Decrypt {
-        /// Input file (optional in interactive mode)
-        #[arg(value_name = "FILE")]
+        /// Input file, '-' for stdin or an http(s) URL (optional in interactive mode)
+        #[arg(value_name = "FILE", add = completion::env_files(ProcessMode::Decrypt))]
         file: Option<PathBuf>,
         
-        /// Password for decryption
-        #[arg(short, long, env = "WC_ENVC_PASSWORD")]
+        /// Password for decryption (default: from the --password-env variable)
+        #[arg(short, long)]
         password: Option<String>,
         
+        /// Read the password stored under this project name in the OS keychain
+        #[arg(long, value_name = "PROJECT")]
+        keychain: Option<String>,
+        
+        /// Run this command and use its trimmed stdout as the password (e.g. "pass show app/env")
+        #[arg(long, value_name = "CMD", conflicts_with_all = ["password", "keychain"])]
+        password_command: Option<String>,
+        
         /// Input file path
-        #[arg(short, long)]
+        #[arg(short, long, add = completion::env_files(ProcessMode::Decrypt))]
         input: Option<PathBuf>,
         
-        /// Output file path
+        /// Output file path ('-' for stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
         
+        /// Default output name template: {name}, {ext}, {date}, {ts} (e.g. "{name}.{date}")
+        #[arg(long, value_name = "TEMPLATE", conflicts_with = "output")]
+        output_template: Option<String>,
+        
+        /// Use local time instead of UTC for {date} and {ts}
+        #[arg(long, default_value = "false", requires = "output_template")]
+        local_time: bool,
+        
+        /// Decrypt the file for an environment: .env.<NAME>.enc -> .env
+        #[arg(long = "env", value_name = "NAME", conflicts_with_all = ["output", "output_template"])]
+        environment: Option<String>,
+        
         /// Skip confirmation prompts (overwrite files)
         #[arg(short, long, default_value = "false")]
         yes: bool,
+        
+        /// Existing output files: prompt (default), always overwrite, or never (skip them)
+        #[arg(long, value_enum, default_value = "prompt")]
+        overwrite: OverwritePolicy,
+        
+        /// In the interactive batch flow, ask about each existing output: overwrite, skip, overwrite all or quit
+        #[arg(long, default_value = "false", conflicts_with = "yes")]
+        interactive_overwrite_per_file: bool,
+        
+        /// Show a diff (values masked) of an existing output before overwriting it
+        #[arg(long, default_value = "false")]
+        diff: bool,
+        
+        /// Verify the input's .sha256 checksum file before decrypting
+        #[arg(long, default_value = "false")]
+        checksum: bool,
+        
+        /// Fail validation if any value is not encrypted
+        #[arg(long, default_value = "false")]
+        fail_on_plaintext: bool,
+        
+        /// Pass values that don't look encrypted through unchanged instead of failing (mixed files)
+        #[arg(long, default_value = "false", conflicts_with = "fail_on_plaintext")]
+        allow_plaintext_passthrough: bool,
+        
+        /// Fail validation unless at least this fraction of values looks encrypted (e.g. 0.9)
+        #[arg(long, value_name = "RATIO", default_value = "0", value_parser = parse_ratio)]
+        min_encrypted: f64,
+        
+        /// With --fail-fast=false, try every value and list all that fail before writing anything
+        #[arg(long, value_name = "BOOL", default_value = "true", action = ArgAction::Set)]
+        fail_fast: bool,
+        
+        /// Write keys as `KEY=`, dropping whitespace around the key; --trim-keys=false keeps it as-is
+        #[arg(long, value_name = "BOOL", default_value = "true", action = ArgAction::Set)]
+        trim_keys: bool,
+        
+        /// End the output with a newline as the input does, always, or never
+        #[arg(long, value_enum, value_name = "POLICY", default_value = "preserve")]
+        final_newline: FinalNewline,
+        
+        /// A share of a --split file's password (repeatable); missing shares are prompted for
+        #[arg(long = "share", value_name = "SHARE", conflicts_with_all = ["password", "keychain", "password_command"])]
+        shares: Vec<String>,
+        
+        /// age identity file (AGE-SECRET-KEY-1...) for a file encrypted with --recipient
+        #[arg(
+            long,
+            value_name = "KEYFILE",
+            conflicts_with_all = ["password", "keychain", "password_command", "shares", "files_from", "glob"]
+        )]
+        identity: Option<PathBuf>,
+        
+        /// Keep decrypting the remaining files when one fails; exit non-zero at the end
+        #[arg(long, default_value = "false")]
+        keep_going: bool,
+        
+        /// Write a JSON summary of batch runs (files, var counts, statuses, timings; no values)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = report::DEFAULT_REPORT_FILE)]
+        report: Option<PathBuf>,
+        
+        /// Write inlined KEY=@path files back out instead of restoring their Base64 contents
+        #[arg(long, default_value = "false")]
+        inline_files: bool,
+        
+        /// Decrypt exactly the files listed in FILE, one path per line ('-' for stdin)
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["file", "input", "output"])]
+        files_from: Option<PathBuf>,
+        
+        /// Decrypt every file matching a glob such as "config/**/.env" (relative to the current directory)
+        #[arg(long, value_name = "PATTERN", conflicts_with_all = ["file", "input", "output", "files_from"])]
+        glob: Option<String>,
+        
+        /// Delete the encrypted file after a successful decrypt (asks unless --yes)
+        #[arg(long, default_value = "false")]
+        remove_source: bool,
+        
+        /// Decrypt into RAM (/dev/shm, Linux only), print the path and remove it on Enter or Ctrl-C
+        #[arg(
+            long,
+            default_value = "false",
+            conflicts_with_all = [
+                "output", "output_template", "environment", "files_from", "glob", "checksum", "remove_source", "inline_files"
+            ]
+        )]
+        to_memory: bool,
+        
+        /// Print the result to stdout in the strict KEY=VALUE form `docker run --env-file` reads
+        #[arg(
+            long,
+            default_value = "false",
+            conflicts_with_all = ["output", "output_template", "environment", "files_from", "glob", "to_memory", "remove_source"]
+        )]
+        env_file_stdout: bool,
+        
+        /// Print key derivation and processing timings to stderr (silenced by --quiet)
+        #[arg(long, default_value = "false")]
+        time: bool,
+        
+        /// Measure key derivation on this machine and warn if it is under 100ms
+        #[arg(long, default_value = "false")]
+        key_derivation_check: bool,
+        
+        /// Print the resolved plan (files, cipher, password source, overwrite policy, options) first
+        #[arg(long, default_value = "false")]
+        show_plan: bool,
+        
+        /// Print the plan and exit without reading or writing any file
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+        
+        /// Refuse input files larger than this many bytes (0 = no limit)
+        #[arg(long, value_name = "BYTES", default_value_t = scanner::DEFAULT_MAX_FILE_SIZE)]
+        max_file_size: u64,
+        
+        /// Write through a symlinked output file instead of replacing the link
+        #[arg(long, default_value = "false")]
+        dereference: bool,
+        
+        /// Give each output the modification time of its input
+        #[arg(long, default_value = "false")]
+        preserve_mtime: bool,
+        
+        /// Worker threads for batch processing (0 = automatic)
+        #[arg(long, value_name = "N", default_value = "0")]
+        threads: usize,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+        
+        /// Treat invalid environment variable names as errors
+        #[arg(long, default_value = "false")]
+        strict: bool,
+        
+        /// Collapse duplicate keys, keeping the last occurrence
+        #[arg(long, default_value = "false")]
+        dedupe: bool,
+        
+        /// Tidy the layout: collapse blank lines, use `\n` endings and one final newline, trim whitespace outside values
+        #[arg(long, default_value = "false")]
+        normalize: bool,
+        
+        /// Merge in the files named by `# include: FILE` lines; the including file's keys win
+        #[arg(long, default_value = "false")]
+        follow_includes: bool,
+        
+        /// Resolve `${VAR}` / `$VAR` in values against keys defined earlier in the file
+        #[arg(long, default_value = "false")]
+        interpolate: bool,
+        
+        /// With --interpolate, fall back to the process environment for names the file does not define
+        #[arg(long, default_value = "false", requires = "interpolate")]
+        interpolate_env: bool,
+        
+        /// With --interpolate, what an undefined reference does: warn and leave it as is, or fail the file
+        #[arg(long, value_enum, value_name = "ACTION", default_value = "warn", requires = "interpolate")]
+        undefined_refs: Undefined,
     },
     
     /// Set environment variables from .env file permanently
@@ -79,44 +513,788 @@ enum Commands {
         /// Skip confirmation prompts
         #[arg(short, long, default_value = "false")]
         yes: bool,
+        
+        /// Show values in full instead of masking them
+        #[arg(long, default_value = "false")]
+        show_values: bool,
+        
+        /// Only set these keys (comma-separated)
+        #[arg(long, value_name = "KEYS", value_delimiter = ',')]
+        only: Vec<String>,
+        
+        /// Skip these keys (comma-separated)
+        #[arg(long, value_name = "KEYS", value_delimiter = ',')]
+        except: Vec<String>,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+        
+        /// Treat invalid environment variable names as errors
+        #[arg(long, default_value = "false")]
+        strict: bool,
+        
+        /// Collapse duplicate keys, keeping the last occurrence
+        #[arg(long, default_value = "false")]
+        dedupe: bool,
+        
+        /// Merge in the files named by `# include: FILE` lines; the including file's keys win
+        #[arg(long, default_value = "false")]
+        follow_includes: bool,
+        
+        /// Input format; auto detects JSON (`{`), YAML (`---` or `key: value`) and falls back to .env
+        #[arg(long, value_enum, default_value = "auto")]
+        input_format: InputFormat,
+    },
+    
+    /// Show added/removed/changed keys between two encrypted files
+    Diff {
+        /// Original encrypted file
+        #[arg(value_name = "OLD")]
+        old: PathBuf,
+        
+        /// Updated encrypted file
+        #[arg(value_name = "NEW")]
+        new: PathBuf,
+        
+        /// Password for decryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Show values in full instead of masking them
+        #[arg(long, default_value = "false")]
+        show_values: bool,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Diff driver for git: show the decrypted changes between two versions of an encrypted file
+    GitDiff {
+        /// OLD NEW, or the seven arguments git passes to `diff.<driver>.command`
+        #[arg(value_name = "ARGS", num_args = 2..=7, required = true)]
+        args: Vec<PathBuf>,
+        
+        /// Show values in full instead of masking them
+        #[arg(long, default_value = "false")]
+        show_values: bool,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// git clean filter: encrypt stdin to stdout when a file is staged (password from the environment)
+    Clean {
+        /// Path of the file being filtered, for messages (git's %f)
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// git smudge filter: decrypt stdin to stdout when a file is checked out (password from the environment)
+    Smudge {
+        /// Path of the file being filtered, for messages (git's %f)
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Decrypt a file into $EDITOR and re-encrypt it on save
+    Edit {
+        /// Encrypted file to edit
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        
+        /// Password for decryption and re-encryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Encrypt a .env file and write a key-only .env.example next to it
+    Example {
+        /// Plain .env file to encrypt
+        #[arg(value_name = "FILE", default_value = ".env")]
+        file: PathBuf,
+        
+        /// Password for encryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Value written for every key in the example file
+        #[arg(long, value_name = "TEXT", default_value = "")]
+        placeholder: String,
+        
+        /// Skip confirmation prompts (overwrite files)
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Decrypt a single key and print its value
+    Get {
+        /// Encrypted input file
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        
+        /// Key to decrypt
+        #[arg(value_name = "KEY")]
+        key: String,
+        
+        /// Password for decryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Print a shell-escaped `export KEY='value'` line for eval
+        #[arg(long, default_value = "false")]
+        export: bool,
+        
+        /// Missing key: error (default) or skip (print nothing, exit 0)
+        #[arg(long, value_enum, default_value = "error")]
+        on_missing_key: get::MissingKeyPolicy,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Print a keyed fingerprint of the decrypted variables to compare secrets without sharing them
+    Fingerprint {
+        /// Encrypted input file
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        
+        /// Password for decryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Shared key for the fingerprint (default: derived from the password)
+        #[arg(long, value_name = "KEY", env = fingerprint::FINGERPRINT_KEY_ENV_VAR, hide_env_values = true)]
+        key: Option<String>,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Show what an encrypted file's headers say (cipher, layout, recipients, ...) without the password
+    Info {
+        /// Encrypted file to describe
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        
+        /// Print JSON instead of text
+        #[arg(long, default_value = "false")]
+        json: bool,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Check env files for duplicate keys, bad names, stray whitespace and unencrypted values
+    #[command(alias = "check-format")]
+    Lint {
+        /// Files to check (default: the plain and encrypted env files in the current directory)
+        #[arg(value_name = "FILE")]
+        files: Vec<PathBuf>,
+        
+        /// Report these rules as errors (comma-separated or repeated)
+        #[arg(long, value_name = "RULE", value_enum, value_delimiter = ',')]
+        deny: Vec<lint::Rule>,
+        
+        /// Report these rules as warnings, which don't fail the run
+        #[arg(long, value_name = "RULE", value_enum, value_delimiter = ',')]
+        warn: Vec<lint::Rule>,
+        
+        /// Don't report these rules
+        #[arg(long, value_name = "RULE", value_enum, value_delimiter = ',')]
+        allow: Vec<lint::Rule>,
+        
+        /// Check only the plain and encrypted .env files git reports as changed (for commit hooks)
+        #[arg(long, default_value = "false", conflicts_with = "files")]
+        only_changed: bool,
+        
+        /// Extra comment prefix besides '#' (repeatable, e.g. ';' or '//')
+        #[arg(long = "comment-prefix", value_name = "PREFIX")]
+        comment_prefixes: Vec<String>,
+    },
+    
+    /// Add or remove recipients of a file encrypted with --recipient, without re-encrypting its values
+    Rekey {
+        /// Encrypted file to update in place
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        
+        /// age identity file of a current recipient, to unwrap the file key
+        #[arg(long, value_name = "KEYFILE")]
+        identity: PathBuf,
+        
+        /// Public key (age1...) to give access (repeatable)
+        #[arg(long, value_name = "PUBKEY", required_unless_present = "remove")]
+        add: Vec<String>,
+        
+        /// Public key (age1...) to take off the file (repeatable)
+        #[arg(long, value_name = "PUBKEY")]
+        remove: Vec<String>,
+    },
+    
+    /// Hold the password in memory and serve it to later runs (like ssh-agent, Unix only)
+    Agent {
+        /// Seconds to keep the password before forgetting it
+        #[arg(long, value_name = "SECONDS", default_value_t = agent::DEFAULT_TTL_SECS)]
+        ttl: u64,
+    },
+    
+    /// Print the env files interactive mode would offer, one path per line (for xargs)
+    Scan {
+        /// Which files to list
+        #[arg(long, value_enum, default_value = "encrypt")]
+        mode: ProcessMode,
+        
+        /// List encrypted files (same as --mode decrypt)
+        #[arg(long, default_value = "false", conflicts_with_all = ["mode", "plain"])]
+        encrypted: bool,
+        
+        /// List plain files (same as --mode encrypt)
+        #[arg(long, default_value = "false", conflicts_with = "mode")]
+        plain: bool,
+        
+        /// Also scan subdirectories (skips .git, node_modules and target)
+        #[arg(short, long, default_value = "false")]
+        recursive: bool,
+        
+        /// Skip files and directories matching this glob (path relative to the scan or name; repeatable)
+        #[arg(long, value_name = "GLOB", value_parser = scanner::parse_pattern)]
+        exclude: Vec<glob::Pattern>,
+        
+        /// Skip files ignored by git
+        #[arg(long, default_value = "false")]
+        respect_gitignore: bool,
+        
+        /// Stable output for scripts: status<TAB>path<TAB>vars (status from the content: P plain, E encrypted, ! unreadable)
+        #[arg(long, default_value = "false")]
+        porcelain: bool,
+        
+        /// Print only totals (files, encrypted, plain, variables) as name<TAB>count lines
+        #[arg(long, default_value = "false", conflicts_with_all = ["mode", "encrypted", "plain", "porcelain"])]
+        count_only: bool,
+        
+        /// Print JSON instead of text: the files with their status and variable count, or the totals with --count-only
+        #[arg(long, default_value = "false", conflicts_with = "porcelain")]
+        json: bool,
+        
+        /// End each entry with NUL instead of a newline and leave paths unquoted (for xargs -0)
+        #[arg(short = 'z', default_value = "false", conflicts_with_all = ["json", "count_only"])]
+        zero: bool,
+    },
+    
+    /// Bundle several environments into one encrypted .env.vault
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommand,
+    },
+    
+    /// Update wc-envc to the latest GitHub release
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Skip confirmation prompt
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
+        
+        /// Only report the available version
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum VaultCommand {
+    /// Encrypt env files into one vault (default: all .env files in the current directory)
+    Build {
+        /// Env files to bundle; the section name is the suffix (.env.production -> production)
+        #[arg(value_name = "FILES")]
+        files: Vec<PathBuf>,
+        
+        /// Vault file path
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        
+        /// Password for encryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Skip confirmation prompts (overwrite files)
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
+    },
+    
+    /// Decrypt one environment from a vault
+    Open {
+        /// Vault file (default: .env.vault)
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+        
+        /// Environment to decrypt
+        #[arg(short, long)]
+        env: String,
+        
+        /// Output file path (default: .env.<env>, '-' for stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        
+        /// Password for decryption (default: from the --password-env variable)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Skip confirmation prompts (overwrite files)
+        #[arg(short, long, default_value = "false")]
+        yes: bool,
     },
 }
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!();
-        eprintln!("{} {}", style("❌").red(), style(e).red());
-        eprintln!();
-        eprintln!("{} Run '{}' to see available commands.", 
-            style("💡").yellow(),
-            style("wc-envc -h").cyan()
-        );
-        process::exit(1);
+    completion::complete::<Cli>();
+    let update_check = update::spawn_check();
+    let result = run();
+    update::print_notice(update_check);
+    
+    if let Err(e) = result {
+        eprint!("{}", error_report(&e, console::colors_enabled_stderr()));
+        process::exit(exit_code(&e));
+    }
+}
+
+/// Text printed for a failed run: deliberate cancellations are neutral, errors red with a hint
+/// `colors` is passed in instead of read from the global console setting
+fn error_report(e: &anyhow::Error, colors: bool) -> String {
+    let paint = |text: String| style(text).force_styling(colors);
+    if e.downcast_ref::<cancel::Cancelled>().is_some() {
+        return format!("\n{}\n", paint(e.to_string()).dim());
+    }
+    
+    let mut report = format!("\n{} {}\n", paint("❌".to_string()).red(), paint(e.to_string()).red());
+    if exit_code(e) == 1 {
+        report.push_str(&format!(
+            "\n{} Run '{}' to see available commands.\n",
+            paint("💡".to_string()).yellow(),
+            paint("wc-envc -h".to_string()).cyan()
+        ));
+    }
+    report
+}
+
+/// `--yes` answers the overwrite question, so the prompt policy becomes `always`
+fn yes_overwrites(yes: bool, overwrite: OverwritePolicy) -> OverwritePolicy {
+    match overwrite {
+        OverwritePolicy::Prompt if yes => OverwritePolicy::Always,
+        policy => policy,
+    }
+}
+
+/// Map an error to the process exit code (1 for general errors)
+fn exit_code(e: &anyhow::Error) -> i32 {
+    if e.downcast_ref::<cancel::Cancelled>().is_some() {
+        cancel::EXIT_CODE
+    } else if e.downcast_ref::<scanner::NoFilesFound>().is_some() {
+        scanner::NO_FILES_EXIT_CODE
+    } else {
+        1
+    }
+}
+
+/// Parse a fraction between 0 and 1 (for --min-encrypted)
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    let ratio: f64 = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(format!("{} is not between 0 and 1", ratio));
+    }
+    Ok(ratio)
+}
+
+/// Decide whether a stream should be colored
+fn colors_enabled(choice: ColorChoice, no_color: bool, is_term: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color && is_term,
     }
 }
 
+/// Apply the color policy globally for stdout and stderr
+fn configure_colors(choice: ColorChoice) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    console::set_colors_enabled(colors_enabled(choice, no_color, Term::stdout().is_term()));
+    console::set_colors_enabled_stderr(colors_enabled(choice, no_color, Term::stderr().is_term()));
+}
+
 fn run() -> Result<()> {
     let cli = Cli::parse();
+    configure_colors(cli.color);
+    cancel::install_handler()?;
+    interactive::set_password_env(&cli.password_env);
+    interactive::set_password_min_length(cli.password_min_length);
+    interactive::set_password_attempts(cli.password_attempts);
     
     match cli.command {
-        Commands::Encrypt { file, password, input, output, yes } => {
-            handle_encrypt(file, password, input, output, yes)
+        Commands::Encrypt {
+            file, password, keychain, password_command, input, output, output_template, local_time, environment, yes,
+            overwrite, interactive_overwrite_per_file, diff, checksum, armor, time, key_derivation_check, show_plan, dry_run, max_file_size,
+            dereference, preserve_mtime, threads, gitignore_comment, force, verify, no_trim, trim_keys, final_newline, compress, hide_keys, store_name, cipher, split,
+            threshold, recipients, inline_files, files_from, glob, only_changed, keep_going, report, comment_prefixes, strict,
+            dedupe, normalize, input_format,
+        } => {
+            let process = ProcessOptions {
+                strict,
+                dedupe,
+                normalize,
+                input_format,
+                trim: !no_trim,
+                trim_keys,
+                final_newline,
+                compress,
+                hide_keys,
+                cipher,
+                shares: split.map(|count| (threshold, count)),
+                inline_files: inline_files.then(|| PathBuf::from(".")),
+                ..ProcessOptions::with_comment_prefixes(&comment_prefixes)
+            };
+            let opts = RunOptions {
+                checksum, time, armor, max_file_size, dereference, preserve_mtime, threads, gitignore_comment,
+                force, verify, keychain, password_command, output_template, environment, keep_going, report, store_name,
+                diff, process,
+                overwrite: yes_overwrites(yes, overwrite),
+                overwrite_per_file: interactive_overwrite_per_file,
+                time_zone: if local_time { TimeZone::Local } else { TimeZone::Utc },
+                password_from_arg: password.is_some(),
+                quiet: cli.quiet,
+                ..Default::default()
+            };
+            if key_derivation_check {
+                interactive::check_key_derivation(&engine::FileCipher::generate(cipher)?)?;
+            }
+            let files = match (files_from, glob, only_changed) {
+                (Some(list), _, _) => Some(scanner::read_file_list(&list)?),
+                (None, Some(pattern), _) => Some(glob_matches(&pattern)?),
+                (None, None, true) => {
+                    let files = scanner::changed_env_files(Path::new("."), ProcessMode::Encrypt)?;
+                    if files.is_empty() {
+                        if !cli.quiet {
+                            println!("{} Nothing to do: no changed .env files", style("ℹ️").cyan());
+                        }
+                        return Ok(());
+                    }
+                    Some(files)
+                }
+                (None, None, false) => None,
+            };
+            if show_plan || dry_run {
+                let single = input.clone().or_else(|| file.clone()).map(|input| vec![input]);
+                plan::show_plan(ProcessMode::Encrypt, files.clone().or(single), output.as_deref(), &recipients, &opts)?;
+                if dry_run {
+                    return Ok(());
+                }
+            }
+            if let Some(count) = split {
+                return handle_split_encrypt(file, input, output, count, threshold, yes, &opts);
+            }
+            if !recipients.is_empty() {
+                return handle_recipient_encrypt(file, input, output, &recipients, yes, &opts);
+            }
+            let password = with_env_password(password);
+            match files {
+                Some(files) => interactive::run_file_list(files, password, yes, ProcessMode::Encrypt, &opts),
+                None => handle_encrypt(file, password, input, output, yes, &opts),
+            }
+        }
+        Commands::Decrypt {
+            file, password, keychain, password_command, input, output, output_template, local_time, environment, yes,
+            overwrite, interactive_overwrite_per_file, diff, checksum, fail_on_plaintext, allow_plaintext_passthrough, min_encrypted, fail_fast, trim_keys, final_newline, shares, identity, keep_going, report,
+            inline_files, files_from, glob, remove_source, to_memory, env_file_stdout, time, key_derivation_check, show_plan, dry_run,
+            max_file_size, dereference, preserve_mtime, threads, comment_prefixes, strict, dedupe, normalize, follow_includes,
+            interpolate, interpolate_env, undefined_refs,
+        } => {
+            let process = ProcessOptions {
+                fail_on_plaintext,
+                allow_plaintext: allow_plaintext_passthrough,
+                min_encrypted,
+                fail_fast,
+                trim_keys,
+                final_newline,
+                inline_files: inline_files.then(|| PathBuf::from(".")),
+                strict,
+                dedupe,
+                normalize,
+                follow_includes,
+                ..ProcessOptions::with_comment_prefixes(&comment_prefixes)
+            };
+            let opts = RunOptions {
+                checksum, time, remove_source, max_file_size, dereference, preserve_mtime, threads, keychain,
+                password_command, output_template, environment, keep_going, report, diff, process,
+                docker_env_file: env_file_stdout,
+                interpolate: interpolate.then_some(Interpolation { env: interpolate_env, undefined: undefined_refs }),
+                overwrite: yes_overwrites(yes, overwrite),
+                overwrite_per_file: interactive_overwrite_per_file,
+                time_zone: if local_time { TimeZone::Local } else { TimeZone::Utc },
+                password_from_arg: password.is_some(),
+                quiet: cli.quiet,
+                ..Default::default()
+            };
+            let files = match (files_from, glob) {
+                (Some(list), _) => Some(scanner::read_file_list(&list)?),
+                (None, Some(pattern)) => Some(glob_matches(&pattern)?),
+                (None, None) => None,
+            };
+            if key_derivation_check {
+                let inputs = files.clone().or_else(|| input.clone().or_else(|| file.clone()).map(|input| vec![input]));
+                for cipher in interactive::header_ciphers(&inputs.unwrap_or_default(), &opts)? {
+                    interactive::check_key_derivation(&cipher)?;
+                }
+            }
+            if show_plan || dry_run {
+                let single = input.clone().or_else(|| file.clone()).map(|input| vec![input]);
+                plan::show_plan(ProcessMode::Decrypt, files.clone().or(single), output.as_deref(), &[], &opts)?;
+                if dry_run {
+                    return Ok(());
+                }
+            }
+            let password = match input.as_ref().or(file.as_ref()) {
+                Some(path) if password.is_none() && !opts.has_external_password() => {
+                    match recipient::password_from_identity(path, identity.as_deref(), opts.max_file_size)? {
+                        Some(key) => Some(key),
+                        None => shares::password_from_shares(path, shares, opts.max_file_size)?.or_else(|| with_env_password(None)),
+                    }
+                }
+                None if identity.is_some() => anyhow::bail!("--identity needs an input file"),
+                _ => with_env_password(password),
+            };
+            match (files, input.or(file)) {
+                (Some(files), _) => interactive::run_file_list(files, password, yes, ProcessMode::Decrypt, &opts),
+                (None, Some(input)) if to_memory => memfile::decrypt_to_memory(input, password, &opts),
+                (None, None) if to_memory => anyhow::bail!("--to-memory needs an input file"),
+                (None, Some(input)) if env_file_stdout => {
+                    interactive::run_one_liner(input, PathBuf::from("-"), password, yes, ProcessMode::Decrypt, &opts)
+                }
+                (None, None) if env_file_stdout => anyhow::bail!("--env-file-stdout needs an input file"),
+                (None, input) => handle_decrypt(None, password, input, output, yes, &opts),
+            }
+        }
+        Commands::Setenv {
+            file, yes, show_values, only, except, comment_prefixes, strict, dedupe, follow_includes, input_format,
+        } => {
+            let process = ProcessOptions {
+                strict,
+                dedupe,
+                follow_includes,
+                input_format,
+                ..ProcessOptions::with_comment_prefixes(&comment_prefixes)
+            };
+            setenv::handle_setenv(file, yes, show_values, &only, &except, &process)
+        }
+        Commands::Diff { old, new, password, show_values, comment_prefixes } => {
+            let opts = RunOptions {
+                max_file_size: scanner::DEFAULT_MAX_FILE_SIZE,
+                process: ProcessOptions::with_comment_prefixes(&comment_prefixes),
+                ..Default::default()
+            };
+            // Exit non-zero when the files differ, like diff(1)
+            if diff::handle_diff(&old, &new, password, show_values, &opts)? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        Commands::GitDiff { args, show_values, comment_prefixes } => {
+            let opts = RunOptions {
+                max_file_size: scanner::DEFAULT_MAX_FILE_SIZE,
+                process: ProcessOptions::with_comment_prefixes(&comment_prefixes),
+                ..Default::default()
+            };
+            diff::handle_git_diff(&args, show_values, &opts)
+        }
+        Commands::Clean { file, comment_prefixes } => {
+            gitfilter::handle_clean(file.as_deref(), &ProcessOptions::with_comment_prefixes(&comment_prefixes))
+        }
+        Commands::Smudge { file, comment_prefixes } => {
+            gitfilter::handle_smudge(file.as_deref(), &ProcessOptions::with_comment_prefixes(&comment_prefixes))
+        }
+        Commands::Edit { file, password, comment_prefixes } => {
+            edit::handle_edit(&file, password, &ProcessOptions::with_comment_prefixes(&comment_prefixes))
+        }
+        Commands::Example { file, password, placeholder, yes, comment_prefixes } => {
+            let opts = RunOptions {
+                max_file_size: scanner::DEFAULT_MAX_FILE_SIZE,
+                password_from_arg: password.is_some(),
+                quiet: cli.quiet,
+                overwrite: yes_overwrites(yes, OverwritePolicy::Prompt),
+                process: ProcessOptions::with_comment_prefixes(&comment_prefixes),
+                ..Default::default()
+            };
+            example::handle_example(&file, with_env_password(password), &placeholder, yes, &opts)
+        }
+        Commands::Get { file, key, password, export, on_missing_key, comment_prefixes } => {
+            let opts = RunOptions {
+                max_file_size: scanner::DEFAULT_MAX_FILE_SIZE,
+                process: ProcessOptions::with_comment_prefixes(&comment_prefixes),
+                ..Default::default()
+            };
+            get::handle_get(&file, &key, password, export, on_missing_key, &opts)
+        }
+        Commands::Fingerprint { file, password, key, comment_prefixes } => {
+            let opts = RunOptions {
+                max_file_size: scanner::DEFAULT_MAX_FILE_SIZE,
+                process: ProcessOptions::with_comment_prefixes(&comment_prefixes),
+                ..Default::default()
+            };
+            fingerprint::handle_fingerprint(&file, password, key, &opts)
         }
-        Commands::Decrypt { file, password, input, output, yes } => {
-            handle_decrypt(file, password, input, output, yes)
+        Commands::Info { file, json, comment_prefixes } => {
+            info::handle_info(&file, json, &ProcessOptions::with_comment_prefixes(&comment_prefixes))
         }
-        Commands::Setenv { file, yes } => {
-            setenv::handle_setenv(file, yes)
+        Commands::Lint { files, deny, warn, allow, comment_prefixes, only_changed } => {
+            let files = if only_changed {
+                let mut changed = scanner::changed_env_files(Path::new("."), ProcessMode::Encrypt)?;
+                changed.extend(scanner::changed_env_files(Path::new("."), ProcessMode::Decrypt)?);
+                if changed.is_empty() {
+                    if !cli.quiet {
+                        println!("{} Nothing to do: no changed .env files", style("ℹ️").cyan());
+                    }
+                    return Ok(());
+                }
+                changed
+            } else {
+                files
+            };
+            let severities = lint::Severities { deny, warn, allow };
+            lint::handle_lint(files, &severities, &ProcessOptions::with_comment_prefixes(&comment_prefixes))
         }
+        Commands::Rekey { file, identity, add, remove } => recipient::handle_rekey(&file, &identity, &add, &remove),
+        Commands::Agent { ttl } => agent::handle_agent(ttl),
+        Commands::Scan { mode, encrypted, plain, recursive, exclude, respect_gitignore, porcelain, count_only, json, zero } => {
+            let mode = if encrypted {
+                ProcessMode::Decrypt
+            } else if plain {
+                ProcessMode::Encrypt
+            } else {
+                mode
+            };
+            let scanner = exclude
+                .into_iter()
+                .fold(scanner::Scanner::new(".", mode), scanner::Scanner::exclude)
+                .recursive(recursive)
+                .respect_gitignore(respect_gitignore);
+            scanner::handle_scan(&scanner, porcelain, count_only, json, zero)
+        }
+        Commands::Vault { command } => match command {
+            VaultCommand::Build { files, output, password, yes } => {
+                vault::handle_vault_build(files, output, password, yes, &ProcessOptions::default())
+            }
+            VaultCommand::Open { file, env, output, password, yes } => {
+                vault::handle_vault_open(file, &env, output, password, yes, &ProcessOptions::default())
+            }
+        },
+        #[cfg(feature = "self-update")]
+        Commands::SelfUpdate { yes, dry_run } => {
+            update::handle_self_update(yes, dry_run)
+        }
+    }
+}
+
+/// Expand --glob and say how many files it matched, warning about paths it could not read
+fn glob_matches(pattern: &str) -> Result<Vec<PathBuf>> {
+    let matches = scanner::glob_files(pattern)?;
+    for error in &matches.errors {
+        eprintln!(
+            "{} Skipped {}: {}",
+            style("⚠️").yellow().for_stderr(),
+            style(error.path().display()).cyan().for_stderr(),
+            error.error()
+        );
     }
+    
+    let files = scanner::require_files(matches.files, &format!("files matching '{}'", pattern))?;
+    println!("{} {} file(s) matched {}", style("🔎").cyan(), files.len(), style(pattern).cyan());
+    Ok(files)
+}
+
+/// Fall back to the --password-env variable so it also selects one-liner mode
+fn with_env_password(password: Option<String>) -> Option<SecretString> {
+    password.or_else(interactive::password_from_env).map(SecretString::new)
+}
+
+/// `encrypt --split`: encrypt one file under a random password, then print its shares
+fn handle_split_encrypt(
+    file: Option<PathBuf>,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    count: u8,
+    threshold: u8,
+    yes: bool,
+    opts: &RunOptions,
+) -> Result<()> {
+    let Some(input_path) = input.or(file) else {
+        anyhow::bail!("--split needs an input file");
+    };
+    
+    let secret = shares::generate_secret();
+    let split = shares::split(&secret, threshold, count)?;
+    let output_path = match output {
+        Some(path) => path,
+        None => interactive::output_path(&input_path, ProcessMode::Encrypt, opts)?,
+    };
+    
+    let to_stdout = scanner::is_stdio(&output_path);
+    interactive::run_one_liner(input_path, output_path, Some(secret), yes, ProcessMode::Encrypt, opts)?;
+    shares::print_shares(&split, threshold, to_stdout);
+    Ok(())
+}
+
+/// `encrypt --recipient`: encrypt one file under a random key that only the recipients' identities unwrap
+fn handle_recipient_encrypt(
+    file: Option<PathBuf>,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    recipients: &[String],
+    yes: bool,
+    opts: &RunOptions,
+) -> Result<()> {
+    let Some(input_path) = input.or(file) else {
+        anyhow::bail!("--recipient needs an input file");
+    };
+    
+    let (key, wrapped_keys) = recipient::wrap_new_key(recipients)?;
+    let output_path = match output {
+        Some(path) => path,
+        None => interactive::output_path(&input_path, ProcessMode::Encrypt, opts)?,
+    };
+    
+    let opts = RunOptions {
+        process: ProcessOptions { recipient_keys: wrapped_keys, ..opts.process.clone() },
+        ..opts.clone()
+    };
+    interactive::run_one_liner(input_path, output_path, Some(key), yes, ProcessMode::Encrypt, &opts)
 }
 
 fn handle_encrypt(
     file: Option<PathBuf>,
-    password: Option<String>,
+    password: Option<SecretString>,
     input: Option<PathBuf>,
     output: Option<PathBuf>,
     yes: bool,
+    opts: &RunOptions,
 ) -> Result<()> {
     // Determine input file: -i flag takes priority over positional arg
     let input_file = input.or(file);
@@ -129,35 +1307,39 @@ fn handle_encrypt(
             password,
             yes,
             ProcessMode::Encrypt,
+            opts,
         )
     } else if let Some(ref input_path) = input_file {
-        // Quick mode: file specified but no output
-        if password.is_some() && output.is_none() {
+        // Quick mode: file specified but no output (stdin is always a one-liner)
+        let streamed = scanner::is_stdio(input_path);
+        if (password.is_some() || opts.has_external_password() || streamed) && output.is_none() {
             // One-liner with default output
-            let default_output = scanner::default_output_name(input_path, ProcessMode::Encrypt);
+            let default_output = interactive::output_path(input_path, ProcessMode::Encrypt, opts)?;
             interactive::run_one_liner(
                 input_path.clone(),
                 default_output,
                 password,
                 yes,
                 ProcessMode::Encrypt,
+                opts,
             )
         } else {
             // Interactive mode with pre-selected file
-            interactive::run_interactive_encrypt(Some(input_path.clone()))
+            interactive::run_interactive_encrypt(Some(input_path.clone()), opts)
         }
     } else {
         // Full interactive mode
-        interactive::run_interactive_encrypt(None)
+        interactive::run_interactive_encrypt(None, opts)
     }
 }
 
 fn handle_decrypt(
     file: Option<PathBuf>,
-    password: Option<String>,
+    password: Option<SecretString>,
     input: Option<PathBuf>,
     output: Option<PathBuf>,
     yes: bool,
+    opts: &RunOptions,
 ) -> Result<()> {
     // Determine input file: -i flag takes priority over positional arg
     let input_file = input.or(file);
@@ -170,25 +1352,70 @@ fn handle_decrypt(
             password,
             yes,
             ProcessMode::Decrypt,
+            opts,
         )
     } else if let Some(ref input_path) = input_file {
-        // Quick mode: file specified but no output
-        if password.is_some() && output.is_none() {
+        // Quick mode: file specified but no output (stdin/URL inputs are always one-liners)
+        let streamed = scanner::is_stdio(input_path) || scanner::is_url(input_path);
+        if (password.is_some() || opts.has_external_password() || streamed) && output.is_none() {
             // One-liner with default output
-            let default_output = scanner::default_output_name(input_path, ProcessMode::Decrypt);
+            let default_output = interactive::output_path(input_path, ProcessMode::Decrypt, opts)?;
             interactive::run_one_liner(
                 input_path.clone(),
                 default_output,
                 password,
                 yes,
                 ProcessMode::Decrypt,
+                opts,
             )
         } else {
             // Interactive mode with pre-selected file
-            interactive::run_interactive_decrypt(Some(input_path.clone()))
+            interactive::run_interactive_decrypt(Some(input_path.clone()), opts)
         }
     } else {
         // Full interactive mode
-        interactive::run_interactive_decrypt(None)
+        interactive::run_interactive_decrypt(None, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_no_color_disables_escape_codes() {
+        assert!(!colors_enabled(ColorChoice::Auto, true, true));
+        assert!(colors_enabled(ColorChoice::Auto, false, true));
+        assert!(!colors_enabled(ColorChoice::Auto, false, false));
+        assert!(colors_enabled(ColorChoice::Always, true, false));
+        
+        console::set_colors_enabled(colors_enabled(ColorChoice::Auto, true, true));
+        let rendered = style("secret").red().to_string();
+        assert_eq!(rendered, "secret");
+        assert!(!rendered.contains('\x1b'));
+    }
+    
+    #[test]
+    fn test_exit_codes() {
+        let no_files = scanner::require_files(Vec::new(), ".env files").unwrap_err();
+        assert_eq!(exit_code(&no_files), scanner::NO_FILES_EXIT_CODE);
+        assert_eq!(exit_code(&anyhow::Error::new(cancel::Cancelled::Interrupted)), cancel::EXIT_CODE);
+        assert_eq!(exit_code(&anyhow::anyhow!("File not found: .env")), 1);
+    }
+    
+    #[test]
+    fn test_user_cancel_is_not_an_error() {
+        let cancelled = anyhow::Error::new(cancel::Cancelled::ByUser);
+        assert_eq!(exit_code(&cancelled), 130);
+        
+        let report = error_report(&cancelled, true);
+        assert!(report.contains("Operation cancelled"));
+        assert!(!report.contains("❌"));
+        assert!(!report.contains("wc-envc -h"));
+        assert!(!report.contains("\x1b[31m"));
+        
+        let failure = error_report(&anyhow::anyhow!("File not found: .env"), true);
+        assert!(failure.contains("❌") && failure.contains("wc-envc -h") && failure.contains("\x1b[31m"));
+        assert!(!error_report(&anyhow::anyhow!("File not found: .env"), false).contains('\x1b'));
     }
 }
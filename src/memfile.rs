@@ -0,0 +1,193 @@
+//! Short-lived plaintext files kept in RAM (tmpfs) where the platform allows it
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use console::style;
+use secrecy::SecretString;
+
+use crate::cancel;
+use crate::engine::ProcessMode;
+use crate::interactive::{self, OverwritePolicy, RunOptions};
+
+/// RAM-backed directory on Linux
+const SHM_DIR: &str = "/dev/shm";
+
+/// Decrypted copy of a file; overwritten and removed on drop
+pub struct TempPlaintext {
+    path: PathBuf,
+}
+
+impl TempPlaintext {
+    /// Create a new owner-only (600) temp file holding the plaintext, in RAM when available
+    pub fn create(content: &str) -> Result<Self> {
+        Self::create_in(&temp_dir(), content)
+    }
+    
+    /// Like `create`, but fail instead of falling back to a disk-backed directory
+    pub fn create_in_memory(content: &str) -> Result<Self> {
+        let dir = memory_dir()
+            .ok_or_else(|| anyhow::anyhow!("--to-memory needs a tmpfs {} (Linux only)", SHM_DIR))?;
+        Self::create_in(&dir, content)
+    }
+    
+    fn create_in(dir: &Path, content: &str) -> Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let path = dir.join(format!("wc-envc-{}-{}.env", std::process::id(), nanos));
+        
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        
+        let mut file = options.open(&path)?;
+        let temp = Self { path };
+        file.write_all(content.as_bytes())?;
+        
+        Ok(temp)
+    }
+    
+    /// Location of the plaintext
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempPlaintext {
+    fn drop(&mut self) {
+        // Overwrite with zeros before unlinking so the plaintext doesn't linger
+        if let Ok(len) = fs::metadata(&self.path).map(|m| m.len()) {
+            if let Ok(mut file) = OpenOptions::new().write(true).open(&self.path) {
+                let _ = file.write_all(&vec![0; len as usize]);
+                let _ = file.sync_all();
+            }
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// RAM-backed /dev/shm on Linux, None elsewhere or when it is not actually a tmpfs mount
+fn memory_dir() -> Option<PathBuf> {
+    let shm = Path::new(SHM_DIR);
+    is_tmpfs(shm).then(|| shm.to_path_buf())
+}
+
+/// Whether `path` is on a tmpfs mount, by its file system magic number
+#[cfg(target_os = "linux")]
+fn is_tmpfs(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a plain C struct statfs fills in
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let found = unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } == 0;
+    // f_type's width differs between targets
+    #[allow(clippy::unnecessary_cast)]
+    let tmpfs = stat.f_type as i64 == libc::TMPFS_MAGIC as i64;
+    found && tmpfs
+}
+
+/// Only Linux has a tmpfs /dev/shm to rely on
+#[cfg(not(target_os = "linux"))]
+fn is_tmpfs(_path: &Path) -> bool {
+    false
+}
+
+/// Directory for plaintext copies: RAM-backed when available, the system temp dir otherwise
+fn temp_dir() -> PathBuf {
+    memory_dir().unwrap_or_else(env::temp_dir)
+}
+
+/// Handle `decrypt --to-memory`
+/// Decrypts into /dev/shm, prints the path and removes the file on Enter, end of input or Ctrl-C
+pub fn decrypt_to_memory(input: PathBuf, password: Option<SecretString>, opts: &RunOptions) -> Result<()> {
+    let temp = TempPlaintext::create_in_memory("")?;
+    // stdout carries only the path, so status lines go to stderr
+    let opts = RunOptions { overwrite: OverwritePolicy::Always, status_to_stderr: true, ..opts.clone() };
+    interactive::run_one_liner(input, temp.path().to_path_buf(), password, true, ProcessMode::Decrypt, &opts)?;
+    
+    println!("{}", temp.path().display());
+    eprintln!(
+        "{} Plaintext is in memory only; press Enter or Ctrl-C to remove it",
+        style("🧠").cyan().for_stderr()
+    );
+    wait_for_release();
+    
+    drop(temp);
+    eprintln!("{} Removed the in-memory copy", style("✅").green().for_stderr());
+    Ok(())
+}
+
+/// Block until Enter, end of stdin or Ctrl-C
+fn wait_for_release() {
+    // Ctrl-C only sets a flag while the guard is held, so the file is still cleaned up
+    let _guard = cancel::WriteGuard::new();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = std::io::stdin().lock().read_line(&mut String::new());
+        let _ = tx.send(());
+    });
+    
+    while !cancel::is_cancelled() {
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            _ => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// File system type of the mount holding `path`, from /proc/self/mounts
+    #[cfg(target_os = "linux")]
+    fn fs_type(path: &Path) -> String {
+        let mounts = fs::read_to_string("/proc/self/mounts").unwrap();
+        mounts
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace().skip(1);
+                Some((fields.next()?, fields.next()?))
+            })
+            .filter(|(mount_point, _)| path.starts_with(mount_point))
+            .max_by_key(|(mount_point, _)| mount_point.len())
+            .map(|(_, fs_type)| fs_type.to_string())
+            .unwrap()
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_in_memory_plaintext_is_on_tmpfs() {
+        if memory_dir().is_none() {
+            return;
+        }
+        
+        let temp = TempPlaintext::create_in_memory("DB_PASS=secret\n").unwrap();
+        let path = temp.path().to_path_buf();
+        assert_eq!(fs_type(&path), "tmpfs");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "DB_PASS=secret\n");
+        
+        drop(temp);
+        assert!(!path.exists());
+    }
+    
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_plain_directory_is_not_tmpfs() {
+        let dir = tempfile::tempdir_in(env::current_dir().unwrap()).unwrap();
+        assert_eq!(is_tmpfs(dir.path()), fs_type(dir.path()) == "tmpfs");
+        assert!(!is_tmpfs(Path::new("/nonexistent/wc-envc")));
+    }
+}
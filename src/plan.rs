@@ -0,0 +1,255 @@
+//! `--show-plan` / `--dry-run`: the resolved operation, printed before any file is read or written
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use console::style;
+
+use crate::engine::{self, Cipher, FinalNewline, ProcessMode};
+use crate::interactive::{self, OverwritePolicy, RunOptions};
+use crate::interpolate::Undefined;
+use crate::keypolicy::{self, KeyPolicy};
+
+/// Print the plan for `inputs` (None = interactive selection) and `encrypt --recipient` public keys
+/// A single input takes `output` if given, like the one-liner does
+pub fn show_plan(
+    mode: ProcessMode,
+    inputs: Option<Vec<PathBuf>>,
+    output: Option<&Path>,
+    recipients: &[String],
+    opts: &RunOptions,
+) -> Result<()> {
+    let files = match inputs {
+        Some(inputs) => Some(
+            inputs
+                .into_iter()
+                .map(|input| {
+                    let output = match output {
+                        Some(output) => output.to_path_buf(),
+                        None => interactive::output_path(&input, mode, opts)?,
+                    };
+                    Ok((input, output))
+                })
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+    
+    // Encrypting follows the `.wc-envc-keys` next to each input; decrypting follows the file header
+    let policies = match (mode, &files) {
+        (ProcessMode::Encrypt, Some(files)) => {
+            let mut policies = Vec::new();
+            for (input, _) in files {
+                if let Some(policy) = keypolicy::find(input)? {
+                    policies.push((input.clone(), policy));
+                }
+            }
+            Some(policies)
+        }
+        _ => None,
+    };
+    
+    let env_password = interactive::password_from_env().is_some();
+    let context = PlanContext { files: files.as_deref(), recipients, policies: policies.as_deref(), env_password };
+    print!("{}", render_plan(mode, &context, opts));
+    Ok(())
+}
+
+/// What the plan is about besides the options
+struct PlanContext<'a> {
+    /// (input, output) pairs; None when files are chosen interactively
+    files: Option<&'a [(PathBuf, PathBuf)]>,
+    /// `encrypt --recipient` public keys
+    recipients: &'a [String],
+    /// Encrypted inputs with a `.wc-envc-keys` policy; None when not known up front
+    policies: Option<&'a [(PathBuf, KeyPolicy)]>,
+    /// Whether the password comes from the environment
+    env_password: bool,
+}
+
+/// Render the plan: files, cipher and key derivation, recipients, key policy, password source,
+/// overwrite policy, options
+fn render_plan(mode: ProcessMode, context: &PlanContext, opts: &RunOptions) -> String {
+    let process = &opts.process;
+    let mut plan = format!(
+        "{} Plan: {}\n",
+        style("📋").cyan(),
+        match mode {
+            ProcessMode::Encrypt => "encrypt",
+            ProcessMode::Decrypt => "decrypt",
+        }
+    );
+    
+    match context.files {
+        Some(files) => {
+            plan.push_str(&format!("  files ({}):\n", files.len()));
+            for (input, output) in files {
+                plan.push_str(&format!("    {} -> {}\n", input.display(), output.display()));
+            }
+        }
+        None => plan.push_str("  files: chosen interactively\n"),
+    }
+    
+    let (cipher, kdf) = match mode {
+        ProcessMode::Encrypt => (
+            process.cipher.name().to_string(),
+            engine::key_derivation(process.cipher.kdf(), context.recipients.len()).to_string(),
+        ),
+        ProcessMode::Decrypt => (
+            format!("from the file header (default {})", Cipher::default().name()),
+            "from the file header".to_string(),
+        ),
+    };
+    plan.push_str(&format!("  cipher: {}\n", cipher));
+    plan.push_str(&format!("  key derivation: {}\n", kdf));
+    
+    if !context.recipients.is_empty() {
+        plan.push_str(&format!("  recipients ({}):\n", context.recipients.len()));
+        for recipient in context.recipients {
+            plan.push_str(&format!("    {}\n", recipient));
+        }
+    }
+    
+    let policy = match (mode, context.policies) {
+        (ProcessMode::Decrypt, _) => "from the file header".to_string(),
+        (ProcessMode::Encrypt, None) => format!("{} next to each file, if any", keypolicy::POLICY_FILE),
+        (ProcessMode::Encrypt, Some([])) => format!("none (no {}, every value is encrypted)", keypolicy::POLICY_FILE),
+        (ProcessMode::Encrypt, Some(policies)) => {
+            let listed: Vec<String> =
+                policies.iter().map(|(input, policy)| format!("{}: {}", input.display(), policy)).collect();
+            format!("{} ({})", keypolicy::POLICY_FILE, listed.join("; "))
+        }
+    };
+    plan.push_str(&format!("  key policy: {}\n", policy));
+    
+    let password = match (&opts.keychain, &opts.password_command, process.shares) {
+        _ if mode == ProcessMode::Encrypt && !context.recipients.is_empty() => {
+            "random file key, no password needed".to_string()
+        }
+        (_, _, Some((threshold, count))) if mode == ProcessMode::Encrypt => {
+            format!("random, split into {} shares ({} needed)", count, threshold)
+        }
+        (Some(project), _, _) => format!("OS keychain ({})", project),
+        (None, Some(command), _) => format!("command `{}`", command),
+        _ if opts.password_from_arg => "-p/--password".to_string(),
+        _ if context.env_password => format!("environment variable {}", interactive::password_env_var()),
+        _ => "prompt".to_string(),
+    };
+    plan.push_str(&format!("  password: {}\n", password));
+    
+    plan.push_str(&format!(
+        "  overwrite: {}\n",
+        match opts.overwrite {
+            OverwritePolicy::Prompt => "prompt",
+            OverwritePolicy::Always => "always",
+            OverwritePolicy::Never => "never (skip existing outputs)",
+        }
+    ));
+    
+    let extra_prefixes: Vec<&str> = process.comment_prefixes.iter().skip(1).map(String::as_str).collect();
+    let mut options = Vec::new();
+    if !extra_prefixes.is_empty() {
+        options.push(format!("comment prefixes {}", extra_prefixes.join(" ")));
+    }
+    let flags = [
+        (process.strict, "strict"),
+        (process.dedupe, "dedupe"),
+        (process.normalize, "normalize"),
+        (process.follow_includes, "follow-includes"),
+        (opts.interpolate.is_some(), "interpolate"),
+        (opts.interpolate.is_some_and(|settings| settings.env), "interpolate-env"),
+        (opts.interpolate.is_some_and(|settings| settings.undefined == Undefined::Error), "undefined-refs=error"),
+        (mode == ProcessMode::Encrypt && !process.trim, "no-trim"),
+        (process.compress, "compress"),
+        (process.hide_keys, "hide-keys"),
+        (process.inline_files.is_some(), "inline-files"),
+        (process.fail_on_plaintext, "fail-on-plaintext"),
+        (process.allow_plaintext, "allow-plaintext-passthrough"),
+        (!process.fail_fast, "fail-fast=false"),
+        (!process.trim_keys, "trim-keys=false"),
+        (opts.store_name, "store-name"),
+        (opts.armor, "armor"),
+        (opts.checksum, "checksum"),
+        (opts.verify, "verify"),
+        (opts.overwrite_per_file, "interactive-overwrite-per-file"),
+        (opts.diff, "diff"),
+        (opts.keep_going, "keep-going"),
+        (opts.remove_source, "remove-source"),
+        (opts.preserve_mtime, "preserve-mtime"),
+        (opts.docker_env_file, "env-file-stdout"),
+    ];
+    options.extend(flags.iter().filter(|(on, _)| *on).map(|(_, name)| name.to_string()));
+    if process.final_newline != FinalNewline::Preserve {
+        let policy = process.final_newline.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+        options.push(format!("final-newline {}", policy));
+    }
+    if process.min_encrypted > 0.0 {
+        options.push(format!("min-encrypted {}", process.min_encrypted));
+    }
+    if let Some(report) = &opts.report {
+        options.push(format!("report {}", report.display()));
+    }
+    
+    if !options.is_empty() {
+        plan.push_str(&format!("  options: {}\n", options.join(", ")));
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ProcessOptions;
+    
+    #[test]
+    fn test_plan_reflects_flags() {
+        let opts = RunOptions {
+            keychain: Some("my-project".to_string()),
+            overwrite: OverwritePolicy::Never,
+            checksum: true,
+            process: ProcessOptions {
+                cipher: Cipher::Chacha20Poly1305,
+                dedupe: true,
+                ..ProcessOptions::with_comment_prefixes(&[";".to_string()])
+            },
+            ..Default::default()
+        };
+        let files = [(PathBuf::from(".env"), PathBuf::from(".env.enc"))];
+        let context = PlanContext { files: Some(&files), recipients: &[], policies: Some(&[]), env_password: false };
+        
+        let plan = render_plan(ProcessMode::Encrypt, &context, &opts);
+        assert!(plan.contains("Plan: encrypt"));
+        assert!(plan.contains(".env -> .env.enc"));
+        assert!(plan.contains("cipher: chacha20-poly1305"));
+        assert!(plan.contains("key derivation: Argon2id with a per-file salt"));
+        assert!(plan.contains("key policy: none"));
+        assert!(plan.contains("password: OS keychain (my-project)"));
+        assert!(plan.contains("overwrite: never"));
+        assert!(plan.contains("options: comment prefixes ;, dedupe, checksum\n"));
+        
+        let interactive = PlanContext { files: None, recipients: &[], policies: None, env_password: true };
+        let plan = render_plan(ProcessMode::Decrypt, &interactive, &RunOptions::default());
+        assert!(plan.contains("files: chosen interactively"));
+        assert!(plan.contains("cipher: from the file header"));
+        assert!(plan.contains("password: environment variable"));
+        assert!(!plan.contains("options:"));
+    }
+    
+    #[test]
+    fn test_plan_lists_recipients_and_key_policy() {
+        let policy = KeyPolicy::parse("*_SECRET\nDB_PASSWORD\n").unwrap();
+        let policies = [(PathBuf::from("app/.env"), policy)];
+        let recipients = ["age1qyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqs3290gq".to_string()];
+        let context = PlanContext { files: None, recipients: &recipients, policies: Some(&policies), env_password: true };
+        
+        let plan = render_plan(ProcessMode::Encrypt, &context, &RunOptions::default());
+        assert!(plan.contains("key derivation: random file key wrapped to age X25519 recipients\n"));
+        assert!(plan.contains(&format!("  recipients (1):\n    {}\n", recipients[0])));
+        assert!(plan.contains("key policy: .wc-envc-keys (app/.env: *_SECRET,DB_PASSWORD)\n"));
+        assert!(plan.contains("password: random file key"));
+        
+        let plain = PlanContext { recipients: &[], ..context };
+        assert!(render_plan(ProcessMode::Encrypt, &plain, &RunOptions::default()).contains("key derivation: SHA-256 of the password"));
+    }
+}
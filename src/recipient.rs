@@ -0,0 +1,257 @@
+//! Public-key mode: encrypt under a random file key wrapped to one or more age X25519 recipients,
+//! so only the holders of the matching identities can decrypt (`age` feature)
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+#[cfg(feature = "age")]
+use base64::Engine;
+use console::style;
+#[cfg(feature = "age")]
+use secrecy::ExposeSecret;
+use secrecy::SecretString;
+
+use crate::{engine, scanner};
+
+/// Random bytes in a generated file key (encoded as Base64)
+#[cfg(feature = "age")]
+const FILE_KEY_LEN: usize = 32;
+
+/// Random file key to encrypt the values under, with one `<public key> <wrapped key>` header entry per recipient
+#[cfg(feature = "age")]
+pub fn wrap_new_key(recipients: &[String]) -> Result<(SecretString, Vec<String>)> {
+    let bytes: [u8; FILE_KEY_LEN] = rand::random();
+    let key = SecretString::new(base64::engine::general_purpose::STANDARD.encode(bytes));
+    let wrapped_keys = recipients
+        .iter()
+        .map(|recipient| wrap_key(&key, recipient))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((key, wrapped_keys))
+}
+
+/// Header entry for `key` wrapped to `recipient`, an age public key (`age1...`)
+#[cfg(feature = "age")]
+fn wrap_key(key: &SecretString, recipient: &str) -> Result<String> {
+    let recipient = recipient.trim();
+    let parsed: age::x25519::Recipient = recipient
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid recipient '{}': {}", recipient, e))?;
+    
+    let wrapped = age::encrypt(&parsed, key.expose_secret().as_bytes())
+        .map_err(|e| anyhow::anyhow!("Cannot wrap the file key: {}", e))?;
+    Ok(format!("{} {}", recipient, base64::engine::general_purpose::STANDARD.encode(wrapped)))
+}
+
+/// Unwrap the file key with the first X25519 identity (`AGE-SECRET-KEY-1...`) in `identity_file`,
+/// trying it against each recipient's wrapped key
+#[cfg(feature = "age")]
+pub fn unwrap_key(wrapped_keys: &[&str], identity_file: &Path) -> Result<SecretString> {
+    let contents = fs::read_to_string(identity_file)
+        .map_err(|e| anyhow::anyhow!("{}: {}", identity_file.display(), e))?;
+    let identity: age::x25519::Identity = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| anyhow::anyhow!("{}: no identity found", identity_file.display()))?
+        .parse()
+        .map_err(|e| anyhow::anyhow!("{}: {}", identity_file.display(), e))?;
+    
+    for entry in wrapped_keys {
+        let wrapped = base64::engine::general_purpose::STANDARD
+            .decode(split_entry(entry).1)
+            .map_err(|_| anyhow::anyhow!("Malformed recipient header"))?;
+        if let Ok(key) = age::decrypt(&identity, &wrapped) {
+            let key = String::from_utf8(key).map_err(|_| anyhow::anyhow!("Malformed recipient header"))?;
+            return Ok(SecretString::new(key));
+        }
+    }
+    
+    anyhow::bail!(
+        "{} does not match any of the file's {} recipient(s)",
+        identity_file.display(),
+        wrapped_keys.len()
+    )
+}
+
+/// Public-key mode needs the optional `age` feature
+#[cfg(not(feature = "age"))]
+pub fn wrap_new_key(_recipients: &[String]) -> Result<(SecretString, Vec<String>)> {
+    anyhow::bail!("--recipient requires building with `--features age`")
+}
+
+/// Public-key mode needs the optional `age` feature
+#[cfg(not(feature = "age"))]
+fn wrap_key(_key: &SecretString, _recipient: &str) -> Result<String> {
+    anyhow::bail!("--recipient requires building with `--features age`")
+}
+
+/// Public-key mode needs the optional `age` feature
+#[cfg(not(feature = "age"))]
+pub fn unwrap_key(_wrapped_keys: &[&str], _identity_file: &Path) -> Result<SecretString> {
+    anyhow::bail!("This file was encrypted with --recipient; rebuild with `--features age` to decrypt it")
+}
+
+/// Split a header entry into (public key, wrapped key); the public key may be missing
+fn split_entry(entry: &str) -> (Option<&str>, &str) {
+    match entry.split_once(' ') {
+        Some((recipient, wrapped)) => (Some(recipient), wrapped),
+        None => (None, entry),
+    }
+}
+
+/// File key of an `encrypt --recipient` file, unwrapped with `identity`
+/// None when the file has no recipient header and no identity was given
+/// Only the header is read, after the `max_size` (`--max-file-size`) check
+pub fn password_from_identity(path: &Path, identity: Option<&Path>, max_size: u64) -> Result<Option<SecretString>> {
+    let local = !scanner::is_stdio(path) && !scanner::is_url(path);
+    if identity.is_some() && !local {
+        anyhow::bail!("--identity needs a local input file");
+    }
+    
+    let header = if local { scanner::read_header(path, max_size)? } else { String::new() };
+    let wrapped_keys = engine::recipient_keys(&header);
+    match (wrapped_keys.is_empty(), identity) {
+        (false, Some(identity)) => unwrap_key(&wrapped_keys, identity).map(Some),
+        (false, None) => {
+            anyhow::bail!("{} was encrypted to a public key; decrypt it with --identity KEYFILE", path.display())
+        }
+        (true, Some(_)) => {
+            anyhow::bail!("{} was not encrypted with --recipient; --identity does not apply", path.display())
+        }
+        (true, None) => Ok(None),
+    }
+}
+
+/// New header entries after adding and removing recipients; the file key itself stays the same
+fn rekey_entries(entries: &[&str], key: &SecretString, add: &[String], remove: &[String]) -> Result<Vec<String>> {
+    for recipient in remove {
+        if !entries.iter().any(|entry| split_entry(entry).0 == Some(recipient.trim())) {
+            anyhow::bail!("{} is not a recipient of this file", recipient.trim());
+        }
+    }
+    
+    let mut kept: Vec<String> = entries
+        .iter()
+        .filter(|entry| !remove.iter().any(|r| split_entry(entry).0 == Some(r.trim())))
+        .map(|entry| entry.to_string())
+        .collect();
+    for recipient in add {
+        if !kept.iter().any(|entry| split_entry(entry).0 == Some(recipient.trim())) {
+            kept.push(wrap_key(key, recipient)?);
+        }
+    }
+    
+    if kept.is_empty() {
+        anyhow::bail!("Removing every recipient would leave the file undecryptable");
+    }
+    Ok(kept)
+}
+
+/// Handle rekey command
+/// Adds and removes recipients by rewrapping the file key; values are not re-encrypted
+pub fn handle_rekey(file: &Path, identity: &Path, add: &[String], remove: &[String]) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("File not found: {}", file.display());
+    }
+    
+    let content = fs::read_to_string(file)?;
+    let armored = engine::is_armored(&content);
+    let content = if armored { engine::dearmor(&content)? } else { content };
+    
+    let (name, rest) = engine::split_name_header(&content);
+    let (entries, body) = engine::split_recipient_header(rest);
+    if entries.is_empty() {
+        anyhow::bail!("{} was not encrypted with --recipient", file.display());
+    }
+    
+    let key = unwrap_key(&entries, identity)?;
+    let kept = rekey_entries(&entries, &key, add, remove)?;
+    
+    let rekeyed = engine::add_recipient_header(body, &kept);
+    let rekeyed = match name {
+        Some(name) => engine::add_name_header(&rekeyed, name),
+        None => rekeyed,
+    };
+    fs::write(file, if armored { engine::armor(&rekeyed) } else { rekeyed })?;
+    
+    println!(
+        "{} {} now has {} recipient(s)",
+        style("🔑").cyan(),
+        style(file.display()).cyan(),
+        kept.len()
+    );
+    if !remove.is_empty() {
+        println!("   Removed recipients may still hold the old file key; re-encrypt to rotate it");
+    }
+    
+    Ok(())
+}
+
+#[cfg(all(test, feature = "age"))]
+mod tests {
+    use age::secrecy::ExposeSecret as _;
+    
+    use super::*;
+    use crate::engine::{ProcessMode, ProcessOptions};
+    
+    /// Write a new identity to `dir/name`, returning its path and public key
+    fn keypair(dir: &Path, name: &str) -> (std::path::PathBuf, String) {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let path = dir.join(name);
+        fs::write(&path, format!("# public key: {}\n{}\n", recipient, identity.to_string().expose_secret())).unwrap();
+        (path, recipient)
+    }
+    
+    /// Decrypt `file` with the identity at `identity`
+    fn decrypt(file: &Path, identity: &Path) -> Result<String> {
+        let password = password_from_identity(file, Some(identity), 0)?.unwrap();
+        let content = fs::read_to_string(file)?;
+        Ok(engine::process_file(&content, &password, ProcessMode::Decrypt, &ProcessOptions::default())?.0)
+    }
+    
+    #[test]
+    fn test_two_recipients_each_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let (alice, alice_pub) = keypair(dir.path(), "alice.txt");
+        let (bob, bob_pub) = keypair(dir.path(), "bob.txt");
+        let (carol, _) = keypair(dir.path(), "carol.txt");
+        
+        let (key, wrapped_keys) = wrap_new_key(&[alice_pub, bob_pub]).unwrap();
+        let opts = ProcessOptions { recipient_keys: wrapped_keys, ..Default::default() };
+        let (encrypted, _) = engine::process_file("DB_PASS=secret\n", &key, ProcessMode::Encrypt, &opts).unwrap();
+        assert_eq!(engine::recipient_keys(&encrypted).len(), 2);
+        let file = dir.path().join(".env.enc");
+        fs::write(&file, &encrypted).unwrap();
+        
+        assert_eq!(decrypt(&file, &alice).unwrap(), "DB_PASS=secret\n");
+        assert_eq!(decrypt(&file, &bob).unwrap(), "DB_PASS=secret\n");
+        assert!(decrypt(&file, &carol).unwrap_err().to_string().contains("any of the file's 2 recipient(s)"));
+        assert!(password_from_identity(&file, None, 0).unwrap_err().to_string().contains("--identity"));
+    }
+    
+    #[test]
+    fn test_rekey_adds_and_removes_recipients() {
+        let dir = tempfile::tempdir().unwrap();
+        let (alice, alice_pub) = keypair(dir.path(), "alice.txt");
+        let (bob, bob_pub) = keypair(dir.path(), "bob.txt");
+        
+        let (key, wrapped_keys) = wrap_new_key(std::slice::from_ref(&alice_pub)).unwrap();
+        let opts = ProcessOptions { recipient_keys: wrapped_keys, ..Default::default() };
+        let (encrypted, _) = engine::process_file("A=1\n", &key, ProcessMode::Encrypt, &opts).unwrap();
+        let file = dir.path().join(".env.enc");
+        fs::write(&file, &encrypted).unwrap();
+        let body = engine::split_recipient_header(&encrypted).1.to_string();
+        
+        handle_rekey(&file, &alice, std::slice::from_ref(&bob_pub), &[]).unwrap();
+        assert_eq!(decrypt(&file, &bob).unwrap(), "A=1\n");
+        
+        handle_rekey(&file, &bob, &[], std::slice::from_ref(&alice_pub)).unwrap();
+        assert!(decrypt(&file, &alice).is_err());
+        assert_eq!(decrypt(&file, &bob).unwrap(), "A=1\n");
+        assert_eq!(engine::split_recipient_header(&fs::read_to_string(&file).unwrap()).1, body);
+        
+        assert!(handle_rekey(&file, &bob, &[], &[bob_pub]).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! `--report` audit summary of a batch run: files, variable counts, statuses and timings
+//! Never contains values, only file paths and counts
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use serde_json::json;
+
+use crate::clock::{TimeZone, Timestamp};
+use crate::engine::ProcessMode;
+
+/// Report file written when `--report` is given without a path
+pub const DEFAULT_REPORT_FILE: &str = "wc-envc-report.json";
+
+/// Outcome of one file in a batch
+#[derive(Debug, PartialEq)]
+pub enum FileStatus {
+    /// Processed and written
+    Ok,
+    /// Unchanged since the last encrypt, not rewritten
+    Skipped,
+    /// Failed with this error message
+    Failed(String),
+}
+
+/// One report entry per batch input
+pub struct FileReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub vars: usize,
+    pub status: FileStatus,
+    pub elapsed: Duration,
+}
+
+/// Render the report as pretty-printed JSON
+fn render(mode: ProcessMode, files: &[FileReport], now: &Timestamp) -> String {
+    let entries: Vec<_> = files
+        .iter()
+        .map(|file| {
+            let (status, error) = match &file.status {
+                FileStatus::Ok => ("ok", None),
+                FileStatus::Skipped => ("skipped", None),
+                FileStatus::Failed(e) => ("failed", Some(e.as_str())),
+            };
+            json!({
+                "input": file.input.display().to_string(),
+                "output": file.output.display().to_string(),
+                "vars": file.vars,
+                "status": status,
+                "error": error,
+                "elapsed_ms": file.elapsed.as_millis() as u64,
+            })
+        })
+        .collect();
+    
+    let failed = files.iter().filter(|f| matches!(f.status, FileStatus::Failed(_))).count();
+    let report = json!({
+        "mode": match mode {
+            ProcessMode::Encrypt => "encrypt",
+            ProcessMode::Decrypt => "decrypt",
+        },
+        "generated_at": now.rfc3339(),
+        "total": files.len(),
+        "failed": failed,
+        "files": entries,
+    });
+    
+    format!("{:#}\n", report)
+}
+
+/// Write the batch report to `path`
+pub fn write_report(path: &Path, mode: ProcessMode, files: &[FileReport], zone: TimeZone) -> Result<()> {
+    fs::write(path, render(mode, files, &Timestamp::new(SystemTime::now(), zone)))
+        .map_err(|e| anyhow::anyhow!("Cannot write report {}: {}", path.display(), e))
+}
@@ -1,50 +1,518 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::SystemTime;
 
-use crate::engine::ProcessMode;
+use anyhow::Result;
+use serde_json::json;
+
+use crate::clock::{TimeZone, Timestamp};
+use crate::engine::{self, ProcessMode, ProcessOptions};
 
 /// Patterns to match for decryption (encrypted files)
 const DECRYPT_EXTENSIONS: &[&str] = &[".enc", ".encrypted"];
 
-/// Find .env files in directory based on mode
-pub fn find_env_files(dir: &Path, mode: ProcessMode) -> Vec<PathBuf> {
-    let mut files = Vec::new();
+/// Suffix of key-only template files written by `example`; never encrypted
+const EXAMPLE_SUFFIX: &str = ".example";
+
+/// Directories never entered by a recursive scan
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules", "target"];
+
+/// Default input size limit (5 MB); env files are tiny
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Exit code when no input files match
+pub const NO_FILES_EXIT_CODE: i32 = 3;
+
+/// Error returned when a scan matches no input files
+#[derive(Debug)]
+pub struct NoFilesFound {
+    /// What was searched for (e.g. ".env.enc files")
+    pub kind: String,
+}
+
+impl fmt::Display for NoFilesFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No matching files: no {} found in current directory", self.kind)
+    }
+}
+
+impl std::error::Error for NoFilesFound {}
+
+/// Fail with `NoFilesFound` if a scan came back empty
+pub fn require_files(files: Vec<PathBuf>, kind: &str) -> Result<Vec<PathBuf>> {
+    if files.is_empty() {
+        return Err(NoFilesFound { kind: kind.to_string() }.into());
+    }
+    Ok(files)
+}
+
+/// Finds the .env files a command works on
+/// Symlinked files are included; symlinked directories and dangling links are skipped
+#[derive(Clone)]
+pub struct Scanner {
+    dir: PathBuf,
+    mode: ProcessMode,
+    recursive: bool,
+    exclude: Vec<glob::Pattern>,
+    respect_gitignore: bool,
+}
+
+impl Scanner {
+    /// Scan `dir` (not its subdirectories) for the input files of `mode`
+    pub fn new(dir: impl Into<PathBuf>, mode: ProcessMode) -> Self {
+        Self {
+            dir: dir.into(),
+            mode,
+            recursive: false,
+            exclude: Vec::new(),
+            respect_gitignore: false,
+        }
+    }
     
-    let entries = match fs::read_dir(dir) {
-        Ok(entries) => entries,
-        Err(_) => return files,
-    };
+    /// Also scan subdirectories; symlinked directories and `SKIPPED_DIRS` are not entered
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
     
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if !path.is_file() {
-            continue;
+    /// Skip files and directories whose path relative to the scanned directory, or whose name, matches
+    pub fn exclude(mut self, pattern: glob::Pattern) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+    
+    /// Skip files git ignores (no effect outside a git work tree)
+    pub fn respect_gitignore(mut self, respect: bool) -> Self {
+        self.respect_gitignore = respect;
+        self
+    }
+    
+    /// Matching files, sorted, as paths under the scanned directory
+    pub fn scan(&self) -> Vec<PathBuf> {
+        let files = self.scan_dir(&self.dir);
+        if !self.respect_gitignore {
+            return files;
         }
         
-        let filename = match path.file_name().and_then(|n| n.to_str()) {
-            Some(name) => name,
-            None => continue,
+        let ignored = gitignored(&self.dir, &files);
+        files.into_iter().filter(|path| !ignored.contains(path)).collect()
+    }
+    
+    /// Files in `dir`, then (when recursive) in its subdirectories
+    fn scan_dir(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+        
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return files,
         };
         
-        match mode {
-            ProcessMode::Encrypt => {
-                // Find plain .env files (not already encrypted)
-                if is_plain_env_file(filename) {
-                    files.push(path);
-                }
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if self.is_excluded(&path) {
+                continue;
             }
-            ProcessMode::Decrypt => {
-                // Find encrypted .env files
-                if is_encrypted_env_file(filename) {
-                    files.push(path);
+            
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                if !SKIPPED_DIRS.iter().any(|skip| entry.file_name() == *skip) {
+                    subdirs.push(path);
                 }
+                continue;
+            }
+            
+            if !is_regular_or_linked_file(&entry) {
+                continue;
+            }
+            
+            let filename = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            
+            if is_env_file_for(filename, self.mode) {
+                files.push(path);
+            }
+        }
+        
+        // Sort for consistent ordering
+        files.sort();
+        if self.recursive {
+            subdirs.sort();
+            for subdir in subdirs {
+                files.extend(self.scan_dir(&subdir));
+            }
+        }
+        files
+    }
+    
+    /// Whether an `exclude` pattern matches `path`
+    fn is_excluded(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        let name = Path::new(path.file_name().unwrap_or_default());
+        self.exclude
+            .iter()
+            .any(|pattern| pattern.matches_path(relative) || pattern.matches_path(name))
+    }
+}
+
+/// The subset of `files` (under `dir`) that git ignores; empty outside a work tree or without git
+fn gitignored(dir: &Path, files: &[PathBuf]) -> HashSet<PathBuf> {
+    let child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["check-ignore", "-z", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return HashSet::new();
+    };
+    
+    // Write from a thread so a full stdout pipe can't block git while we are still writing
+    let mut input = Vec::new();
+    for path in files {
+        input.extend_from_slice(path.strip_prefix(dir).unwrap_or(path).as_os_str().as_encoded_bytes());
+        input.push(0);
+    }
+    if let Some(mut stdin) = child.stdin.take() {
+        thread::spawn(move || stdin.write_all(&input));
+    }
+    
+    let Ok(output) = child.wait_with_output() else {
+        return HashSet::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(|path| dir.join(path))
+        .collect()
+}
+
+/// Parse a `--exclude` glob
+pub fn parse_pattern(pattern: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(pattern).map_err(|e| format!("invalid glob: {}", e))
+}
+
+/// Handle scan command: print matching paths relative to the scanned directory, one per line
+/// With `porcelain`, each line is `status<TAB>path<TAB>vars` (see `porcelain_line`); `json`
+/// prints the same per-file details (or the `count_only` totals) as one JSON document.
+/// `zero` ends each entry with NUL instead of a newline and leaves paths unquoted
+pub fn handle_scan(scanner: &Scanner, porcelain: bool, count_only: bool, json: bool, zero: bool) -> Result<()> {
+    if count_only {
+        let totals = scan_totals(scanner);
+        if json {
+            print!("{}", totals_json(&totals));
+            return Ok(());
+        }
+        println!("files\t{}", totals.plain + totals.encrypted);
+        println!("encrypted\t{}", totals.encrypted);
+        println!("plain\t{}", totals.plain);
+        println!("variables\t{}", totals.variables);
+        return Ok(());
+    }
+    
+    let kind = match scanner.mode {
+        ProcessMode::Encrypt => ".env files",
+        ProcessMode::Decrypt => ".env.enc files",
+    };
+    
+    let paths = require_files(scan_paths(scanner), kind)?;
+    if json {
+        print!("{}", files_json(&scanner.dir, &paths));
+        return Ok(());
+    }
+    
+    let mut stdout = std::io::stdout().lock();
+    for path in paths {
+        let entry = match (porcelain, zero) {
+            (true, _) => porcelain_line(&scanner.dir, &path, zero),
+            (false, true) => path.display().to_string(),
+            (false, false) => quote_path(&path),
+        };
+        write!(stdout, "{}{}", entry, if zero { '\0' } else { '\n' })?;
+    }
+    
+    Ok(())
+}
+
+/// What a scan reports about one file
+#[derive(Debug, PartialEq)]
+enum FileStatus {
+    Plain(usize),
+    Encrypted(usize),
+    Unreadable,
+}
+
+impl FileStatus {
+    /// Status of `path` (relative to `dir`) from its own content, whatever its name says:
+    /// encrypted when it passes the check `decrypt` runs before decrypting
+    fn of(dir: &Path, path: &Path) -> Self {
+        let opts = ProcessOptions::default();
+        let Ok(content) = read_env_content(&dir.join(path)) else {
+            return FileStatus::Unreadable;
+        };
+        let count = variable_count(&content, &opts);
+        match engine::validate_encrypted_file(&content, &opts) {
+            Ok(()) => FileStatus::Encrypted(count),
+            Err(_) => FileStatus::Plain(count),
+        }
+    }
+    
+    /// Name used in `--json` output
+    fn name(&self) -> &'static str {
+        match self {
+            FileStatus::Plain(_) => "plain",
+            FileStatus::Encrypted(_) => "encrypted",
+            FileStatus::Unreadable => "unreadable",
+        }
+    }
+    
+    /// Variable count, None if the file can't be read
+    fn variables(&self) -> Option<usize> {
+        match self {
+            FileStatus::Plain(count) | FileStatus::Encrypted(count) => Some(*count),
+            FileStatus::Unreadable => None,
+        }
+    }
+}
+
+/// Stable script-parseable scan line: `status<TAB>path<TAB>vars`
+/// Status is `P` (plain), `E` (encrypted) or `!` (unreadable, vars is `-`). The path is quoted
+/// like `git status --porcelain` does, unless `zero` (NUL-terminated entries) makes that unneeded
+fn porcelain_line(dir: &Path, path: &Path, zero: bool) -> String {
+    let (status, vars) = match FileStatus::of(dir, path) {
+        FileStatus::Plain(count) => ("P", count.to_string()),
+        FileStatus::Encrypted(count) => ("E", count.to_string()),
+        FileStatus::Unreadable => ("!", "-".to_string()),
+    };
+    let path = if zero { path.display().to_string() } else { quote_path(path) };
+    format!("{}\t{}\t{}", status, path, vars)
+}
+
+/// `path` as git prints it: as is, or in double quotes with C escapes (octal for other control
+/// and non-ASCII bytes) when it holds anything that could break a line-based parser
+fn quote_path(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    if !bytes.iter().any(|&b| b.is_ascii_control() || !b.is_ascii() || b == b'"' || b == b'\\') {
+        return path.display().to_string();
+    }
+    
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            byte if byte.is_ascii_control() || !byte.is_ascii() => quoted.push_str(&format!("\\{:03o}", byte)),
+            byte => quoted.push(byte as char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// `scan --json`: an array of `{path, status, variables}` objects (variables is null when unreadable)
+fn files_json(dir: &Path, paths: &[PathBuf]) -> String {
+    let files: Vec<serde_json::Value> = paths
+        .iter()
+        .map(|path| {
+            let status = FileStatus::of(dir, path);
+            json!({ "path": path.display().to_string(), "status": status.name(), "variables": status.variables() })
+        })
+        .collect();
+    format!("{:#}\n", serde_json::Value::Array(files))
+}
+
+/// `scan --count-only --json`: the totals as one object
+fn totals_json(totals: &ScanTotals) -> String {
+    let summary = json!({
+        "files": totals.plain + totals.encrypted,
+        "encrypted": totals.encrypted,
+        "plain": totals.plain,
+        "variables": totals.variables,
+    });
+    format!("{:#}\n", summary)
+}
+
+/// Aggregate numbers for `scan --count-only`, over plain and encrypted files alike
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanTotals {
+    pub plain: usize,
+    pub encrypted: usize,
+    /// Variables across all readable files
+    pub variables: usize,
+}
+
+/// Count the plain and encrypted .env files `scanner` finds and their variables
+fn scan_totals(scanner: &Scanner) -> ScanTotals {
+    let mut totals = ScanTotals::default();
+    for mode in [ProcessMode::Encrypt, ProcessMode::Decrypt] {
+        for path in scan_paths(&Scanner { mode, ..scanner.clone() }) {
+            match mode {
+                ProcessMode::Encrypt => totals.plain += 1,
+                ProcessMode::Decrypt => totals.encrypted += 1,
             }
+            totals.variables += count_variables(&scanner.dir.join(path), &ProcessOptions::default()).unwrap_or(0);
         }
     }
+    totals
+}
+
+/// Paths a scan selects, relative to the scanned directory
+fn scan_paths(scanner: &Scanner) -> Vec<PathBuf> {
+    scanner
+        .scan()
+        .into_iter()
+        .map(|path| path.strip_prefix(&scanner.dir).map(Path::to_path_buf).unwrap_or(path))
+        .collect()
+}
+
+/// What a `--glob` pattern matched
+pub struct GlobMatches {
+    /// Regular files, sorted
+    pub files: Vec<PathBuf>,
+    /// Paths the walk could not read (e.g. a directory without permission), for the caller to report
+    pub errors: Vec<glob::GlobError>,
+}
+
+/// Regular files matching a `--glob` pattern (relative to the current directory)
+/// Like a recursive scan, `SKIPPED_DIRS` are left out unless the pattern names them literally
+pub fn glob_files(pattern: &str) -> Result<GlobMatches> {
+    let paths = glob::glob(pattern).map_err(|e| anyhow::anyhow!("Invalid --glob '{}': {}", pattern, e))?;
+    let named: Vec<&str> = SKIPPED_DIRS
+        .iter()
+        .copied()
+        .filter(|dir| Path::new(pattern).components().any(|part| part.as_os_str() == *dir))
+        .collect();
+    let skipped = |path: &Path| {
+        path.components()
+            .any(|part| SKIPPED_DIRS.iter().any(|dir| part.as_os_str() == *dir && !named.contains(dir)))
+    };
+    
+    let mut matches = GlobMatches { files: Vec::new(), errors: Vec::new() };
+    for path in paths {
+        match path {
+            Ok(path) if path.is_file() && !skipped(&path) => matches.files.push(path),
+            Ok(_) => {}
+            Err(e) => matches.errors.push(e),
+        }
+    }
+    matches.files.sort();
+    Ok(matches)
+}
+
+/// Read a newline-delimited file list from a file, or from stdin for `-`
+pub fn read_file_list(source: &Path) -> Result<Vec<PathBuf>> {
+    let content = if is_stdio(source) {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        fs::read_to_string(source)
+            .map_err(|e| anyhow::anyhow!("Cannot read file list {}: {}", source.display(), e))?
+    };
+    Ok(parse_file_list(&content))
+}
+
+/// Paths from a file list, one per line; blank lines are skipped
+fn parse_file_list(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Env files for `mode` under `dir` that git reports as staged, modified or untracked
+/// Git can't tell whether an ignored file changed, so a gitignored plain .env counts when its
+/// encrypted output is missing or older than it. Deleted files are left out; paths are joined onto `dir`
+pub fn changed_env_files(dir: &Path, mode: ProcessMode) -> Result<Vec<PathBuf>> {
+    let git = |args: &[&str]| -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run git: {}", e))?;
+        if !output.status.success() {
+            anyhow::bail!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
     
-    // Sort for consistent ordering
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim_end());
+    let status = git(&["status", "--porcelain=v1", "-z", "--untracked-files=all", "--ignored=matching", "--", "."])?;
+    let base = dir.canonicalize()?;
+    
+    let mut files: Vec<PathBuf> = changed_paths(&status)
+        .into_iter()
+        .filter(|(_, path)| Path::new(path).file_name().and_then(|n| n.to_str()).is_some_and(|n| is_env_file_for(n, mode)))
+        .filter_map(|(ignored, path)| {
+            let file = dir.join(root.join(path).strip_prefix(&base).ok()?);
+            (!ignored || (mode == ProcessMode::Encrypt && is_newer_than_output(&file))).then_some(file)
+        })
+        .collect();
     files.sort();
-    files
+    Ok(files)
+}
+
+/// Check if `source` was modified after its default encrypted output, or has none yet
+fn is_newer_than_output(source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(source), modified(&default_output_name(source, ProcessMode::Encrypt))) {
+        (Some(source), Some(output)) => source > output,
+        (_, output) => output.is_none(),
+    }
+}
+
+/// Repo-relative paths of changed entries in `git status --porcelain -z` output, skipping deletions
+/// Each path comes with whether git reported it as ignored (`!!`)
+fn changed_paths(status: &str) -> Vec<(bool, &str)> {
+    let mut paths = Vec::new();
+    let mut entries = status.split('\0');
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        
+        let (code, path) = entry.split_at(3);
+        // Renames and copies are followed by their original path
+        if code.starts_with(['R', 'C']) {
+            entries.next();
+        }
+        if !code.contains('D') {
+            paths.push((code == "!! ", path));
+        }
+    }
+    paths
+}
+
+/// Check a directory entry without following symlinks into directories
+fn is_regular_or_linked_file(entry: &fs::DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(file_type) if file_type.is_symlink() => {
+            // Only follow the link far enough to check its target is a file
+            fs::metadata(entry.path()).map(|m| m.is_file()).unwrap_or(false)
+        }
+        Ok(file_type) => file_type.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// Whether `filename` is an input for `mode`: plain .env files to encrypt, .env*.enc files to decrypt
+pub fn is_env_file_for(filename: &str, mode: ProcessMode) -> bool {
+    match mode {
+        ProcessMode::Encrypt => is_plain_env_file(filename),
+        ProcessMode::Decrypt => is_encrypted_env_file(filename),
+    }
 }
 
 /// Check if filename is a plain .env file (not encrypted)
@@ -54,8 +522,8 @@ fn is_plain_env_file(filename: &str) -> bool {
         return false;
     }
     
-    // Must not end with encrypted extension
-    for ext in DECRYPT_EXTENSIONS {
+    // Must not end with encrypted extension, and key-only templates hold no secrets
+    for ext in DECRYPT_EXTENSIONS.iter().chain([&EXAMPLE_SUFFIX]) {
         if filename.ends_with(ext) {
             return false;
         }
@@ -81,38 +549,291 @@ fn is_encrypted_env_file(filename: &str) -> bool {
 }
 
 /// Count environment variables in a file
-pub fn count_variables(path: &Path) -> usize {
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(_) => return 0,
-    };
-    
-    content.lines()
+/// Errors if the file can't be read (permissions, not UTF-8 text)
+pub fn count_variables(path: &Path, opts: &ProcessOptions) -> Result<usize> {
+    Ok(variable_count(&read_env_content(path)?, opts))
+}
+
+/// A file's text, with armored files unwrapped
+fn read_env_content(path: &Path) -> Result<String> {
+    let content = fs::read_to_string(path)?;
+    if engine::is_armored(&content) {
+        return Ok(engine::dearmor(&content).unwrap_or_default());
+    }
+    Ok(content)
+}
+
+/// Variable lines in `content`
+fn variable_count(content: &str, opts: &ProcessOptions) -> usize {
+    engine::lines(content)
         .filter(|line| {
             let trimmed = line.trim();
-            !trimmed.is_empty() && !trimmed.starts_with('#') && trimmed.contains('=')
+            !opts.is_ignored(trimmed) && trimmed.contains('=')
         })
         .count()
 }
 
+/// Variable count label for file lists ("3 vars" or "unreadable")
+pub fn variables_label(path: &Path, opts: &ProcessOptions) -> String {
+    match count_variables(path, opts) {
+        Ok(count) => format!("{} vars", count),
+        Err(_) => "unreadable".to_string(),
+    }
+}
+
+/// Check if a path means stdin/stdout (`-`)
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Check if an output is a stream rather than a file to replace: stdout (`-`), a FIFO or a character device
+/// Streams are written straight through; there is nothing to overwrite or diff against.
+/// Sockets and block devices are not streams, so they still get the usual overwrite checks
+pub fn is_stream(path: &Path) -> bool {
+    is_stdio(path) || fs::metadata(path).is_ok_and(|m| is_stream_type(m.file_type()))
+}
+
+#[cfg(unix)]
+fn is_stream_type(file_type: fs::FileType) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file_type.is_fifo() || file_type.is_char_device()
+}
+
+/// Only stdout counts as a stream off unix
+#[cfg(not(unix))]
+fn is_stream_type(_file_type: fs::FileType) -> bool {
+    false
+}
+
+/// Check if writing to an output would replace an existing file
+pub fn output_exists(path: &Path) -> bool {
+    path.exists() && !is_stream(path)
+}
+
+/// Check if an input path is an http(s) URL
+pub fn is_url(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Last path segment of a URL, ignoring query string and fragment
+fn url_file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default()
+}
+
 /// Generate default output filename based on input and mode
+/// stdin maps to stdout, URLs map to their file name in the current directory
 pub fn default_output_name(input: &Path, mode: ProcessMode) -> PathBuf {
-    let input_str = input.to_string_lossy();
+    if is_stdio(input) {
+        return PathBuf::from("-");
+    }
+    
+    if is_url(input) {
+        let url = input.to_string_lossy();
+        let name = match url_file_name(&url) {
+            "" => ".env.enc",
+            name => name,
+        };
+        return default_output_name(Path::new(name), mode);
+    }
     
     match mode {
         ProcessMode::Encrypt => {
             // .env -> .env.enc
-            PathBuf::from(format!("{}.enc", input_str))
+            append_to_file_name(input, ".enc")
         }
         ProcessMode::Decrypt => {
             // .env.enc -> .env
             // .env.local.enc -> .env.local
-            let name = input_str.trim_end_matches(".enc").trim_end_matches(".encrypted");
-            PathBuf::from(name)
+            DECRYPT_EXTENSIONS
+                .iter()
+                .find_map(|ext| strip_file_name_suffix(input, ext))
+                .unwrap_or_else(|| input.to_path_buf())
         }
     }
 }
 
+/// Key-only template next to a plain file (`.env` -> `.env.example`)
+pub fn example_output_name(input: &Path) -> PathBuf {
+    append_to_file_name(input, EXAMPLE_SUFFIX)
+}
+
+/// Append `suffix` to the file name of `path` (`./config/.env` -> `./config/.env.enc`)
+/// Works on the raw OS string, so separators and non-UTF-8 names are kept intact
+fn append_to_file_name(path: &Path, suffix: &str) -> PathBuf {
+    let Some(name) = path.file_name() else {
+        let mut raw = path.as_os_str().to_os_string();
+        raw.push(suffix);
+        return PathBuf::from(raw);
+    };
+    
+    let mut name = name.to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Remove `suffix` from the file name of `path`; None if it doesn't end with it
+fn strip_file_name_suffix(path: &Path, suffix: &str) -> Option<PathBuf> {
+    let stripped = strip_os_suffix(path.file_name()?, suffix)?;
+    (!stripped.is_empty()).then(|| path.with_file_name(stripped))
+}
+
+/// Strip an ASCII suffix from an OS string without a lossy conversion
+#[cfg(unix)]
+fn strip_os_suffix(name: &OsStr, suffix: &str) -> Option<OsString> {
+    use std::os::unix::ffi::OsStrExt;
+    
+    let stripped = name.as_bytes().strip_suffix(suffix.as_bytes())?;
+    Some(OsStr::from_bytes(stripped).to_os_string())
+}
+
+/// Strip an ASCII suffix from an OS string (names that aren't valid Unicode are left alone)
+#[cfg(not(unix))]
+fn strip_os_suffix(name: &OsStr, suffix: &str) -> Option<OsString> {
+    name.to_str()?.strip_suffix(suffix).map(OsString::from)
+}
+
+/// Output path for an input: the rendered `template` if given, otherwise the default name
+pub fn output_name(
+    input: &Path,
+    mode: ProcessMode,
+    template: Option<&str>,
+    zone: TimeZone,
+) -> Result<PathBuf> {
+    match template {
+        Some(template) if !is_stdio(input) => {
+            render_output_name(template, input, mode, &Timestamp::new(SystemTime::now(), zone))
+        }
+        _ => Ok(default_output_name(input, mode)),
+    }
+}
+
+/// Environment-aware output name: `.env` -> `.env.<env>.enc` and `.env.<env>.enc` -> `.env`
+pub fn env_output_name(input: &Path, mode: ProcessMode, environment: &str) -> Result<PathBuf> {
+    validate_environment(environment)?;
+    
+    let default = default_output_name(input, mode);
+    if is_stdio(input) {
+        return Ok(default);
+    }
+    
+    match mode {
+        ProcessMode::Encrypt => Ok(append_to_file_name(input, &format!(".{}.enc", environment))),
+        ProcessMode::Decrypt => {
+            strip_file_name_suffix(&default, &format!(".{}", environment))
+                .ok_or_else(|| anyhow::anyhow!(
+                    "{} is not encrypted for environment '{}' (expected *.{}.enc)",
+                    input.display(), environment, environment
+                ))
+        }
+    }
+}
+
+/// Check an environment name is safe to put in a file name
+fn validate_environment(environment: &str) -> Result<()> {
+    let valid = !environment.is_empty()
+        && environment.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!("Invalid environment name '{}': use letters, digits, '-' and '_'", environment);
+    }
+    Ok(())
+}
+
+/// Render an output name template next to the default output
+/// Tokens: `{name}` plain file name, `{ext}` encrypted extension (`enc`),
+/// `{date}` date (YYYY-MM-DD), `{ts}` timestamp (YYYYMMDD-HHMMSS)
+fn render_output_name(
+    template: &str,
+    input: &Path,
+    mode: ProcessMode,
+    now: &Timestamp,
+) -> Result<PathBuf> {
+    let default = default_output_name(input, mode);
+    let plain = match mode {
+        ProcessMode::Encrypt => input,
+        ProcessMode::Decrypt => default.as_path(),
+    };
+    let name = plain.file_name().unwrap_or_default().to_string_lossy();
+    
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed '{{' in output template '{}'", template))?;
+        match &rest[start + 1..start + end] {
+            "name" => rendered.push_str(&name),
+            "ext" => rendered.push_str("enc"),
+            "date" => rendered.push_str(&now.date()),
+            "ts" => rendered.push_str(&now.compact()),
+            token => anyhow::bail!("Unknown output template token '{{{}}}'", token),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    
+    Ok(default.with_file_name(rendered))
+}
+
+/// Sidecar checksum path for a file (.env.enc -> .env.enc.sha256)
+pub fn checksum_path(path: &Path) -> PathBuf {
+    append_to_file_name(path, ".sha256")
+}
+
+/// Fail before reading if a file exceeds `max_size` bytes (0 disables the check)
+pub fn check_file_size(path: &Path, max_size: u64) -> Result<()> {
+    if max_size == 0 {
+        return Ok(());
+    }
+    
+    let size = fs::metadata(path)?.len();
+    if size > max_size {
+        anyhow::bail!(
+            "{}: file is {} bytes, larger than --max-file-size ({} bytes)",
+            path.display(),
+            size,
+            max_size
+        );
+    }
+    
+    Ok(())
+}
+
+/// Fail early for a missing input, or a directory where a file is expected
+pub fn check_input_file(input: &Path) -> Result<()> {
+    if input.is_dir() {
+        anyhow::bail!(
+            "{} is a directory, expected a file. To pick from the .env files in it, run wc-envc from \
+             inside that directory (or list them with `wc-envc scan --recursive`)",
+            input.display()
+        );
+    }
+    if !input.exists() {
+        anyhow::bail!("File not found: {}", input.display());
+    }
+    Ok(())
+}
+
+/// The leading `# wc-envc` header lines of a local file, without reading the rest of it
+/// The input and `--max-file-size` are checked first, like a full read would
+pub fn read_header(path: &Path, max_size: u64) -> Result<String> {
+    check_input_file(path)?;
+    check_file_size(path, max_size)?;
+    
+    let read_failed = |e: std::io::Error| anyhow::anyhow!("Cannot read {}: {}", path.display(), e);
+    let mut reader = BufReader::new(fs::File::open(path).map_err(read_failed)?);
+    let mut header = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(read_failed)? == 0 || !line.starts_with("# wc-envc") {
+            return Ok(header);
+        }
+        header.push_str(&line);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -124,6 +845,7 @@ mod tests {
         assert!(is_plain_env_file(".env.production"));
         assert!(!is_plain_env_file(".env.enc"));
         assert!(!is_plain_env_file(".env.local.enc"));
+        assert!(!is_plain_env_file(".env.example"));
         assert!(!is_plain_env_file("readme.md"));
     }
     
@@ -144,4 +866,409 @@ mod tests {
         let decrypt = default_output_name(Path::new(".env.enc"), ProcessMode::Decrypt);
         assert_eq!(decrypt, PathBuf::from(".env"));
     }
+    
+    #[test]
+    fn test_default_output_name_separators() {
+        let unix = default_output_name(Path::new("./config/.env"), ProcessMode::Encrypt);
+        assert_eq!(unix, PathBuf::from("./config/.env.enc"));
+        
+        let trailing = default_output_name(Path::new("config/.env/"), ProcessMode::Encrypt);
+        assert_eq!(trailing, PathBuf::from("config/.env.enc"));
+        
+        let windows = default_output_name(Path::new(r"C:\app\config\.env.enc"), ProcessMode::Decrypt);
+        assert_eq!(windows, PathBuf::from(r"C:\app\config\.env"));
+        
+        let checksum = checksum_path(Path::new(r".\config\.env.enc"));
+        assert_eq!(checksum, PathBuf::from(r".\config\.env.enc.sha256"));
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_default_output_name_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        
+        let input = Path::new(OsStr::from_bytes(b"conf\xff/.env.\xfe.enc"));
+        let output = default_output_name(input, ProcessMode::Decrypt);
+        assert_eq!(output.as_os_str().as_bytes(), b"conf\xff/.env.\xfe");
+        
+        let encrypted = default_output_name(&output, ProcessMode::Encrypt);
+        assert_eq!(encrypted.as_path(), input);
+    }
+    
+    #[test]
+    fn test_default_output_name_stdin_and_url() {
+        let stdin = default_output_name(Path::new("-"), ProcessMode::Decrypt);
+        assert_eq!(stdin, PathBuf::from("-"));
+        
+        let url = Path::new("https://artifacts.example.com/ci/.env.production.enc?token=abc");
+        assert_eq!(default_output_name(url, ProcessMode::Decrypt), PathBuf::from(".env.production"));
+    }
+    
+    #[test]
+    #[cfg(unix)]
+    fn test_is_stream_only_for_fifos_and_character_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".env");
+        fs::write(&file, "A=1\n").unwrap();
+        let socket = dir.path().join("agent.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        
+        assert!(is_stream(Path::new("-")));
+        assert!(is_stream(Path::new("/dev/null")));
+        assert!(!is_stream(&file));
+        assert!(!is_stream(dir.path()));
+        assert!(!is_stream(&socket));
+        assert!(output_exists(&socket));
+    }
+    
+    #[test]
+    fn test_require_files_empty() {
+        let err = require_files(Vec::new(), ".env.enc files").unwrap_err();
+        
+        assert!(err.downcast_ref::<NoFilesFound>().is_some());
+        assert_eq!(err.to_string(), "No matching files: no .env.enc files found in current directory");
+        assert_eq!(require_files(vec![PathBuf::from(".env")], ".env files").unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_count_variables_unreadable() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = ProcessOptions::default();
+        let empty = dir.path().join(".env");
+        let binary = dir.path().join(".env.bin");
+        fs::write(&empty, "").unwrap();
+        fs::write(&binary, [0xff, 0xfe, 0x00]).unwrap();
+        
+        assert_eq!(count_variables(&empty, &opts).unwrap(), 0);
+        assert_eq!(variables_label(&empty, &opts), "0 vars");
+        assert!(count_variables(&binary, &opts).is_err());
+        assert_eq!(variables_label(&binary, &opts), "unreadable");
+    }
+    
+    #[test]
+    fn test_render_output_name() {
+        // 2024-03-05 14:07:09 UTC
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_709_647_629);
+        let now = &Timestamp::new(time, TimeZone::Utc);
+        let input = Path::new("config/.env");
+        
+        let dated = render_output_name(".env.enc.{date}", input, ProcessMode::Encrypt, now).unwrap();
+        assert_eq!(dated, PathBuf::from("config/.env.enc.2024-03-05"));
+        
+        let stamped = render_output_name("{name}.{ts}.{ext}", input, ProcessMode::Encrypt, now).unwrap();
+        assert_eq!(stamped, PathBuf::from("config/.env.20240305-140709.enc"));
+        
+        let encrypted = Path::new(".env.enc");
+        let decrypted = render_output_name("{name}.{date}", encrypted, ProcessMode::Decrypt, now);
+        assert_eq!(decrypted.unwrap(), PathBuf::from(".env.2024-03-05"));
+        
+        assert!(render_output_name("{nope}", input, ProcessMode::Encrypt, now).is_err());
+        let default = output_name(input, ProcessMode::Encrypt, None, TimeZone::Utc).unwrap();
+        assert_eq!(default, PathBuf::from("config/.env.enc"));
+    }
+    
+    #[test]
+    fn test_check_file_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "DB_HOST=localhost\n").unwrap();
+        
+        assert!(check_file_size(&path, 1024).is_ok());
+        assert!(check_file_size(&path, 0).is_ok());
+        
+        let err = check_file_size(&path, 4).unwrap_err();
+        assert!(err.to_string().contains("--max-file-size"));
+    }
+    
+    #[test]
+    fn test_read_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env.enc");
+        let header = "# wc-envc: name=.env\n# wc-envc: recipient=abc\n";
+        fs::write(&path, format!("{}A=bG9jYWxob3N0\n# wc-envc: not a header\n", header)).unwrap();
+        
+        assert_eq!(read_header(&path, 0).unwrap(), header);
+        assert!(read_header(&path, 4).unwrap_err().to_string().contains("--max-file-size"));
+        assert!(read_header(dir.path(), 0).unwrap_err().to_string().contains("is a directory"));
+        assert!(read_header(&dir.path().join("missing.enc"), 0).unwrap_err().to_string().contains("File not found"));
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_find_env_files_symlinks() {
+        use std::os::unix::fs::symlink;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let shared = tempfile::tempdir().unwrap();
+        fs::write(shared.path().join("shared.env"), "DB_HOST=localhost\n").unwrap();
+        
+        symlink(shared.path().join("shared.env"), dir.path().join(".env")).unwrap();
+        symlink(shared.path(), dir.path().join(".env.d")).unwrap();
+        symlink(dir.path().join("missing"), dir.path().join(".env.broken")).unwrap();
+        
+        let files = Scanner::new(dir.path(), ProcessMode::Encrypt).scan();
+        assert_eq!(files, vec![dir.path().join(".env")]);
+    }
+    
+    #[test]
+    fn test_scan_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [".env", ".env.local", ".env.enc", "README.md", "app/.env.production", "app/.env.enc", ".git/.env"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "DB_HOST=localhost\n").unwrap();
+        }
+        
+        let plain = scan_paths(&Scanner::new(dir.path(), ProcessMode::Encrypt));
+        assert_eq!(plain, vec![PathBuf::from(".env"), PathBuf::from(".env.local")]);
+        
+        let recursive = scan_paths(&Scanner::new(dir.path(), ProcessMode::Encrypt).recursive(true));
+        assert_eq!(recursive, vec![
+            PathBuf::from(".env"),
+            PathBuf::from(".env.local"),
+            PathBuf::from("app/.env.production"),
+        ]);
+        
+        let encrypted = scan_paths(&Scanner::new(dir.path(), ProcessMode::Decrypt).recursive(true));
+        assert_eq!(encrypted, vec![PathBuf::from(".env.enc"), PathBuf::from("app/.env.enc")]);
+    }
+    
+    #[test]
+    fn test_scanner_builder_options() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [".env", ".env.local", "app/.env", "app/.env.test", "vendor/.env"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "A=1\n").unwrap();
+        }
+        let scan = |scanner: Scanner| -> Vec<String> {
+            scan_paths(&scanner).iter().map(|path| path.display().to_string()).collect()
+        };
+        let pattern = |glob: &str| parse_pattern(glob).unwrap();
+        let scanner = Scanner::new(dir.path(), ProcessMode::Encrypt);
+        
+        assert_eq!(scan(scanner.clone().exclude(pattern(".env.local"))), [".env"]);
+        assert_eq!(
+            scan(scanner.clone().recursive(true).exclude(pattern("vendor")).exclude(pattern("*.test"))),
+            [".env", ".env.local", "app/.env"]
+        );
+        assert_eq!(scan(scanner.clone().recursive(true).exclude(pattern("app/.env"))), [
+            ".env", ".env.local", "app/.env.test", "vendor/.env"
+        ]);
+        
+        // Outside a work tree nothing is ignored; inside, .gitignore applies
+        assert_eq!(scan(scanner.clone().respect_gitignore(true)), [".env", ".env.local"]);
+        let initialized = Command::new("git").arg("-C").arg(dir.path()).args(["init", "-q"]).status();
+        if initialized.is_ok_and(|status| status.success()) {
+            fs::write(dir.path().join(".gitignore"), ".env.local\nvendor/\n").unwrap();
+            assert_eq!(scan(scanner.recursive(true).respect_gitignore(true)), [".env", "app/.env", "app/.env.test"]);
+        }
+    }
+    
+    #[test]
+    fn test_quote_path_like_git() {
+        assert_eq!(quote_path(Path::new("app/.env")), "app/.env");
+        assert_eq!(quote_path(Path::new("a\tb/.env")), "\"a\\tb/.env\"");
+        assert_eq!(quote_path(Path::new(".env\nP\tfake")), "\".env\\nP\\tfake\"");
+        assert_eq!(quote_path(Path::new("say \"hi\"\\.env")), "\"say \\\"hi\\\"\\\\.env\"");
+        assert_eq!(quote_path(Path::new("café/.env")), "\"caf\\303\\251/.env\"");
+        
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".env\tx"), "A=1\n").unwrap();
+        assert_eq!(porcelain_line(dir.path(), Path::new(".env\tx"), false), "P\t\".env\\tx\"\t1");
+        assert_eq!(porcelain_line(dir.path(), Path::new(".env\tx"), true), "P\t.env\tx\t1");
+    }
+    
+    #[test]
+    fn test_scan_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            (".env", "A=1\nB=2\n"),
+            (".env.local", "# note\nC=3\n"),
+            (".env.enc", "A=x\n"),
+            ("app/.env", "D=4\n"),
+        ];
+        for (name, content) in files {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        
+        assert_eq!(scan_totals(&Scanner::new(dir.path(), ProcessMode::Encrypt)), ScanTotals { plain: 2, encrypted: 1, variables: 4 });
+        assert_eq!(scan_totals(&Scanner::new(dir.path(), ProcessMode::Encrypt).recursive(true)), ScanTotals { plain: 3, encrypted: 1, variables: 5 });
+        
+        let totals: serde_json::Value = serde_json::from_str(&totals_json(&ScanTotals { plain: 3, encrypted: 1, variables: 5 })).unwrap();
+        assert_eq!(totals, json!({ "files": 4, "encrypted": 1, "plain": 3, "variables": 5 }));
+    }
+    
+    #[test]
+    fn test_glob_files_nested() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["config/.env", "config/app/.env", "config/app/db/.env", "config/app/.env.enc", ".env"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "A=1\n").unwrap();
+        }
+        fs::create_dir_all(dir.path().join("config/empty/.env")).unwrap();
+        
+        let root = dir.path().display().to_string();
+        let files = glob_files(&format!("{}/config/**/.env", root)).unwrap().files;
+        let relative: Vec<_> = files.iter().map(|p| p.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+        assert_eq!(relative, vec![
+            PathBuf::from("config/.env"),
+            PathBuf::from("config/app/.env"),
+            PathBuf::from("config/app/db/.env"),
+        ]);
+        
+        assert!(glob_files(&format!("{}/missing/**/.env", root)).unwrap().files.is_empty());
+        assert!(glob_files("config/***/.env").is_err());
+    }
+    
+    #[test]
+    fn test_glob_files_skips_vendored_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["app/.env", "node_modules/pkg/.env", ".git/.env", "node_modules/.env.enc"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "A=1\n").unwrap();
+        }
+        
+        let root = dir.path().display().to_string();
+        let relative = |pattern: &str| -> Vec<PathBuf> {
+            glob_files(&format!("{}/{}", root, pattern))
+                .unwrap()
+                .files
+                .iter()
+                .map(|p| p.strip_prefix(dir.path()).unwrap().to_path_buf())
+                .collect()
+        };
+        assert_eq!(relative("**/.env"), vec![PathBuf::from("app/.env")]);
+        // Naming the directory in the pattern opts back in
+        assert_eq!(relative("node_modules/**/.env"), vec![PathBuf::from("node_modules/pkg/.env")]);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_glob_files_reports_unreadable_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir_all(locked.join("inner")).unwrap();
+        fs::write(dir.path().join(".env"), "A=1\n").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Root reads through any permission, so there is nothing to report there
+        let unreadable = fs::read_dir(&locked).is_err();
+        
+        let matches = glob_files(&format!("{}/**/.env", dir.path().display())).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(matches.files, vec![dir.path().join(".env")]);
+        if unreadable {
+            assert_eq!(matches.errors.len(), 1);
+            assert_eq!(matches.errors[0].path(), locked);
+        }
+    }
+    
+    #[test]
+    fn test_env_output_name() {
+        let encrypted = env_output_name(Path::new(".env"), ProcessMode::Encrypt, "staging").unwrap();
+        assert_eq!(encrypted, PathBuf::from(".env.staging.enc"));
+        
+        let nested = env_output_name(Path::new("config/.env"), ProcessMode::Encrypt, "prod").unwrap();
+        assert_eq!(nested, PathBuf::from("config/.env.prod.enc"));
+        
+        let decrypted = env_output_name(Path::new(".env.staging.enc"), ProcessMode::Decrypt, "staging").unwrap();
+        assert_eq!(decrypted, PathBuf::from(".env"));
+        
+        assert!(env_output_name(Path::new(".env.prod.enc"), ProcessMode::Decrypt, "staging").is_err());
+        assert!(env_output_name(Path::new(".env"), ProcessMode::Encrypt, "../prod").is_err());
+        assert!(env_output_name(Path::new(".env"), ProcessMode::Encrypt, "").is_err());
+    }
+    
+    #[test]
+    fn test_parse_file_list() {
+        let files = parse_file_list("./.env\r\n\nconfig/.env.local\n  \n");
+        assert_eq!(files, vec![PathBuf::from("./.env"), PathBuf::from("config/.env.local")]);
+    }
+    
+    #[test]
+    fn test_changed_paths() {
+        let status = "M  .env\0 D .env.old\0R  .env.new\0.env.renamed\0?? app/.env.local\0!! .env.dev\0";
+        assert_eq!(
+            changed_paths(status),
+            vec![(false, ".env"), (false, ".env.new"), (false, "app/.env.local"), (true, ".env.dev")]
+        );
+    }
+    
+    #[test]
+    fn test_changed_env_files_in_git_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        
+        git(&["init", "-q"]);
+        fs::write(dir.path().join(".env"), "A=1\n").unwrap();
+        fs::write(dir.path().join(".env.production"), "B=2\n").unwrap();
+        git(&["add", "-f", ".env", ".env.production"]);
+        git(&["commit", "-q", "-m", "init"]);
+        
+        // Only the staged file is reported; committed and encrypted files are not
+        fs::write(dir.path().join(".env.production"), "B=3\n").unwrap();
+        fs::write(dir.path().join(".env.production.enc"), "B=Zm9v\n").unwrap();
+        git(&["add", "-f", ".env.production"]);
+        
+        let files = changed_env_files(dir.path(), ProcessMode::Encrypt).unwrap();
+        assert_eq!(files, vec![dir.path().join(".env.production")]);
+        assert_eq!(changed_env_files(dir.path(), ProcessMode::Decrypt).unwrap(), vec![dir.path().join(".env.production.enc")]);
+        
+        // A gitignored .env counts until its encrypted output is newer
+        fs::write(dir.path().join(".gitignore"), ".env.local\n").unwrap();
+        fs::write(dir.path().join(".env.local"), "C=4\n").unwrap();
+        let files = changed_env_files(dir.path(), ProcessMode::Encrypt).unwrap();
+        assert_eq!(files, vec![dir.path().join(".env.local"), dir.path().join(".env.production")]);
+        
+        fs::write(dir.path().join(".env.local.enc"), "C=NA==\n").unwrap();
+        let an_hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options().write(true).open(dir.path().join(".env.local")).unwrap().set_modified(an_hour_ago).unwrap();
+        let files = changed_env_files(dir.path(), ProcessMode::Encrypt).unwrap();
+        assert_eq!(files, vec![dir.path().join(".env.production")]);
+    }
+    
+    #[test]
+    fn test_porcelain_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".env"), "DB_HOST=localhost\nDB_PORT=5432\n").unwrap();
+        fs::write(dir.path().join(".env.local"), [0xff, 0xfe, 0x00]).unwrap();
+        fs::write(dir.path().join(".env.enc"), "DB_HOST=bG9jYWxob3N0\n").unwrap();
+        // Status follows the content, not the name
+        fs::write(dir.path().join(".env.staging"), "DB_HOST=bG9jYWxob3N0\n").unwrap();
+        fs::write(dir.path().join(".env.test.enc"), "DB_HOST=localhost\n").unwrap();
+        
+        let lines = |mode| -> Vec<String> {
+            scan_paths(&Scanner::new(dir.path(), mode))
+                .iter()
+                .map(|path| porcelain_line(dir.path(), path, false))
+                .collect()
+        };
+        
+        assert_eq!(lines(ProcessMode::Encrypt), vec!["P\t.env\t2", "!\t.env.local\t-", "E\t.env.staging\t1"]);
+        assert_eq!(lines(ProcessMode::Decrypt), vec!["E\t.env.enc\t1", "P\t.env.test.enc\t1"]);
+        
+        fs::remove_file(dir.path().join(".env.staging")).unwrap();
+        let paths = scan_paths(&Scanner::new(dir.path(), ProcessMode::Encrypt));
+        let listed: serde_json::Value = serde_json::from_str(&files_json(dir.path(), &paths)).unwrap();
+        assert_eq!(listed, json!([
+            { "path": ".env", "status": "plain", "variables": 2 },
+            { "path": ".env.local", "status": "unreadable", "variables": null },
+        ]));
+    }
 }
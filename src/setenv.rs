@@ -3,38 +3,42 @@
 use std::env;
 use std::fs;
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use anyhow::Result;
 use console::style;
-use dialoguer::{Confirm, Select};
+use dialoguer::{Confirm, MultiSelect, Select};
 
+use crate::cancel;
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::format;
+use crate::include;
 use crate::scanner;
 
 /// Parse .env file content and return list of (key, value) pairs
-fn parse_env_file(content: &str) -> Vec<(String, String)> {
+/// Duplicate keys are collapsed (last wins) when `opts.dedupe` is set
+pub fn parse_env_file(content: &str, opts: &ProcessOptions) -> Vec<(String, String)> {
     let mut vars = Vec::new();
+    let deduped;
+    let content = if opts.dedupe {
+        deduped = engine::dedupe_keys(content, opts);
+        deduped.as_str()
+    } else {
+        content
+    };
     
-    for line in content.lines() {
+    for line in engine::lines(content) {
         let trimmed = line.trim();
         
         // Skip empty lines and comments
-        if trimmed.is_empty() || trimmed.starts_with('#') {
+        if opts.is_ignored(trimmed) {
             continue;
         }
         
         // Parse KEY=VALUE
         if let Some(eq_pos) = line.find('=') {
             let key = line[..eq_pos].trim().to_string();
-            let value = line[eq_pos + 1..].trim().to_string();
-            
-            // Remove surrounding quotes if present
-            let value = value
-                .trim_start_matches('"')
-                .trim_end_matches('"')
-                .trim_start_matches('\'')
-                .trim_end_matches('\'')
-                .to_string();
+            let value = strip_quotes(line[eq_pos + 1..].trim());
             
             if !key.is_empty() {
                 vars.push((key, value));
@@ -45,6 +49,65 @@ fn parse_env_file(content: &str) -> Vec<(String, String)> {
     vars
 }
 
+/// Remove surrounding quotes from a value if present
+pub fn strip_quotes(value: &str) -> String {
+    value
+        .trim_start_matches('"')
+        .trim_end_matches('"')
+        .trim_start_matches('\'')
+        .trim_end_matches('\'')
+        .to_string()
+}
+
+/// Quote a value for POSIX shells (single quotes, embedded quotes escaped as '\'')
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Apply --only/--except filters to parsed variables
+/// Returns tuple: (kept variables, skipped keys)
+fn filter_vars(
+    vars: Vec<(String, String)>,
+    only: &[String],
+    except: &[String],
+) -> (Vec<(String, String)>, Vec<String>) {
+    let mut kept = Vec::new();
+    let mut skipped = Vec::new();
+    
+    for (key, value) in vars {
+        let included = only.is_empty() || only.contains(&key);
+        let excluded = except.contains(&key);
+        
+        if included && !excluded {
+            kept.push((key, value));
+        } else {
+            skipped.push(key);
+        }
+    }
+    
+    (kept, skipped)
+}
+
+/// Keep only the variables at the selected indices
+/// Returns tuple: (selected variables, deselected keys)
+fn select_vars(
+    vars: Vec<(String, String)>,
+    selections: &[usize],
+) -> (Vec<(String, String)>, Vec<String>) {
+    let mut selected = Vec::new();
+    let mut deselected = Vec::new();
+    
+    for (i, (key, value)) in vars.into_iter().enumerate() {
+        if selections.contains(&i) {
+            selected.push((key, value));
+        } else {
+            deselected.push(key);
+        }
+    }
+    
+    (selected, deselected)
+}
+
 /// Set environment variable permanently (Windows)
 #[cfg(target_os = "windows")]
 fn set_env_permanent(key: &str, value: &str) -> Result<()> {
@@ -91,33 +154,17 @@ fn set_env_permanent(key: &str, value: &str) -> Result<()> {
 }
 
 /// Select .env file interactively
-fn select_env_file() -> Result<PathBuf> {
-    let current_dir = env::current_dir()?;
-    
-    // Find all .env files (both encrypted and plain)
-    let all_files: Vec<PathBuf> = fs::read_dir(&current_dir)?
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .filter(|p| {
-            if let Some(name) = p.file_name() {
-                let name = name.to_string_lossy();
-                name.starts_with(".env") && !name.ends_with(".enc") && !name.ends_with(".encrypted")
-            } else {
-                false
-            }
-        })
-        .collect();
-    
-    if all_files.is_empty() {
-        anyhow::bail!("No .env files found in current directory");
-    }
+fn select_env_file(opts: &ProcessOptions) -> Result<PathBuf> {
+    // Same plain .env files `encrypt` would pick up, sorted
+    let found = scanner::Scanner::new(env::current_dir()?, ProcessMode::Encrypt).scan();
+    let all_files = scanner::require_files(found, ".env files")?;
     
     // Show files with var count
     println!("{} Found .env file(s) in current directory:", style("📂").cyan());
     for file in &all_files {
         let name = file.file_name().unwrap_or_default().to_string_lossy();
-        let vars = scanner::count_variables(file);
-        println!("  • {} ({} vars)", style(&name).cyan(), vars);
+        let vars = scanner::variables_label(file, opts);
+        println!("  • {} ({})", style(&name).cyan(), vars);
     }
     println!();
     
@@ -126,8 +173,8 @@ fn select_env_file() -> Result<PathBuf> {
         .iter()
         .map(|p| {
             let name = p.file_name().unwrap_or_default().to_string_lossy().to_string();
-            let vars = scanner::count_variables(p);
-            format!("{} ({} vars)", name, vars)
+            let vars = scanner::variables_label(p, opts);
+            format!("{} ({})", name, vars)
         })
         .chain(std::iter::once("❌ Quit".to_string()))
         .collect();
@@ -140,14 +187,21 @@ fn select_env_file() -> Result<PathBuf> {
     
     // Check if Quit selected
     if selection >= all_files.len() {
-        anyhow::bail!("Operation cancelled");
+        return Err(cancel::Cancelled::ByUser.into());
     }
     
     Ok(all_files[selection].clone())
 }
 
 /// Handle setenv command
-pub fn handle_setenv(file: Option<PathBuf>, skip_confirm: bool) -> Result<()> {
+pub fn handle_setenv(
+    file: Option<PathBuf>,
+    skip_confirm: bool,
+    show_values: bool,
+    only: &[String],
+    except: &[String],
+    opts: &ProcessOptions,
+) -> Result<()> {
     println!();
     
     // Step 1: Select or validate file
@@ -158,29 +212,83 @@ pub fn handle_setenv(file: Option<PathBuf>, skip_confirm: bool) -> Result<()> {
             }
             path
         }
-        None => select_env_file()?,
+        None => select_env_file(opts)?,
     };
     
     // Step 2: Read and parse file
-    let content = fs::read_to_string(&file_path)?;
-    let vars = parse_env_file(&content);
+    let content = format::to_dotenv(&fs::read_to_string(&file_path)?, opts.input_format)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file_path.display(), e))?;
+    let content = if opts.follow_includes {
+        include::resolve(&file_path, &content, opts, &mut |path| Ok(fs::read_to_string(path)?))?
+    } else {
+        content
+    };
+    for (line, key) in engine::validate_key_names(&content, opts)? {
+        println!("{} line {}: '{}' is not a valid environment variable name", 
+            style("⚠️").yellow(),
+            line,
+            key
+        );
+    }
+    for (key, lines) in engine::find_duplicate_keys(&content, opts) {
+        let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        println!("{} '{}' is defined {} times (lines {}){}", 
+            style("⚠️").yellow(),
+            key,
+            lines.len(),
+            lines.join(", "),
+            if opts.dedupe { ", keeping the last" } else { "" }
+        );
+    }
+    let vars = parse_env_file(&content, opts);
     
     if vars.is_empty() {
         anyhow::bail!("No environment variables found in file");
     }
     
-    // Step 3: Show variables to be set
-    println!("{} Will set {} environment variable(s):", style("📝").cyan(), vars.len());
-    for (key, value) in &vars {
-        // Mask sensitive values
-        let display_value = if value.len() > 20 {
-            format!("{}...", &value[..20])
-        } else {
-            value.clone()
-        };
-        println!("  • {} = {}", style(key).yellow(), style(&display_value).dim());
+    let (vars, skipped) = filter_vars(vars, only, except);
+    
+    if vars.is_empty() {
+        anyhow::bail!("No environment variables left after applying filters");
     }
-    println!();
+    
+    // Step 3: Show variables to be set (mask sensitive values unless explicitly requested)
+    let display_items: Vec<String> = vars
+        .iter()
+        .map(|(key, value)| {
+            let display_value = if show_values {
+                value.clone()
+            } else {
+                engine::mask_value(value)
+            };
+            format!("{} = {}", key, display_value)
+        })
+        .collect();
+    
+    let (vars, skipped) = if skip_confirm {
+        println!("{} Will set {} environment variable(s):", style("📝").cyan(), vars.len());
+        for item in &display_items {
+            println!("  • {}", style(item).yellow());
+        }
+        println!();
+        (vars, skipped)
+    } else {
+        let defaults = vec![true; vars.len()];
+        let selections = MultiSelect::new()
+            .with_prompt("Select variables to set (Space to toggle, Enter to confirm)")
+            .items(&display_items)
+            .defaults(&defaults)
+            .interact()?;
+        
+        if selections.is_empty() {
+            anyhow::bail!("No variables selected");
+        }
+        
+        let (selected, mut deselected) = select_vars(vars, &selections);
+        deselected.extend(skipped);
+        println!();
+        (selected, deselected)
+    };
     
     // Step 4: Confirm
     if !skip_confirm {
@@ -198,7 +306,7 @@ pub fn handle_setenv(file: Option<PathBuf>, skip_confirm: bool) -> Result<()> {
             .interact()?;
         
         if !confirmed {
-            anyhow::bail!("Operation cancelled");
+            return Err(cancel::Cancelled::ByUser.into());
         }
     }
     
@@ -245,5 +353,68 @@ pub fn handle_setenv(file: Option<PathBuf>, skip_confirm: bool) -> Result<()> {
         );
     }
     
+    if !skipped.is_empty() {
+        println!();
+        println!("{} Skipped {} variable(s): {}", 
+            style("⏭️").dim(),
+            skipped.len(),
+            skipped.join(", ")
+        );
+    }
+    
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn sample_vars() -> Vec<(String, String)> {
+        parse_env_file(
+            "DB_HOST=localhost\nDB_PASS=secret\nAPI_KEY=abc123\n",
+            &ProcessOptions::default(),
+        )
+    }
+    
+    #[test]
+    fn test_filter_vars_only() {
+        let only = vec!["DB_HOST".to_string(), "API_KEY".to_string()];
+        let (kept, skipped) = filter_vars(sample_vars(), &only, &[]);
+        
+        let kept_keys: Vec<&str> = kept.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(kept_keys, vec!["DB_HOST", "API_KEY"]);
+        assert_eq!(skipped, vec!["DB_PASS"]);
+    }
+    
+    #[test]
+    fn test_filter_vars_except() {
+        let except = vec!["DB_PASS".to_string()];
+        let (kept, skipped) = filter_vars(sample_vars(), &[], &except);
+        
+        let kept_keys: Vec<&str> = kept.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(kept_keys, vec!["DB_HOST", "API_KEY"]);
+        assert_eq!(skipped, vec!["DB_PASS"]);
+    }
+    
+    #[test]
+    fn test_parse_env_file_dedupe() {
+        let content = "DB_HOST=a\nDB_PASS=x\nDB_HOST=b\n";
+        assert_eq!(parse_env_file(content, &ProcessOptions::default()).len(), 3);
+        
+        let opts = ProcessOptions { dedupe: true, ..Default::default() };
+        let vars = parse_env_file(content, &opts);
+        assert_eq!(vars, vec![
+            ("DB_PASS".to_string(), "x".to_string()),
+            ("DB_HOST".to_string(), "b".to_string()),
+        ]);
+    }
+    
+    #[test]
+    fn test_select_vars() {
+        let (selected, deselected) = select_vars(sample_vars(), &[0, 2]);
+        
+        let selected_keys: Vec<&str> = selected.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(selected_keys, vec!["DB_HOST", "API_KEY"]);
+        assert_eq!(deselected, vec!["DB_PASS"]);
+    }
+}
@@ -0,0 +1,152 @@
+//! Key splitting with Shamir's secret sharing: encrypt under a random password and hand out
+//! N shares of it, any K of which rebuild the password (`shamir` feature)
+
+use std::path::Path;
+
+use anyhow::Result;
+#[cfg(feature = "shamir")]
+use base64::Engine;
+use console::style;
+use dialoguer::Password;
+#[cfg(feature = "shamir")]
+use secrecy::ExposeSecret;
+use secrecy::SecretString;
+
+use crate::{engine, scanner};
+
+/// Random bytes in a generated password (encoded as Base64)
+#[cfg(feature = "shamir")]
+const SECRET_LEN: usize = 32;
+
+/// Random password to encrypt under when splitting
+#[cfg(feature = "shamir")]
+pub fn generate_secret() -> SecretString {
+    let bytes: [u8; SECRET_LEN] = rand::random();
+    SecretString::new(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Split `secret` into `count` Base64 shares, any `threshold` of which recover it
+#[cfg(feature = "shamir")]
+pub fn split(secret: &SecretString, threshold: u8, count: u8) -> Result<Vec<String>> {
+    if threshold < 2 || threshold > count {
+        anyhow::bail!("--threshold must be at least 2 and at most --split ({})", count);
+    }
+    
+    let shares = sharks::Sharks(threshold)
+        .dealer(secret.expose_secret().as_bytes())
+        .take(count as usize)
+        .map(|share| base64::engine::general_purpose::STANDARD.encode(Vec::from(&share)))
+        .collect();
+    Ok(shares)
+}
+
+/// Rebuild the password from at least `threshold` shares
+#[cfg(feature = "shamir")]
+pub fn combine(shares: &[String], threshold: u8) -> Result<SecretString> {
+    let shares = shares
+        .iter()
+        .map(|share| {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(share.trim())
+                .map_err(|_| anyhow::anyhow!("Share is not valid Base64"))?;
+            sharks::Share::try_from(bytes.as_slice()).map_err(|e| anyhow::anyhow!("Invalid share: {}", e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    
+    let secret = sharks::Sharks(threshold)
+        .recover(&shares)
+        .map_err(|e| anyhow::anyhow!("{} (need {} distinct shares)", e, threshold))?;
+    let secret = String::from_utf8(secret).map_err(|_| anyhow::anyhow!("Shares do not belong together"))?;
+    Ok(SecretString::new(secret))
+}
+
+/// Key splitting needs the optional `shamir` feature
+#[cfg(not(feature = "shamir"))]
+pub fn generate_secret() -> SecretString {
+    SecretString::new(String::new())
+}
+
+/// Key splitting needs the optional `shamir` feature
+#[cfg(not(feature = "shamir"))]
+pub fn split(_secret: &SecretString, _threshold: u8, _count: u8) -> Result<Vec<String>> {
+    anyhow::bail!("--split requires building with `--features shamir`")
+}
+
+/// Key splitting needs the optional `shamir` feature
+#[cfg(not(feature = "shamir"))]
+pub fn combine(_shares: &[String], _threshold: u8) -> Result<SecretString> {
+    anyhow::bail!("This file was encrypted with --split; rebuild with `--features shamir` to decrypt it")
+}
+
+/// (threshold, count) of a local `--split` file; None for other files, stdin and URLs
+/// Only the header is read, after the `max_size` (`--max-file-size`) check
+pub fn split_scheme(path: &Path, max_size: u64) -> Result<Option<(u8, u8)>> {
+    if scanner::is_stdio(path) || scanner::is_url(path) {
+        return Ok(None);
+    }
+    Ok(engine::shares_scheme(&scanner::read_header(path, max_size)?))
+}
+
+/// Rebuild the password of a `--split` file from `given` shares plus prompts for the rest
+/// None when the file has no share header (or is stdin or a URL)
+pub fn password_from_shares(path: &Path, given: Vec<String>, max_size: u64) -> Result<Option<SecretString>> {
+    let Some((threshold, _)) = split_scheme(path, max_size)? else {
+        if !given.is_empty() {
+            anyhow::bail!("{} was not encrypted with --split; --share does not apply", path.display());
+        }
+        return Ok(None);
+    };
+    
+    let shares = prompt_shares(given, threshold)?;
+    combine(&shares, threshold).map(Some)
+}
+
+/// Ask for the shares still missing after `given`, one hidden prompt each
+pub fn prompt_shares(mut given: Vec<String>, threshold: u8) -> Result<Vec<String>> {
+    while given.len() < threshold as usize {
+        let share = Password::new()
+            .with_prompt(format!("{} Share {} of {}", style("🧩").cyan(), given.len() + 1, threshold))
+            .interact()?;
+        given.push(share);
+    }
+    Ok(given)
+}
+
+/// Print the shares after encrypting; each one goes to a different holder
+/// `to_stderr` keeps them out of the ciphertext when the encrypted output goes to stdout
+pub fn print_shares(shares: &[String], threshold: u8, to_stderr: bool) {
+    let print = |line: String| if to_stderr { eprintln!("{}", line) } else { println!("{}", line) };
+    let styled = |text: String| if to_stderr { style(text).for_stderr() } else { style(text) };
+    
+    print(String::new());
+    print(format!(
+        "{} Password split into {} shares; any {} of them decrypt:",
+        styled("🧩".to_string()).cyan(),
+        shares.len(),
+        threshold
+    ));
+    for (index, share) in shares.iter().enumerate() {
+        print(format!("  {} {}", styled(format!("#{}", index + 1)).dim(), share));
+    }
+    print("   Give each share to a different person; they are not stored anywhere else".to_string());
+}
+
+#[cfg(all(test, feature = "shamir"))]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_k_of_n_roundtrip() {
+        let secret = generate_secret();
+        let shares = split(&secret, 2, 3).unwrap();
+        assert_eq!(shares.len(), 3);
+        
+        for pair in [[0, 1], [0, 2], [1, 2]] {
+            let chosen: Vec<String> = pair.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine(&chosen, 2).unwrap().expose_secret(), secret.expose_secret());
+        }
+        
+        assert!(combine(&shares[..1], 2).is_err());
+        assert!(split(&secret, 4, 3).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Opt-in upgrade notice and self-update from GitHub releases
+
+use std::env;
+use std::sync::mpsc::Receiver;
+
+#[cfg(feature = "self-update")]
+use anyhow::Result;
+use console::style;
+#[cfg(feature = "self-update")]
+use dialoguer::Confirm;
+
+/// Environment variable that enables the upgrade check (off by default)
+const UPDATE_CHECK_ENV_VAR: &str = "WC_ENVC_UPDATE_CHECK";
+
+/// GitHub repository owner for release lookups
+#[cfg(feature = "self-update")]
+const REPO_OWNER: &str = "GinCanhViet";
+
+/// GitHub repository name for release lookups
+#[cfg(feature = "self-update")]
+const REPO_NAME: &str = "wc-envc";
+
+/// Release asset listing SHA-256 digests of all binaries
+#[cfg(feature = "self-update")]
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// Check whether an env var value turns the upgrade check on
+fn is_enabled(value: Option<&str>) -> bool {
+    matches!(
+        value.map(|v| v.trim().to_ascii_lowercase()).as_deref(),
+        Some("1" | "true" | "yes" | "on")
+    )
+}
+
+/// Start a background upgrade check if enabled via `WC_ENVC_UPDATE_CHECK`
+/// Returns a receiver for the latest version, or None when skipped
+pub fn spawn_check() -> Option<Receiver<String>> {
+    spawn_check_with(env::var(UPDATE_CHECK_ENV_VAR).ok().as_deref())
+}
+
+/// Start a background upgrade check for the given env var value
+fn spawn_check_with(value: Option<&str>) -> Option<Receiver<String>> {
+    if !is_enabled(value) {
+        return None;
+    }
+    
+    start_check()
+}
+
+/// Fetch the latest release on a background thread so startup never blocks
+#[cfg(feature = "self-update")]
+fn start_check() -> Option<Receiver<String>> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        if let Ok(latest) = fetch_latest_version() {
+            let _ = tx.send(latest);
+        }
+    });
+    Some(rx)
+}
+
+/// Without the `self-update` feature there is no network access: skip the check
+#[cfg(not(feature = "self-update"))]
+fn start_check() -> Option<Receiver<String>> {
+    None
+}
+
+/// Release asset name fragment for the current platform (see release.yml)
+#[cfg(feature = "self-update")]
+fn asset_target() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "windows-x64"
+    } else if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        "macos-arm64"
+    } else if cfg!(target_os = "macos") {
+        "macos-x64"
+    } else {
+        "linux-x64"
+    }
+}
+
+/// Configure the GitHub release updater for this binary
+#[cfg(feature = "self-update")]
+fn configure_updater() -> Result<self_update::backends::github::Update> {
+    let updater = self_update::backends::github::Update::configure()
+        .repo_owner(REPO_OWNER)
+        .repo_name(REPO_NAME)
+        .bin_name("wc-envc")
+        .target(asset_target())
+        .current_version(env!("CARGO_PKG_VERSION"))
+        .checksum_from_asset(CHECKSUMS_ASSET)
+        .show_output(false)
+        .no_confirm(true)
+        .build()?;
+    
+    Ok(updater)
+}
+
+/// Query GitHub for the latest release version
+#[cfg(feature = "self-update")]
+fn fetch_latest_version() -> Result<String> {
+    let releases = configure_updater()?.get_latest_release()?;
+    let latest = releases
+        .into_vec()
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No releases found on GitHub"))?;
+    Ok(latest.version().to_string())
+}
+
+/// Handle self-update command
+/// Downloads the latest release binary, verifies it against SHA256SUMS and replaces the running binary
+#[cfg(feature = "self-update")]
+pub fn handle_self_update(skip_confirm: bool, dry_run: bool) -> Result<()> {
+    println!();
+    println!("{} Checking for updates...", style("⏳").cyan());
+    
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = fetch_latest_version()?;
+    
+    if !is_newer(&latest, current) {
+        println!("{} Already up to date ({})", style("✅").green(), current);
+        return Ok(());
+    }
+    
+    println!("{} New version available: {} (you have {})", 
+        style("⬆️").cyan(),
+        style(&latest).green(),
+        current
+    );
+    
+    if dry_run {
+        return Ok(());
+    }
+    
+    if !skip_confirm {
+        let confirmed = Confirm::new()
+            .with_prompt("Replace the current binary?")
+            .default(true)
+            .interact()?;
+        
+        if !confirmed {
+            return Err(crate::cancel::Cancelled::ByUser.into());
+        }
+    }
+    
+    println!("{} Downloading and verifying {}...", style("⏳").cyan(), asset_target());
+    let status = configure_updater()?.update()?;
+    
+    println!("{} Updated to {}", style("✅").green(), status.version());
+    
+    Ok(())
+}
+
+/// Compare dotted numeric versions (e.g. `0.2.0` > `0.1.9`)
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(latest) > parse(current)
+}
+
+/// Print a one-line upgrade notice if the background check already finished
+pub fn print_notice(check: Option<Receiver<String>>) {
+    let Some(rx) = check else {
+        return;
+    };
+    
+    if let Ok(latest) = rx.try_recv() {
+        let current = env!("CARGO_PKG_VERSION");
+        if is_newer(&latest, current) {
+            eprintln!("{} wc-envc {} is available (you have {})",
+                style("⬆️").cyan().for_stderr(),
+                style(&latest).green().for_stderr(),
+                current
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_check_skipped_when_disabled() {
+        assert!(spawn_check_with(None).is_none());
+        assert!(spawn_check_with(Some("0")).is_none());
+        assert!(spawn_check_with(Some("")).is_none());
+        assert!(is_enabled(Some("1")));
+    }
+    
+    #[test]
+    fn test_version_comparison() {
+        assert!(is_newer("0.2.0", "0.1.9"));
+        assert!(is_newer("1.0.0", "0.10.0"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+    }
+}
@@ -0,0 +1,203 @@
+//! Bundle several environment files into one encrypted `.env.vault` artifact
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use console::style;
+use secrecy::SecretString;
+
+use crate::engine::{self, ProcessMode, ProcessOptions};
+use crate::interactive;
+use crate::scanner;
+
+/// Default vault file name
+pub const VAULT_FILE: &str = ".env.vault";
+
+/// Manifest line listing the environments in a vault
+const MANIFEST_PREFIX: &str = "# wc-envc vault:";
+
+/// Environment name for an env file (.env.production -> production, .env -> default)
+fn env_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    match name.strip_prefix(".env.") {
+        Some(env) if !env.is_empty() => env.to_string(),
+        _ => "default".to_string(),
+    }
+}
+
+/// Encrypt each (environment, content) pair into a section of a vault
+fn build(
+    envs: &[(String, String)],
+    password: &SecretString,
+    opts: &ProcessOptions,
+) -> Result<String> {
+    let names: Vec<&str> = envs.iter().map(|(name, _)| name.as_str()).collect();
+    let mut vault = format!("{} {}\n", MANIFEST_PREFIX, names.join(", "));
+    
+    for (name, content) in envs {
+        let (encrypted, _) = engine::process_file(content, password, ProcessMode::Encrypt, opts)
+            .map_err(|e| anyhow::anyhow!("{}: {}", name, e))?;
+        vault.push_str(&format!("\n[{}]\n{}\n", name, encrypted));
+    }
+    
+    Ok(vault)
+}
+
+/// Split a vault into (environment, encrypted section body) pairs
+fn sections(vault: &str) -> Vec<(String, String)> {
+    let mut sections: Vec<(String, String)> = Vec::new();
+    
+    for line in vault.lines() {
+        let header = line
+            .trim()
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'));
+        
+        match (header, sections.last_mut()) {
+            (Some(name), _) => sections.push((name.to_string(), String::new())),
+            (None, Some((_, body))) if !line.trim().is_empty() => {
+                body.push_str(line);
+                body.push('\n');
+            }
+            _ => {}
+        }
+    }
+    
+    sections
+}
+
+/// Decrypt a single environment from a vault
+fn open(vault: &str, env: &str, password: &SecretString, opts: &ProcessOptions) -> Result<String> {
+    let all = sections(vault);
+    let (_, body) = all.iter().find(|(name, _)| name == env).ok_or_else(|| {
+        let names: Vec<&str> = all.iter().map(|(name, _)| name.as_str()).collect();
+        anyhow::anyhow!("Environment '{}' not found in vault (available: {})", env, names.join(", "))
+    })?;
+    
+    let (decrypted, _) = engine::process_file(body, password, ProcessMode::Decrypt, opts)
+        .map_err(|e| anyhow::anyhow!("{}: {}", env, e))?;
+    
+    Ok(decrypted)
+}
+
+/// Get password from: arg > env > prompt; `with_confirm` is for building, which encrypts
+fn resolve_password(password: Option<String>, with_confirm: bool) -> Result<SecretString> {
+    match password {
+        Some(p) if with_confirm => interactive::password_from_arg(p, ProcessMode::Encrypt),
+        Some(p) => interactive::password_from_arg(p, ProcessMode::Decrypt),
+        None => interactive::get_password_from_env_or_prompt(with_confirm),
+    }
+}
+
+/// Handle `vault build`
+/// Encrypts the given files (or all plain .env files in the current directory) into one vault
+pub fn handle_vault_build(
+    files: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    password: Option<String>,
+    skip_confirm: bool,
+    opts: &ProcessOptions,
+) -> Result<()> {
+    let output = output.unwrap_or_else(|| PathBuf::from(VAULT_FILE));
+    let files = if files.is_empty() {
+        let found = scanner::Scanner::new(env::current_dir()?, ProcessMode::Encrypt)
+            .scan()
+            .into_iter()
+            .filter(|p| p.file_name().is_some_and(|n| n != VAULT_FILE))
+            .collect();
+        scanner::require_files(found, ".env files")?
+    } else {
+        files
+    };
+    
+    let mut envs = Vec::new();
+    for file in &files {
+        let content = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        envs.push((env_name(file), content));
+    }
+    
+    if scanner::output_exists(&output) && !skip_confirm {
+        interactive::confirm_overwrite(&output)?;
+    }
+    
+    let password = resolve_password(password, true)?;
+    std::fs::write(&output, build(&envs, &password, opts)?)?;
+    
+    println!();
+    for (file, (name, _)) in files.iter().zip(&envs) {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        println!("  {} {} → [{}]", style("✓").green(), style(file_name).cyan(), name);
+    }
+    println!("{} Done! Saved: {}", style("✅").green(), style(output.display()).cyan());
+    
+    Ok(())
+}
+
+/// Handle `vault open`
+/// Decrypts one environment from the vault to a file or stdout (`-`)
+pub fn handle_vault_open(
+    vault: Option<PathBuf>,
+    env: &str,
+    output: Option<PathBuf>,
+    password: Option<String>,
+    skip_confirm: bool,
+    opts: &ProcessOptions,
+) -> Result<()> {
+    let vault = vault.unwrap_or_else(|| PathBuf::from(VAULT_FILE));
+    if !vault.exists() {
+        anyhow::bail!("File not found: {}", vault.display());
+    }
+    
+    let output = output.unwrap_or_else(|| PathBuf::from(format!(".env.{}", env)));
+    if scanner::output_exists(&output) && !skip_confirm {
+        interactive::confirm_overwrite(&output)?;
+    }
+    
+    let content = std::fs::read_to_string(&vault)?;
+    let password = resolve_password(password, false)?;
+    let decrypted = open(&content, env, &password, opts)?;
+    
+    if scanner::is_stdio(&output) {
+        print!("{}", decrypted);
+        return Ok(());
+    }
+    
+    std::fs::write(&output, decrypted)?;
+    println!("{} Done! Saved [{}] to {}", style("✅").green(), env, style(output.display()).cyan());
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_vault_roundtrip() {
+        let password = SecretString::new("secret".to_string());
+        let opts = ProcessOptions::default();
+        let envs = vec![
+            ("development".to_string(), "DB_HOST=localhost\nDEBUG=1".to_string()),
+            ("production".to_string(), "DB_HOST=db.internal".to_string()),
+        ];
+        
+        let vault = build(&envs, &password, &opts).unwrap();
+        assert!(vault.starts_with("# wc-envc vault: development, production\n"));
+        assert!(!vault.contains("db.internal"));
+        
+        assert_eq!(open(&vault, "production", &password, &opts).unwrap(), "DB_HOST=db.internal\n");
+        assert_eq!(
+            open(&vault, "development", &password, &opts).unwrap(),
+            "DB_HOST=localhost\nDEBUG=1\n"
+        );
+        assert!(open(&vault, "staging", &password, &opts).is_err());
+    }
+    
+    #[test]
+    fn test_env_name() {
+        assert_eq!(env_name(Path::new(".env.production")), "production");
+        assert_eq!(env_name(Path::new("config/.env")), "default");
+    }
+}